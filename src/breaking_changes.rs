@@ -92,6 +92,11 @@ fn data_dir() -> PathBuf {
     return WINDOWS_DIRS.data_dir();
 }
 
+/// Return the directory Topgrade keeps its own data in, such as the run history.
+pub(crate) fn topgrade_data_dir() -> PathBuf {
+    data_dir().join("topgrade")
+}
+
 /// Return Topgrade's keep file path.
 ///
 /// keep file is a file under the data directory containing a major version
@@ -1,14 +1,16 @@
 //! Utilities for running commands and providing user-friendly error messages.
 
 use std::fmt::Display;
-use std::process::Child;
-use std::process::{Command, ExitStatus, Output};
+use std::io;
+use std::process::{Command, ExitStatus, Output, Stdio};
 
 use color_eyre::eyre;
 use color_eyre::eyre::Context;
 use color_eyre::eyre::eyre;
 
 use crate::error::TopgradeError;
+use crate::step_log::{Streams, Tee};
+use crate::watchdog::{self, WatchedChild};
 
 use tracing::debug;
 
@@ -157,73 +159,126 @@ pub trait CommandExt {
 }
 
 impl CommandExt for Command {
-    type Child = Child;
+    type Child = WatchedChild;
 
     fn output_checked_with(&mut self, succeeded: impl Fn(&Output) -> Result<(), ()>) -> eyre::Result<Output> {
-        let command = log(self);
-
-        // This is where we implement `output_checked`, which is what we prefer to use instead of
-        // `output`, so we allow `Command::output` here.
-        #[expect(clippy::disallowed_methods)]
-        let output = self
-            .output()
-            .with_context(|| format!("Failed to execute `{command}`"))?;
-
-        if succeeded(&output).is_ok() {
-            Ok(output)
-        } else {
-            let mut message = format!("Command failed: `{command}`");
-            let stderr = String::from_utf8_lossy(&output.stderr);
-            let stdout = String::from_utf8_lossy(&output.stdout);
-
-            let stdout_trimmed = stdout.trim();
-            if !stdout_trimmed.is_empty() {
-                message.push_str(&format!("\n\nStdout:\n{stdout_trimmed}"));
-            }
-            let stderr_trimmed = stderr.trim();
-            if !stderr_trimmed.is_empty() {
-                message.push_str(&format!("\n\nStderr:\n{stderr_trimmed}"));
-            }
-
-            let (program, _) = get_program_and_args(self);
-            let err = TopgradeError::ProcessFailedWithOutput(program, output.status, stderr.into_owned());
-
-            let ret = Err(err).with_context(|| message);
-            debug!("Command failed: {ret:?}");
-            ret
-        }
+        output_checked_captured(
+            self,
+            Streams {
+                stdout: true,
+                stderr: true,
+            },
+            false,
+            succeeded,
+        )
     }
 
     fn status_checked_with(&mut self, succeeded: impl Fn(ExitStatus) -> Result<(), ()>) -> eyre::Result<()> {
-        let command = log(self);
-        let message = format!("Failed to execute `{command}`");
-
-        // This is where we implement `status_checked`, which is what we prefer to use instead of
-        // `status`, so we allow `Command::status` here.
-        #[expect(clippy::disallowed_methods)]
-        let status = self.status().with_context(|| message.clone())?;
-
-        if succeeded(status).is_ok() {
-            Ok(())
-        } else {
-            let (program, _) = get_program_and_args(self);
-            let err = TopgradeError::ProcessFailed(program, status);
-            let ret = Err(err).with_context(|| format!("Command failed: `{command}`"));
-            debug!("Command failed: {ret:?}");
-            ret
-        }
+        status_checked_teed(self, Streams::default(), succeeded)
     }
 
     fn spawn_checked(&mut self) -> eyre::Result<Self::Child> {
         let command = log(self);
-        let message = format!("Failed to execute `{command}`");
 
         // This is where we implement `spawn_checked`, which is what we prefer to use instead of
         // `spawn`, so we allow `Command::spawn` here.
         #[expect(clippy::disallowed_methods)]
-        {
-            self.spawn().with_context(|| message.clone())
+        let child = self.spawn().with_context(|| format!("Failed to execute `{command}`"))?;
+        Ok(watchdog::watch(child))
+    }
+}
+
+/// `output_checked_with`, with the streams set up by `output_watched`.
+pub fn output_checked_captured(
+    command: &mut Command,
+    inherited: Streams,
+    stdin_set: bool,
+    succeeded: impl Fn(&Output) -> Result<(), ()>,
+) -> eyre::Result<Output> {
+    let logged = log(command);
+
+    let output =
+        output_watched(command, inherited, stdin_set).with_context(|| format!("Failed to execute `{logged}`"))?;
+
+    if succeeded(&output).is_ok() {
+        Ok(output)
+    } else {
+        let mut message = format!("Command failed: `{logged}`");
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        let stdout = String::from_utf8_lossy(&output.stdout);
+
+        let stdout_trimmed = stdout.trim();
+        if !stdout_trimmed.is_empty() {
+            message.push_str(&format!("\n\nStdout:\n{stdout_trimmed}"));
         }
+        let stderr_trimmed = stderr.trim();
+        if !stderr_trimmed.is_empty() {
+            message.push_str(&format!("\n\nStderr:\n{stderr_trimmed}"));
+        }
+
+        let (program, _) = get_program_and_args(command);
+        let err = TopgradeError::ProcessFailedWithOutput(program, output.status, stderr.into_owned());
+
+        let ret = Err(err).with_context(|| message);
+        debug!("Command failed: {ret:?}");
+        ret
+    }
+}
+
+/// Like [`Command::output`], but the process is registered with the watchdog, so that it is
+/// killed when the step times out.
+///
+/// A `Command` doesn't tell which of its streams were set, so the caller says which ones to set
+/// up the way `Command::output` does: the `inherited` output streams are captured and, unless
+/// `stdin_set`, stdin is null.
+pub fn output_watched(command: &mut Command, inherited: Streams, stdin_set: bool) -> io::Result<Output> {
+    if !stdin_set {
+        command.stdin(Stdio::null());
+    }
+    if inherited.stdout {
+        command.stdout(Stdio::piped());
+    }
+    if inherited.stderr {
+        command.stderr(Stdio::piped());
+    }
+
+    // This is where we implement `output_checked`, which is what we prefer to use instead of
+    // `output`. We spawn and wait rather than using `output` so that the watchdog can kill
+    // the process when the step times out.
+    #[expect(clippy::disallowed_methods)]
+    let child = command.spawn()?;
+    watchdog::watch(child).wait_with_output()
+}
+
+/// `status_checked_with`, copying the `teed` streams of the command into the step log.
+///
+/// The streams have to be prepared with `step_log::prepare`.
+pub fn status_checked_teed(
+    command: &mut Command,
+    teed: Streams,
+    succeeded: impl Fn(ExitStatus) -> Result<(), ()>,
+) -> eyre::Result<()> {
+    let logged = log(command);
+    let message = format!("Failed to execute `{logged}`");
+
+    // This is where we implement `status_checked`, which is what we prefer to use instead of
+    // `status`. We spawn and wait rather than using `status` so that the watchdog can kill
+    // the process when the step times out.
+    #[expect(clippy::disallowed_methods)]
+    let mut child = command.spawn().with_context(|| message.clone())?;
+    let tee = Tee::start(&mut child, teed);
+    let status = watchdog::watch(child).wait().with_context(|| message.clone());
+    tee.finish();
+    let status = status?;
+
+    if succeeded(status).is_ok() {
+        Ok(())
+    } else {
+        let (program, _) = get_program_and_args(command);
+        let err = TopgradeError::ProcessFailed(program, status);
+        let ret = Err(err).with_context(|| format!("Command failed: `{logged}`"));
+        debug!("Command failed: {ret:?}");
+        ret
     }
 }
 
@@ -1,12 +1,13 @@
 #![allow(dead_code)]
 
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 use std::fs::{File, write};
 use std::io::Write;
 use std::path::{Path, PathBuf};
-use std::{env, fmt, fs};
+use std::time::Duration;
+use std::{env, fmt, fs, iter};
 
-use clap::{Parser, ValueEnum};
+use clap::{Parser, Subcommand, ValueEnum};
 use clap_complete::Shell;
 use color_eyre::eyre::Result;
 use color_eyre::eyre::{Context, OptionExt};
@@ -16,12 +17,17 @@ use merge2::Merge;
 use regex::Regex;
 use regex_split::RegexSplit;
 use rust_i18n::t;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use strum::IntoEnumIterator;
 use tracing::{debug, error};
 
 use crate::execution_context::RunType;
+use crate::notify::Notifier;
+use crate::plan::PlanFormat;
+use crate::report::ReportFormat;
+use crate::schedule::Interval;
 use crate::step::{DEPRECATED_STEPS, Step};
+use crate::step_config::{self, OptionDoc, StepOptions};
 use crate::sudo::SudoKind;
 use crate::terminal::print_warning;
 use crate::utils::string_prepend_str;
@@ -46,14 +52,132 @@ macro_rules! str_value {
 
 pub type Commands = IndexMap<String, String>;
 
-#[derive(Deserialize, Default, Debug, Merge)]
+pub type CustomCommands = IndexMap<String, CustomCommand>;
+
+/// An entry of `[commands]`: either just the command, or a table describing a custom step.
+#[derive(Default, Debug, Clone, PartialEq)]
+pub struct CustomCommand {
+    /// The shell command to run.
+    pub command: String,
+
+    /// Skip the step if this binary can't be found.
+    pub require: Option<String>,
+
+    /// Skip the step unless this shell command succeeds.
+    pub only_if: Option<String>,
+
+    /// Run the command with sudo.
+    pub sudo: bool,
+
+    /// A shell command to run after the command with `--cleanup`.
+    pub cleanup: Option<String>,
+
+    /// Appended to the command when answering yes to prompts, e.g. with `--yes`.
+    pub yes_args: Option<String>,
+
+    /// Run the step right before this step rather than in the place of `custom_commands`.
+    pub before: Option<Step>,
+
+    /// Run the step right after this step rather than in the place of `custom_commands`.
+    pub after: Option<Step>,
+
+    /// Don't count a failure of the step as a failure of the run.
+    pub ignore_failure: bool,
+}
+
+#[derive(Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+struct CustomCommandTable {
+    command: String,
+    require: Option<String>,
+    only_if: Option<String>,
+    #[serde(default, skip_serializing_if = "is_false")]
+    sudo: bool,
+    cleanup: Option<String>,
+    yes_args: Option<String>,
+    before: Option<Step>,
+    after: Option<Step>,
+    #[serde(default, skip_serializing_if = "is_false")]
+    ignore_failure: bool,
+}
+
+fn is_false(value: &bool) -> bool {
+    !value
+}
+
+impl Serialize for CustomCommand {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let plain = CustomCommand {
+            command: self.command.clone(),
+            ..CustomCommand::default()
+        };
+        if *self == plain {
+            return serializer.serialize_str(&self.command);
+        }
+        CustomCommandTable {
+            command: self.command.clone(),
+            require: self.require.clone(),
+            only_if: self.only_if.clone(),
+            sudo: self.sudo,
+            cleanup: self.cleanup.clone(),
+            yes_args: self.yes_args.clone(),
+            before: self.before,
+            after: self.after,
+            ignore_failure: self.ignore_failure,
+        }
+        .serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for CustomCommand {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct Visitor;
+
+        impl<'de> serde::de::Visitor<'de> for Visitor {
+            type Value = CustomCommand;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                formatter.write_str("a command or a table with a `command`")
+            }
+
+            fn visit_str<E: serde::de::Error>(self, command: &str) -> Result<CustomCommand, E> {
+                Ok(CustomCommand {
+                    command: command.to_string(),
+                    ..CustomCommand::default()
+                })
+            }
+
+            fn visit_map<A: serde::de::MapAccess<'de>>(self, map: A) -> Result<CustomCommand, A::Error> {
+                let table = CustomCommandTable::deserialize(serde::de::value::MapAccessDeserializer::new(map))?;
+                if table.before.is_some() && table.after.is_some() {
+                    return Err(serde::de::Error::custom("`before` and `after` can't both be set"));
+                }
+                Ok(CustomCommand {
+                    command: table.command,
+                    require: table.require,
+                    only_if: table.only_if,
+                    sudo: table.sudo,
+                    cleanup: table.cleanup,
+                    yes_args: table.yes_args,
+                    before: table.before,
+                    after: table.after,
+                    ignore_failure: table.ignore_failure,
+                })
+            }
+        }
+
+        deserializer.deserialize_any(Visitor)
+    }
+}
+
+#[derive(Deserialize, Serialize, Default, Debug, Merge)]
 #[serde(deny_unknown_fields)]
 pub struct Include {
     #[merge(strategy = crate::utils::merge_strategies::vec_prepend_opt)]
     paths: Option<Vec<String>>,
 }
 
-#[derive(Deserialize, Default, Debug, Merge)]
+#[derive(Deserialize, Serialize, Default, Debug, Merge)]
 #[serde(deny_unknown_fields)]
 pub struct Containers {
     #[merge(strategy = crate::utils::merge_strategies::vec_prepend_opt)]
@@ -61,15 +185,17 @@ pub struct Containers {
     runtime: Option<ContainerRuntime>,
     system_prune: Option<bool>,
     use_sudo: Option<bool>,
+    #[merge(strategy = crate::utils::merge_strategies::vec_prepend_opt)]
+    exec_in: Option<Vec<String>>,
 }
 
-#[derive(Deserialize, Default, Debug, Merge)]
+#[derive(Deserialize, Serialize, Default, Debug, Merge)]
 #[serde(deny_unknown_fields)]
 pub struct Mandb {
     enable: Option<bool>,
 }
 
-#[derive(Deserialize, Default, Debug, Merge)]
+#[derive(Deserialize, Serialize, Default, Debug, Merge)]
 #[serde(deny_unknown_fields)]
 pub struct Git {
     max_concurrency: Option<usize>,
@@ -87,7 +213,7 @@ pub struct Git {
     fallback_to_fetch_default: Option<bool>,
 }
 
-#[derive(Deserialize, Default, Debug, Merge)]
+#[derive(Deserialize, Serialize, Default, Debug, Merge)]
 #[serde(deny_unknown_fields)]
 pub struct Vagrant {
     #[merge(strategy = crate::utils::merge_strategies::vec_prepend_opt)]
@@ -97,7 +223,7 @@ pub struct Vagrant {
     always_suspend: Option<bool>,
 }
 
-#[derive(Deserialize, Default, Debug, Copy, Clone)]
+#[derive(Deserialize, Serialize, Default, Debug, Copy, Clone)]
 #[serde(rename_all = "snake_case")]
 pub enum UpdatesAutoReboot {
     Yes,
@@ -106,7 +232,7 @@ pub enum UpdatesAutoReboot {
     Ask,
 }
 
-#[derive(Deserialize, Default, Debug, Merge)]
+#[derive(Deserialize, Serialize, Default, Debug, Merge)]
 #[serde(deny_unknown_fields)]
 pub struct Windows {
     accept_all_updates: Option<bool>,
@@ -119,7 +245,7 @@ pub struct Windows {
     winget_use_sudo: Option<bool>,
 }
 
-#[derive(Deserialize, Default, Debug, Merge)]
+#[derive(Deserialize, Serialize, Default, Debug, Merge)]
 #[serde(deny_unknown_fields)]
 pub struct Python {
     enable_pip_review: Option<bool>,
@@ -129,7 +255,7 @@ pub struct Python {
     poetry_force_self_update: Option<bool>,
 }
 
-#[derive(Deserialize, Default, Debug, Merge)]
+#[derive(Deserialize, Serialize, Default, Debug, Merge)]
 #[serde(deny_unknown_fields)]
 pub struct Conda {
     #[merge(strategy = crate::utils::merge_strategies::vec_prepend_opt)]
@@ -139,7 +265,7 @@ pub struct Conda {
     env_paths: Option<Vec<String>>,
 }
 
-#[derive(Deserialize, Default, Debug, Merge)]
+#[derive(Deserialize, Serialize, Default, Debug, Merge)]
 #[serde(deny_unknown_fields)]
 pub struct Distrobox {
     use_root: Option<bool>,
@@ -148,66 +274,106 @@ pub struct Distrobox {
     containers: Option<Vec<String>>,
 }
 
-#[derive(Deserialize, Default, Debug, Merge)]
+#[derive(Deserialize, Serialize, Default, Debug, Merge)]
 #[serde(deny_unknown_fields)]
 pub struct Yarn {
     use_sudo: Option<bool>,
 }
 
-#[derive(Deserialize, Default, Debug, Merge)]
+#[derive(Deserialize, Serialize, Default, Debug, Merge)]
 #[serde(deny_unknown_fields)]
 pub struct VitePlus {
     use_sudo: Option<bool>,
 }
 
-#[derive(Deserialize, Default, Debug, Merge)]
+#[derive(Deserialize, Serialize, Default, Debug, Merge)]
 #[serde(deny_unknown_fields)]
 pub struct Npm {
     use_sudo: Option<bool>,
 }
 
-#[derive(Deserialize, Default, Debug, Merge)]
+#[derive(Deserialize, Serialize, Default, Debug, Merge)]
 #[serde(deny_unknown_fields)]
 pub struct Deno {
     version: Option<String>,
 }
 
-#[derive(Deserialize, Default, Debug, Merge)]
+#[derive(Deserialize, Serialize, Default, Debug, Merge)]
 #[serde(deny_unknown_fields)]
 pub struct Chezmoi {
     exclude_encrypted: Option<bool>,
 }
 
-#[derive(Deserialize, Default, Debug, Merge)]
+#[derive(Deserialize, Serialize, Default, Debug, Merge, Clone)]
 #[serde(deny_unknown_fields)]
 pub struct Mise {
-    bump: Option<bool>,
-    interactive: Option<bool>,
-    jobs: Option<u32>,
-    verbose: Option<bool>,
-    quiet: Option<bool>,
-    silent: Option<bool>,
+    pub bump: Option<bool>,
+    pub interactive: Option<bool>,
+    pub jobs: Option<u32>,
+    pub verbose: Option<bool>,
+    pub quiet: Option<bool>,
+    pub silent: Option<bool>,
+}
+
+impl StepOptions for Mise {
+    const STEP: Step = Step::Mise;
+    const REFERENCE: &'static [OptionDoc] = &[
+        OptionDoc {
+            name: "bump",
+            example: "true",
+            description: "Upgrade to the latest version available, bumping the version in mise.toml",
+        },
+        OptionDoc {
+            name: "jobs",
+            example: "8",
+            description: "Number of jobs to run in parallel (mise's default)",
+        },
+        OptionDoc {
+            name: "interactive",
+            example: "true",
+            description: "Run interactively",
+        },
+        OptionDoc {
+            name: "quiet",
+            example: "true",
+            description: "Suppress non-error messages",
+        },
+        OptionDoc {
+            name: "silent",
+            example: "true",
+            description: "Suppress all task output and mise non-error messages",
+        },
+        OptionDoc {
+            name: "verbose",
+            example: "true",
+            description: "Show extra output",
+        },
+    ];
+
+    fn legacy(config: &ConfigFile) -> Option<&Self> {
+        config.mise.as_ref()
+    }
 }
 
-#[derive(Deserialize, Default, Debug, Merge)]
+#[derive(Deserialize, Serialize, Default, Debug, Merge)]
 #[serde(deny_unknown_fields)]
 pub struct Firmware {
     upgrade: Option<bool>,
 }
 
-#[derive(Deserialize, Default, Debug, Merge)]
+#[derive(Deserialize, Serialize, Default, Debug, Merge)]
 #[serde(deny_unknown_fields)]
 pub struct Flatpak {
     use_sudo: Option<bool>,
 }
 
-#[derive(Deserialize, Default, Debug, Merge)]
+#[derive(Deserialize, Serialize, Default, Debug, Merge)]
 #[serde(deny_unknown_fields)]
 pub struct Pixi {
     include_release_notes: Option<bool>,
 }
 
-#[derive(Deserialize, Default, Debug, Merge)]
+#[derive(Deserialize, Serialize, Default, Debug, Merge)]
 #[serde(deny_unknown_fields)]
 pub struct Brew {
     greedy_cask: Option<bool>,
@@ -217,14 +383,14 @@ pub struct Brew {
     fetch_head: Option<bool>,
 }
 
-#[derive(Deserialize, Default, Debug, Merge)]
+#[derive(Deserialize, Serialize, Default, Debug, Merge)]
 #[serde(deny_unknown_fields)]
 pub struct Go {
     #[merge(strategy = crate::utils::merge_strategies::vec_prepend_opt)]
     gup_exclude: Option<Vec<String>>,
 }
 
-#[derive(Debug, Deserialize, Clone, Copy, Default)]
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, Default)]
 #[serde(rename_all = "snake_case")]
 pub enum ArchPackageManager {
     #[default]
@@ -240,7 +406,7 @@ pub enum ArchPackageManager {
     Yay,
 }
 
-#[derive(Clone, Copy, Debug, Deserialize, Default)]
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, Default)]
 #[serde(rename_all = "snake_case")]
 pub enum ContainerRuntime {
     #[default] // defaults to a popular choice
@@ -257,7 +423,28 @@ impl fmt::Display for ContainerRuntime {
     }
 }
 
-#[derive(Debug, Deserialize, Clone, Copy, Default, PartialEq)]
+/// The tool to take filesystem snapshots around the system update with, see `linux.snapshot`.
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum SnapshotTool {
+    Snapper,
+    Timeshift,
+    Btrfs,
+    Zfs,
+}
+
+impl fmt::Display for SnapshotTool {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SnapshotTool::Snapper => write!(f, "snapper"),
+            SnapshotTool::Timeshift => write!(f, "timeshift"),
+            SnapshotTool::Btrfs => write!(f, "btrfs"),
+            SnapshotTool::Zfs => write!(f, "zfs"),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, Default, PartialEq)]
 #[serde(rename_all = "kebab-case")]
 pub enum NixHandler {
     #[default]
@@ -266,7 +453,7 @@ pub enum NixHandler {
     Vanilla,
 }
 
-#[derive(Deserialize, Default, Debug, Merge)]
+#[derive(Deserialize, Serialize, Default, Debug, Merge)]
 #[serde(deny_unknown_fields)]
 pub struct Linux {
     #[merge(strategy = crate::utils::merge_strategies::string_append_opt)]
@@ -323,15 +510,19 @@ pub struct Linux {
     home_manager_arguments: Option<Vec<String>>,
 
     wsl_use_windows_path: Option<bool>,
+
+    snapshot: Option<SnapshotTool>,
+
+    snapshot_dir: Option<String>,
 }
 
-#[derive(Deserialize, Default, Debug, Merge)]
+#[derive(Deserialize, Serialize, Default, Debug, Merge)]
 #[serde(deny_unknown_fields)]
 pub struct Composer {
     self_update: Option<bool>,
 }
 
-#[derive(Deserialize, Default, Debug, Merge)]
+#[derive(Deserialize, Serialize, Default, Debug, Merge)]
 #[serde(deny_unknown_fields)]
 pub struct Vim {
     force_plug_update: Option<bool>,
@@ -339,7 +530,52 @@ pub struct Vim {
     vim_pack_prune: Option<bool>,
 }
 
-#[derive(Deserialize, Default, Debug, Merge)]
+/// Ordering constraints of a single step, see `misc.order`.
+#[derive(Deserialize, Serialize, Default, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct StepOrder {
+    #[serde(default)]
+    before: Vec<Step>,
+
+    #[serde(default)]
+    after: Vec<Step>,
+}
+
+/// A remote host to run Topgrade on, see `[[remotes]]`.
+#[derive(Deserialize, Serialize, Default, Debug, Clone, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct Remote {
+    /// The host as passed to ssh, e.g. `user@pi`.
+    pub host: String,
+
+    /// Passed to ssh after `misc.ssh_arguments`.
+    pub ssh_arguments: Option<String>,
+
+    /// Overrides `misc.remote_topgrade_path`.
+    pub topgrade_path: Option<String>,
+
+    /// Passed to the remote Topgrade, e.g. `--only system`.
+    pub topgrade_arguments: Option<String>,
+
+    /// Environment variables to run the remote Topgrade with.
+    #[serde(default)]
+    pub env: IndexMap<String, String>,
+
+    /// The groups the host can be selected by with `--remote-group`.
+    #[serde(default)]
+    pub tags: Vec<String>,
+
+    /// Overrides `misc.push_remote_topgrade`.
+    pub push_topgrade: Option<bool>,
+
+    /// Overrides `misc.push_remote_config`.
+    pub push_config: Option<bool>,
+
+    #[serde(default)]
+    pub skip: bool,
+}
+
+#[derive(Deserialize, Serialize, Default, Debug, Merge)]
 #[serde(deny_unknown_fields)]
 pub struct Misc {
     allow_root: Option<bool>,
@@ -361,6 +597,9 @@ pub struct Misc {
     #[merge(strategy = crate::utils::merge_strategies::vec_prepend_opt)]
     last: Option<Vec<Step>>,
 
+    #[merge(strategy = crate::utils::merge_strategies::indexmap_merge_opt)]
+    order: Option<IndexMap<Step, StepOrder>>,
+
     #[merge(strategy = crate::utils::merge_strategies::vec_prepend_opt)]
     ignore_failures: Option<Vec<Step>>,
 
@@ -372,6 +611,12 @@ pub struct Misc {
     #[merge(strategy = crate::utils::merge_strategies::string_append_opt)]
     ssh_arguments: Option<String>,
 
+    parallel_remotes: Option<usize>,
+
+    push_remote_topgrade: Option<bool>,
+
+    push_remote_config: Option<bool>,
+
     #[merge(strategy = crate::utils::merge_strategies::string_append_opt)]
     tmux_arguments: Option<String>,
 
@@ -415,9 +660,23 @@ pub struct Misc {
     show_distribution_summary: Option<bool>,
 
     nix_handler: Option<NixHandler>,
+
+    history_size: Option<usize>,
+
+    parallel_steps: Option<usize>,
+
+    step_logs: Option<bool>,
+
+    step_timeout: Option<u64>,
+
+    #[merge(strategy = crate::utils::merge_strategies::indexmap_merge_opt)]
+    step_timeouts: Option<IndexMap<Step, u64>>,
+
+    #[merge(strategy = crate::utils::merge_strategies::indexmap_merge_opt)]
+    intervals: Option<IndexMap<Step, Interval>>,
 }
 
-#[derive(Clone, Copy, Debug, Deserialize, ValueEnum, Default)]
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, ValueEnum, Default)]
 #[clap(rename_all = "snake_case")]
 #[serde(rename_all = "snake_case")]
 pub enum TmuxSessionMode {
@@ -427,7 +686,7 @@ pub enum TmuxSessionMode {
 }
 
 /// Controls when the end-of-run desktop notification is sent.
-#[derive(Clone, Copy, Debug, Deserialize, ValueEnum, Default)]
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, ValueEnum, Default)]
 #[clap(rename_all = "snake_case")]
 #[serde(rename_all = "snake_case")]
 pub enum NotifyEnd {
@@ -445,46 +704,83 @@ pub struct TmuxConfig {
     pub session_mode: TmuxSessionMode,
 }
 
-#[derive(Deserialize, Default, Debug, Merge)]
+#[derive(Deserialize, Serialize, Default, Debug, Merge)]
 #[serde(deny_unknown_fields)]
 pub struct Lensfun {
     use_sudo: Option<bool>,
 }
 
-#[derive(Deserialize, Default, Debug, Merge)]
+#[derive(Deserialize, Serialize, Default, Debug, Merge)]
 #[serde(deny_unknown_fields)]
 pub struct JuliaConfig {
     startup_file: Option<bool>,
 }
 
-#[derive(Deserialize, Default, Debug, Merge)]
+#[derive(Deserialize, Serialize, Default, Debug, Merge, Clone)]
 #[serde(deny_unknown_fields)]
 pub struct Zigup {
-    target_versions: Option<Vec<String>>,
-    install_dir: Option<String>,
-    path_link: Option<String>,
-    cleanup: Option<bool>,
+    pub target_versions: Option<Vec<String>>,
+    pub install_dir: Option<String>,
+    pub path_link: Option<String>,
+    pub cleanup: Option<bool>,
 }
 
-#[derive(Deserialize, Default, Debug, Merge)]
+impl Zigup {
+    pub fn target_versions(&self) -> Vec<String> {
+        self.target_versions.clone().unwrap_or(vec!["master".to_owned()])
+    }
+}
+
+impl StepOptions for Zigup {
+    const STEP: Step = Step::Zigup;
+    const REFERENCE: &'static [OptionDoc] = &[
+        OptionDoc {
+            name: "target_versions",
+            example: r#"["master", "0.13.0"]"#,
+            description: "Versions to update, pinned ones such as \"0.13.0\" or branches such as \"master\",\n\
+                          each in its own zigup invocation (default: [\"master\"])",
+        },
+        OptionDoc {
+            name: "install_dir",
+            example: r#""~/.zig""#,
+            description: "The directory to install zig to, passed with --install-dir",
+        },
+        OptionDoc {
+            name: "path_link",
+            example: r#""~/.bin/zig""#,
+            description: "The symlink to point at the default compiler version, passed with --path-link",
+        },
+        OptionDoc {
+            name: "cleanup",
+            example: "true",
+            description: "Keep the versions above with `zigup keep` and run `zigup clean` after updating them",
+        },
+    ];
+
+    fn legacy(config: &ConfigFile) -> Option<&Self> {
+        config.zigup.as_ref()
+    }
+}
+
+#[derive(Deserialize, Serialize, Default, Debug, Merge)]
 #[serde(deny_unknown_fields)]
 pub struct VscodeConfig {
     profile: Option<String>,
 }
 
-#[derive(Deserialize, Default, Debug, Merge)]
+#[derive(Deserialize, Serialize, Default, Debug, Merge)]
 #[serde(deny_unknown_fields)]
 pub struct DoomConfig {
     aot: Option<bool>,
 }
 
-#[derive(Deserialize, Default, Debug, Merge)]
+#[derive(Deserialize, Serialize, Default, Debug, Merge)]
 #[serde(deny_unknown_fields)]
 pub struct Flutter {
     force: Option<bool>,
 }
 
-#[derive(Deserialize, Default, Debug, Merge)]
+#[derive(Deserialize, Serialize, Default, Debug, Merge)]
 #[serde(deny_unknown_fields)]
 pub struct Cargo {
     git: Option<bool>,
@@ -492,19 +788,45 @@ pub struct Cargo {
     locked: Option<bool>,
 }
 
-#[derive(Deserialize, Default, Debug, Merge)]
+#[derive(Deserialize, Serialize, Default, Debug, Merge)]
 #[serde(deny_unknown_fields)]
 pub struct Rustup {
     channels: Option<Vec<String>>,
 }
 
-#[derive(Deserialize, Default, Debug, Merge)]
+#[derive(Deserialize, Serialize, Default, Debug, Merge)]
 #[serde(deny_unknown_fields)]
 pub struct Pkgfile {
     enable: Option<bool>,
 }
 
-#[derive(Deserialize, Default, Debug, Merge)]
+/// A `[steps.<step>]` table.
+#[derive(Deserialize, Serialize, Default, Debug, Clone)]
+pub struct StepSettings {
+    enabled: Option<bool>,
+    yes: Option<bool>,
+    ignore_failure: Option<bool>,
+    extra_args: Option<Vec<String>>,
+    env: Option<IndexMap<String, String>>,
+    working_dir: Option<String>,
+
+    /// The options of the step's own `StepOptions`.
+    #[serde(flatten)]
+    options: toml::Table,
+}
+
+fn deserialize_steps<'de, D>(deserializer: D) -> Result<Option<IndexMap<Step, StepSettings>>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    let steps = IndexMap::<Step, StepSettings>::deserialize(deserializer)?;
+    for (step, settings) in &steps {
+        step_config::validate(*step, &settings.options).map_err(serde::de::Error::custom)?;
+    }
+    Ok(Some(steps))
+}
+
+#[derive(Deserialize, Serialize, Default, Debug, Merge)]
 #[serde(deny_unknown_fields)]
 /// Configuration file
 pub struct ConfigFile {
@@ -521,7 +843,17 @@ pub struct ConfigFile {
     post_commands: Option<Commands>,
 
     #[merge(strategy = crate::utils::merge_strategies::indexmap_merge_opt)]
-    commands: Option<Commands>,
+    commands: Option<CustomCommands>,
+
+    #[merge(strategy = crate::utils::merge_strategies::vec_prepend_opt)]
+    notifiers: Option<Vec<Notifier>>,
+
+    #[merge(strategy = crate::utils::merge_strategies::vec_prepend_opt)]
+    remotes: Option<Vec<Remote>>,
+
+    #[serde(default, deserialize_with = "deserialize_steps")]
+    #[merge(strategy = crate::utils::merge_strategies::indexmap_merge_opt)]
+    steps: Option<IndexMap<Step, StepSettings>>,
 
     #[merge(strategy = merge2::option::recursive)]
     conda: Option<Conda>,
@@ -625,8 +957,13 @@ fn config_directory() -> PathBuf {
     return crate::WINDOWS_DIRS.config_dir();
 }
 
+/// The directory of the step plugins that aren't on `PATH`, see `steps::plugins`.
+pub fn plugin_directory() -> PathBuf {
+    config_directory().join("topgrade.d").join("steps")
+}
+
 /// The only purpose of this struct is to deserialize only the `include` field of the config file.
-#[derive(Deserialize, Default, Debug)]
+#[derive(Deserialize, Serialize, Default, Debug)]
 struct ConfigFileIncludeOnly {
     include: Option<Include>,
 }
@@ -636,6 +973,27 @@ impl ConfigFile {
     /// 0 = main config file
     /// 1 = additional config files coming from topgrade.d
     fn ensure() -> Result<(PathBuf, Vec<PathBuf>)> {
+        let mut res = Self::locate()?;
+
+        // If no config file exists, create a default one in the config directory
+        if !res.0.exists() && res.1.is_empty() {
+            res.0 = config_directory().join("topgrade.toml");
+            debug!("No configuration exists");
+            write(&res.0, EXAMPLE_CONFIG).map_err(|e| {
+                debug!(
+                    "Unable to write the example configuration file to {}: {}. Using blank config.",
+                    &res.0.display(),
+                    e
+                );
+                e
+            })?;
+        }
+
+        Ok(res)
+    }
+
+    /// Returns the main config file, or an empty path if there is none, and any additional config files
+    pub(crate) fn locate() -> Result<(PathBuf, Vec<PathBuf>)> {
         let mut res = (PathBuf::new(), Vec::new());
 
         let config_directory = config_directory();
@@ -656,20 +1014,6 @@ impl ConfigFile {
 
         res.1 = Self::find_topgrade_d_configs(&config_directory)?;
 
-        // If no config file exists, create a default one in the config directory
-        if !res.0.exists() && res.1.is_empty() {
-            res.0.clone_from(&possible_config_paths[0]);
-            debug!("No configuration exists");
-            write(&res.0, EXAMPLE_CONFIG).map_err(|e| {
-                debug!(
-                    "Unable to write the example configuration file to {}: {}. Using blank config.",
-                    &res.0.display(),
-                    e
-                );
-                e
-            })?;
-        }
-
         Ok(res)
     }
 
@@ -708,6 +1052,17 @@ impl ConfigFile {
     /// If the configuration file does not exist, the function returns the default ConfigFile.
     fn read(config_path: Option<PathBuf>) -> Result<ConfigFile> {
         let mut result = Self::default();
+        for (_, mut source) in Self::read_sources(config_path)? {
+            result.merge(&mut source);
+        }
+
+        debug!("Loaded configuration: {:?}", result);
+        Ok(result)
+    }
+
+    /// Read every configuration file, in the order they are merged in.
+    pub(crate) fn read_sources(config_path: Option<PathBuf>) -> Result<Vec<(PathBuf, ConfigFile)>> {
+        let mut result = Vec::new();
 
         let config_path = if let Some(path) = config_path {
             path
@@ -722,11 +1077,11 @@ impl ConfigFile {
                 let include_contents = fs::read_to_string(&include).inspect_err(|_| {
                     error!("Unable to read {}", include.display());
                 })?;
-                let mut include_contents_parsed = toml::from_str(include_contents.as_str()).inspect_err(|_| {
+                let include_contents_parsed = toml::from_str(include_contents.as_str()).inspect_err(|_| {
                     error!("Failed to deserialize {}", include.display());
                 })?;
 
-                result.merge(&mut include_contents_parsed);
+                result.push((include, include_contents_parsed));
             }
 
             path
@@ -746,8 +1101,7 @@ impl ConfigFile {
 
         // To parse [include] sections in the order as they are written,
         // we split the file and parse each part as a separate file
-        let regex_match_include = Regex::new(r"^\s*\[include]").expect("Failed to compile regex");
-        let contents_split = regex_match_include.split_inclusive_left(contents_non_split.as_str());
+        let contents_split = Self::split_includes(&contents_non_split);
 
         for contents in contents_split {
             let config_file_include_only: ConfigFileIncludeOnly = toml::from_str(contents).inspect_err(|_| {
@@ -768,7 +1122,7 @@ impl ConfigFile {
                             }
                         };
                         match toml::from_str::<Self>(&include_contents) {
-                            Ok(mut include_parsed) => result.merge(&mut include_parsed),
+                            Ok(include_parsed) => result.push((include_path, include_parsed)),
                             Err(e) => {
                                 error!("Failed to deserialize {}: {e}", include_path.display(),);
                                 continue;
@@ -779,15 +1133,20 @@ impl ConfigFile {
             }
 
             match toml::from_str::<Self>(contents) {
-                Ok(mut contents) => result.merge(&mut contents),
+                Ok(contents) => result.push((config_path.clone(), contents)),
                 Err(e) => error!("Failed to deserialize {}: {e}", config_path.display(),),
             }
         }
 
-        debug!("Loaded configuration: {:?}", result);
         Ok(result)
     }
 
+    /// Split the main config file before each `[include]` section.
+    pub(crate) fn split_includes(contents: &str) -> Vec<&str> {
+        let regex_match_include = Regex::new(r"^\s*\[include]").expect("Failed to compile regex");
+        regex_match_include.split_inclusive_left(contents).collect()
+    }
+
     fn edit() -> Result<()> {
         let config_path = Self::ensure()?.0;
         debug!("Editing config file: {:?}", config_path);
@@ -822,6 +1181,14 @@ pub struct CommandLineArgs {
     #[arg(long = "config-reference")]
     show_config_reference: bool,
 
+    /// Check the configuration files for errors and exit
+    #[arg(long = "check-config")]
+    check_config: bool,
+
+    /// Show the merged configuration and the file each value comes from, then exit
+    #[arg(long = "show-effective-config")]
+    show_effective_config: bool,
+
     /// Run inside tmux
     #[arg(short = 't', long = "tmux")]
     run_in_tmux: bool,
@@ -840,6 +1207,20 @@ pub struct CommandLineArgs {
     #[arg(short = 'n', long = "dry-run")]
     dry_run: bool,
 
+    /// List pending updates without installing them
+    ///
+    /// Alias for --run-type check
+    #[arg(long = "check")]
+    check: bool,
+
+    /// Continue an interrupted run, skipping the steps that already succeeded
+    #[arg(long = "resume")]
+    resume: bool,
+
+    /// Run steps even if `misc.intervals` says they aren't due yet
+    #[arg(long = "force")]
+    force: bool,
+
     /// Pick between just running commands, running and logging commands, and just logging commands
     #[arg(short = 'r', long = "run-type", value_enum, default_value_t)]
     run_type: RunType,
@@ -910,6 +1291,10 @@ pub struct CommandLineArgs {
     #[arg(long = "remote-host-limit", value_name = "REGEX")]
     remote_host_limit: Option<Regex>,
 
+    /// Run Topgrade only on the remote hosts of `[[remotes]]` tagged with one of these groups
+    #[arg(long = "remote-group", value_name = "GROUP", num_args = 1..)]
+    remote_groups: Vec<String>,
+
     /// Show the reason for skipped steps
     #[arg(long = "show-skipped")]
     show_skipped: bool,
@@ -943,6 +1328,63 @@ pub struct CommandLineArgs {
     /// Don't update Topgrade
     #[arg(long = "no-self-update")]
     pub no_self_update: bool,
+
+    /// Run up to N independent steps at the same time
+    ///
+    /// Only steps that update user-level tooling run in parallel; steps that need
+    /// sudo or the system package manager still run on their own.
+    #[arg(short = 'j', long = "jobs", value_name = "N")]
+    jobs: Option<usize>,
+
+    /// Run Topgrade on up to N remote hosts at the same time, without a terminal
+    ///
+    /// The output of every host is saved to a log file and a summary is shown at the end.
+    #[arg(long = "remote-jobs", value_name = "N")]
+    remote_jobs: Option<usize>,
+
+    /// Save the output of every step to a log file
+    #[arg(long = "step-logs")]
+    step_logs: bool,
+
+    /// The format of the machine-readable report written to `--report-file`
+    #[arg(long = "report-format", value_name = "FORMAT", value_enum, requires = "report_file")]
+    report_format: Option<ReportFormat>,
+
+    /// Write a machine-readable report of the run to PATH
+    ///
+    /// The report is in JSON unless `--report-format` says otherwise.
+    #[arg(long = "report-file", value_name = "PATH")]
+    report_file: Option<PathBuf>,
+
+    /// The format of the plan written to `--plan-file`
+    #[arg(long = "plan-format", value_name = "FORMAT", value_enum, requires = "plan_file")]
+    plan_format: Option<PlanFormat>,
+
+    /// Write the commands a dry run would execute to PATH
+    ///
+    /// Implies `--dry-run`. The plan is in JSON unless `--plan-format` says otherwise.
+    #[arg(long = "plan-file", value_name = "PATH")]
+    plan_file: Option<PathBuf>,
+
+    /// Save the output of the commands that are run to a fixture file at PATH
+    #[arg(long = "record-fixture", value_name = "PATH", conflicts_with = "replay_fixture")]
+    record_fixture: Option<PathBuf>,
+
+    /// Take the output of commands from the fixture file at PATH instead of running them
+    #[arg(long = "replay-fixture", value_name = "PATH")]
+    replay_fixture: Option<PathBuf>,
+
+    #[command(subcommand)]
+    pub command: Option<TopgradeCommand>,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum TopgradeCommand {
+    /// List past runs, or show the details of one of them
+    History {
+        /// The run to show, as numbered in the list (1 is the most recent run)
+        run: Option<usize>,
+    },
 }
 
 fn env_args_parser(arg: &str) -> Result<(String, String)> {
@@ -961,6 +1403,19 @@ impl CommandLineArgs {
         self.show_config_reference
     }
 
+    pub fn check_config(&self) -> bool {
+        self.check_config
+    }
+
+    pub fn show_effective_config(&self) -> bool {
+        self.show_effective_config
+    }
+
+    /// The configuration file passed with `--config`
+    pub fn config_path(&self) -> Option<&Path> {
+        self.config.as_deref()
+    }
+
     pub fn env_variables(&self) -> &Vec<(String, String)> {
         &self.env
     }
@@ -1042,10 +1497,19 @@ impl Config {
     }
 
     /// The list of custom steps.
-    pub fn commands(&self) -> &Option<Commands> {
+    pub fn commands(&self) -> &Option<CustomCommands> {
         &self.config_file.commands
     }
 
+    /// Whether a custom step runs right before or after `step`.
+    pub fn has_commands_around(&self, step: Step) -> bool {
+        self.config_file
+            .commands
+            .iter()
+            .flatten()
+            .any(|(_, command)| command.before == Some(step) || command.after == Some(step))
+    }
+
     /// The list of additional named conda environments.
     pub fn conda_env_names(&self) -> Option<&Vec<String>> {
         self.config_file
@@ -1075,6 +1539,14 @@ impl Config {
             .and_then(|containers| containers.ignored_containers.as_ref())
     }
 
+    /// The running containers to run Topgrade in (wildcards supported).
+    pub fn containers_exec_in(&self) -> Option<&Vec<String>> {
+        self.config_file
+            .containers
+            .as_ref()
+            .and_then(|containers| containers.exec_in.as_ref())
+    }
+
     /// The preferred runtime for container updates (podman / docker).
     pub fn containers_runtime(&self) -> ContainerRuntime {
         self.config_file
@@ -1141,6 +1613,14 @@ impl Config {
         {
             disabled_steps.extend(disabled);
         }
+        // `steps.<step>.enabled` overrides `misc.disable`, but not `--disable`
+        for (step, settings) in config_file.steps.iter().flatten() {
+            match settings.enabled {
+                Some(false) => disabled_steps.push(*step),
+                Some(true) if !opt.disable.contains(step) => disabled_steps.retain(|s| s != step),
+                _ => (),
+            }
+        }
 
         // When a deprecated step is mentioned,
         for step in enabled_steps
@@ -1201,8 +1681,10 @@ impl Config {
 
     /// Get the [RunType] for the current execution
     pub fn run_type(&self) -> RunType {
-        if self.opt.dry_run {
+        if self.opt.dry_run || self.plan_file().is_some() {
             RunType::Dry
+        } else if self.opt.check {
+            RunType::Check
         } else {
             self.opt.run_type
         }
@@ -1256,6 +1738,77 @@ impl Config {
             .and_then(|misc| misc.remote_topgrades.as_ref())
     }
 
+    /// The remote hosts to run Topgrade in, from `misc.remote_topgrades` and `[[remotes]]`.
+    ///
+    /// A host listed more than once runs once, with its `[[remotes]]` entry if it has one.
+    /// Hosts marked with `skip`, or not in any of the groups given with `--remote-group`, are left out.
+    pub fn remotes(&self) -> Vec<Remote> {
+        let configured = self.config_file.remotes.iter().flatten();
+        let listed = self
+            .remote_topgrades()
+            .into_iter()
+            .flatten()
+            .filter(|host| !configured.clone().any(|remote| remote.host == **host))
+            .map(|host| Remote {
+                host: host.clone(),
+                ..Remote::default()
+            });
+
+        let mut hosts = HashSet::new();
+        listed
+            .chain(configured.clone().cloned())
+            .filter(|remote| hosts.insert(remote.host.clone()))
+            .filter(|remote| !remote.skip)
+            .filter(|remote| {
+                self.opt.remote_groups.is_empty() || remote.tags.iter().any(|tag| self.opt.remote_groups.contains(tag))
+            })
+            .collect()
+    }
+
+    /// Whether to copy this Topgrade to `remote` if it doesn't have it, or has an older one.
+    pub fn push_remote_topgrade(&self, remote: &Remote) -> bool {
+        remote
+            .push_topgrade
+            .or_else(|| {
+                self.config_file
+                    .misc
+                    .as_ref()
+                    .and_then(|misc| misc.push_remote_topgrade)
+            })
+            .unwrap_or(false)
+    }
+
+    /// Whether a Topgrade copied to `remote` gets this configuration.
+    pub fn push_remote_config(&self, remote: &Remote) -> bool {
+        remote
+            .push_config
+            .or_else(|| self.config_file.misc.as_ref().and_then(|misc| misc.push_remote_config))
+            .unwrap_or(false)
+    }
+
+    /// This configuration as a config file for a Topgrade copied to a remote host.
+    ///
+    /// The remote hosts are left out, so the copy doesn't run Topgrade on them again, and so are
+    /// the commands and notifiers, which are written for this machine. The report, plan and
+    /// fixture files are only set on the command line, so they never reach the copy.
+    pub fn remote_config_file(&self) -> Result<String> {
+        let mut config = toml::Table::try_from(&self.config_file).context("Failed to serialize the configuration")?;
+        for key in [
+            "include",
+            "remotes",
+            "pre_commands",
+            "post_commands",
+            "commands",
+            "notifiers",
+        ] {
+            config.remove(key);
+        }
+        if let Some(toml::Value::Table(misc)) = config.get_mut("misc") {
+            misc.remove("remote_topgrades");
+        }
+        toml::to_string(&config).context("Failed to serialize the configuration")
+    }
+
     /// Path to Topgrade executable used for all remote hosts
     pub fn remote_topgrade_path(&self) -> &str {
         self.config_file
@@ -1352,6 +1905,11 @@ impl Config {
         notify_end
     }
 
+    /// Where to send notifications, desktop notifications unless configured otherwise
+    pub fn notifiers(&self) -> &[Notifier] {
+        self.config_file.notifiers.as_deref().unwrap_or(&[Notifier::Desktop])
+    }
+
     /// Whether to set the terminal title
     pub fn set_title(&self) -> bool {
         self.config_file
@@ -1363,6 +1921,10 @@ impl Config {
 
     /// Whether to say yes to package managers
     pub fn yes(&self, step: Step) -> bool {
+        if let Some(yes) = self.step_settings(step).and_then(|settings| settings.yes) {
+            return yes;
+        }
+
         if let Some(yes) = self.config_file.misc.as_ref().and_then(|misc| misc.assume_yes) {
             return yes;
         }
@@ -1603,6 +2165,20 @@ impl Config {
             .unwrap_or(true)
     }
 
+    /// The tool to take snapshots before and after the system update with, if any
+    pub fn linux_snapshot(&self) -> Option<SnapshotTool> {
+        self.config_file.linux.as_ref().and_then(|linux| linux.snapshot)
+    }
+
+    /// Where btrfs snapshots of the root subvolume are created
+    pub fn linux_snapshot_dir(&self) -> &str {
+        self.config_file
+            .linux
+            .as_ref()
+            .and_then(|linux| linux.snapshot_dir.as_deref())
+            .unwrap_or("/.snapshots")
+    }
+
     /// Get the package manager of an Arch Linux system
     pub fn arch_package_manager(&self) -> ArchPackageManager {
         self.config_file
@@ -1776,6 +2352,8 @@ impl Config {
     ///
     /// Steps in `first` run first (in the order listed), then any remaining default
     /// steps in their normal order, then steps in `last` (in the order listed).
+    /// The constraints in `misc.order` are applied on top of that, and win over
+    /// `first` and `last` when they disagree.
     pub fn steps(&self) -> Result<impl Iterator<Item = Step> + '_> {
         let misc = self.config_file.misc.as_ref();
         let first = misc.and_then(|m| m.first.as_deref()).unwrap_or_default();
@@ -1786,7 +2364,7 @@ impl Config {
             color_eyre::eyre::bail!("All steps included in `misc.first` and `misc.last` must be unique");
         }
 
-        Ok(first
+        let steps = first
             .iter()
             .copied()
             .chain(
@@ -1794,11 +2372,34 @@ impl Config {
                     .into_iter()
                     .filter(move |s| !specified.contains(s)),
             )
-            .chain(last.iter().copied()))
+            .chain(last.iter().copied())
+            .collect();
+
+        Ok(sort_steps(steps, &self.step_order())?.into_iter())
+    }
+
+    /// The constraints of `misc.order` as `(before, after)` pairs.
+    pub fn step_order(&self) -> Vec<(Step, Step)> {
+        let Some(order) = self.config_file.misc.as_ref().and_then(|misc| misc.order.as_ref()) else {
+            return Vec::new();
+        };
+
+        order
+            .iter()
+            .flat_map(|(&step, order)| {
+                let before = order.before.iter().map(move |&other| (step, other));
+                let after = order.after.iter().map(move |&other| (other, step));
+                before.chain(after)
+            })
+            .collect()
     }
 
     /// Determine if we should ignore failures for this step
     pub fn ignore_failure(&self, step: Step) -> bool {
+        if let Some(ignore) = self.step_settings(step).and_then(|settings| settings.ignore_failure) {
+            return ignore;
+        }
+
         self.config_file
             .misc
             .as_ref()
@@ -1860,6 +2461,37 @@ impl Config {
         self.opt.verbose
     }
 
+    /// Where to write the machine-readable run report, and in which format, if one was requested.
+    ///
+    /// Reports always go to a file, as stdout is full of output for humans.
+    pub fn report_file(&self) -> Option<(&Path, ReportFormat)> {
+        let path = self.opt.report_file.as_deref()?;
+        Some((path, self.opt.report_format.unwrap_or_default()))
+    }
+
+    /// Where to write the dry-run plan, and in which format, if one was requested.
+    ///
+    /// Plans always go to a file, as stdout is full of output for humans.
+    pub fn plan_file(&self) -> Option<(&Path, PlanFormat)> {
+        let path = self.opt.plan_file.as_deref()?;
+        Some((path, self.opt.plan_format.unwrap_or_default()))
+    }
+
+    /// Whether to continue the interrupted run.
+    pub fn resume(&self) -> bool {
+        self.opt.resume
+    }
+
+    /// Where to save the output of the commands that are run, if anywhere.
+    pub fn record_fixture(&self) -> Option<&Path> {
+        self.opt.record_fixture.as_deref()
+    }
+
+    /// The fixture file to take the output of commands from, if any.
+    pub fn replay_fixture(&self) -> Option<&Path> {
+        self.opt.replay_fixture.as_deref()
+    }
+
     /// After loading the config file, filter directives consist of 3 parts:
     ///
     ///     1. directives from the configuration file
@@ -1958,6 +2590,108 @@ impl Config {
             .unwrap_or(240)
     }
 
+    /// The number of past runs to keep in the history, 0 disables it
+    pub fn history_size(&self) -> usize {
+        self.config_file
+            .misc
+            .as_ref()
+            .and_then(|misc| misc.history_size)
+            .unwrap_or(100)
+    }
+
+    /// Whether to save the output of every step to a log file
+    pub fn step_logs(&self) -> bool {
+        self.opt.step_logs
+            || self
+                .config_file
+                .misc
+                .as_ref()
+                .and_then(|misc| misc.step_logs)
+                .unwrap_or(false)
+    }
+
+    /// The `[steps.<step>]` table of `step`, if any.
+    fn step_settings(&self, step: Step) -> Option<&StepSettings> {
+        self.config_file.steps.as_ref().and_then(|steps| steps.get(&step))
+    }
+
+    /// The options of a step from its `[steps.<step>]` table and its legacy section.
+    pub fn step_options<T: StepOptions>(&self) -> T {
+        let mut options = self
+            .step_settings(T::STEP)
+            // The options were validated when the configuration was read.
+            .and_then(|settings| step_config::parse::<T>(&settings.options).ok())
+            .unwrap_or_default();
+        if let Some(legacy) = T::legacy(&self.config_file) {
+            options.merge(&mut legacy.clone());
+        }
+        options
+    }
+
+    /// Arguments appended to the update command of `step`.
+    pub fn step_extra_args(&self, step: Step) -> &[String] {
+        self.step_settings(step)
+            .and_then(|settings| settings.extra_args.as_deref())
+            .unwrap_or_default()
+    }
+
+    /// Environment variables for the commands of `step`.
+    pub fn step_env(&self, step: Step) -> impl Iterator<Item = (&String, &String)> {
+        self.step_settings(step)
+            .and_then(|settings| settings.env.as_ref())
+            .into_iter()
+            .flatten()
+    }
+
+    /// The directory to run the commands of `step` in.
+    pub fn step_working_dir(&self, step: Step) -> Option<PathBuf> {
+        self.step_settings(step)
+            .and_then(|settings| settings.working_dir.as_deref())
+            .map(|dir| PathBuf::from(shellexpand::tilde(dir).into_owned()))
+    }
+
+    /// How long `step` may run before it is killed, `None` if it may run forever
+    pub fn step_timeout(&self, step: Step) -> Option<Duration> {
+        let misc = self.config_file.misc.as_ref();
+        misc.and_then(|misc| misc.step_timeouts.as_ref())
+            .and_then(|timeouts| timeouts.get(&step).copied())
+            .or_else(|| misc.and_then(|misc| misc.step_timeout))
+            .filter(|&secs| secs > 0)
+            .map(Duration::from_secs)
+    }
+
+    /// How long to wait after `step` succeeded before running it again.
+    pub fn step_interval(&self, step: Step) -> Option<Interval> {
+        self.config_file
+            .misc
+            .as_ref()
+            .and_then(|misc| misc.intervals.as_ref())
+            .and_then(|intervals| intervals.get(&step).copied())
+    }
+
+    /// Whether to run steps that aren't due according to `misc.intervals`.
+    pub fn force(&self) -> bool {
+        self.opt.force
+    }
+
+    /// On how many remote hosts to run Topgrade at the same time, if they run
+    /// non-interactively instead of one after another in a terminal.
+    pub fn remote_jobs(&self) -> Option<usize> {
+        self.opt
+            .remote_jobs
+            .or_else(|| self.config_file.misc.as_ref().and_then(|misc| misc.parallel_remotes))
+            .map(|jobs| jobs.max(1))
+    }
+
+    /// How many steps may run at the same time, 1 runs all steps one after another
+    pub fn jobs(&self) -> usize {
+        self.opt
+            .jobs
+            .or_else(|| self.config_file.misc.as_ref().and_then(|misc| misc.parallel_steps))
+            .unwrap_or(1)
+            .max(1)
+    }
+
     pub fn show_distribution_summary(&self) -> bool {
         self.config_file
             .misc
@@ -2103,36 +2837,6 @@ impl Config {
             .unwrap_or(true)
     }
 
-    pub fn zigup_target_versions(&self) -> Vec<String> {
-        self.config_file
-            .zigup
-            .as_ref()
-            .and_then(|zigup| zigup.target_versions.clone())
-            .unwrap_or(vec!["master".to_owned()])
-    }
-
-    pub fn zigup_install_dir(&self) -> Option<&str> {
-        self.config_file
-            .zigup
-            .as_ref()
-            .and_then(|zigup| zigup.install_dir.as_deref())
-    }
-
-    pub fn zigup_path_link(&self) -> Option<&str> {
-        self.config_file
-            .zigup
-            .as_ref()
-            .and_then(|zigup| zigup.path_link.as_deref())
-    }
-
-    pub fn zigup_cleanup(&self) -> bool {
-        self.config_file
-            .zigup
-            .as_ref()
-            .and_then(|zigup| zigup.cleanup)
-            .unwrap_or(false)
-    }
-
     pub fn chezmoi_exclude_encrypted(&self) -> bool {
         self.config_file
             .chezmoi
@@ -2141,50 +2845,6 @@ impl Config {
             .unwrap_or(false)
     }
 
-    pub fn mise_bump(&self) -> bool {
-        self.config_file
-            .mise
-            .as_ref()
-            .and_then(|mise| mise.bump)
-            .unwrap_or(false)
-    }
-
-    pub fn mise_jobs(&self) -> Option<u32> {
-        self.config_file.mise.as_ref().and_then(|mise| mise.jobs)
-    }
-
-    pub fn mise_interactive(&self) -> bool {
-        self.config_file
-            .mise
-            .as_ref()
-            .and_then(|mise| mise.interactive)
-            .unwrap_or(false)
-    }
-
-    pub fn mise_quiet(&self) -> bool {
-        self.config_file
-            .mise
-            .as_ref()
-            .and_then(|mise| mise.quiet)
-            .unwrap_or(false)
-    }
-
-    pub fn mise_silent(&self) -> bool {
-        self.config_file
-            .mise
-            .as_ref()
-            .and_then(|mise| mise.silent)
-            .unwrap_or(false)
-    }
-
-    pub fn mise_verbose(&self) -> bool {
-        self.config_file
-            .mise
-            .as_ref()
-            .and_then(|mise| mise.verbose)
-            .unwrap_or(false)
-    }
-
     pub fn vscode_profile(&self) -> Option<&str> {
         let vscode_cfg = self.config_file.vscode.as_ref()?;
         let profile = vscode_cfg.profile.as_ref()?;
@@ -2213,8 +2873,77 @@ impl Config {
     }
 }
 
+/// Sort `steps` so that every `(before, after)` constraint holds, keeping the
+/// existing order wherever the constraints allow it.
+///
+/// A step that has to run after others pulls those forward to run right before
+/// it, rather than being pushed back itself.
+fn sort_steps(steps: Vec<Step>, constraints: &[(Step, Step)]) -> Result<Vec<Step>> {
+    #[derive(Clone, Copy, PartialEq)]
+    enum Mark {
+        Unvisited,
+        Visiting,
+        Done,
+    }
+
+    fn visit(
+        index: usize,
+        steps: &[Step],
+        predecessors: &[Vec<usize>],
+        marks: &mut [Mark],
+        path: &mut Vec<usize>,
+        sorted: &mut Vec<Step>,
+    ) -> Result<()> {
+        match marks[index] {
+            Mark::Done => return Ok(()),
+            Mark::Visiting => {
+                // `path` leads from later steps to the ones they wait for, so the
+                // cycle reads backwards.
+                let start = path.iter().position(|&i| i == index).unwrap();
+                let cycle: Vec<String> = iter::once(index)
+                    .chain(path[start..].iter().rev().copied())
+                    .map(|i| format!("{:?}", steps[i]))
+                    .collect();
+                color_eyre::eyre::bail!("The steps in `misc.order` form a cycle: {}", cycle.join(" -> "));
+            }
+            Mark::Unvisited => (),
+        }
+
+        marks[index] = Mark::Visiting;
+        path.push(index);
+        for &previous in &predecessors[index] {
+            visit(previous, steps, predecessors, marks, path, sorted)?;
+        }
+        path.pop();
+        marks[index] = Mark::Done;
+        sorted.push(steps[index]);
+        Ok(())
+    }
+
+    let position: HashMap<Step, usize> = steps.iter().enumerate().map(|(index, &step)| (step, index)).collect();
+    let mut predecessors = vec![Vec::new(); steps.len()];
+    for (before, after) in constraints {
+        // Constraints on steps that aren't run have nothing to order.
+        if let (Some(&before), Some(&after)) = (position.get(before), position.get(after)) {
+            predecessors[after].push(before);
+        }
+    }
+    for previous in &mut predecessors {
+        previous.sort_unstable();
+        previous.dedup();
+    }
+
+    let mut marks = vec![Mark::Unvisited; steps.len()];
+    let mut sorted = Vec::with_capacity(steps.len());
+    for index in 0..steps.len() {
+        visit(index, &steps, &predecessors, &mut marks, &mut Vec::new(), &mut sorted)?;
+    }
+
+    Ok(sorted)
+}
+
 #[cfg(test)]
-mod test {
+pub(crate) mod test {
 
     use crate::config::*;
     use color_eyre::eyre::eyre;
@@ -2230,12 +2959,14 @@ mod test {
             runtime: Some(ContainerRuntime::Podman),
             system_prune: Some(false),
             use_sudo: None,
+            exec_in: None,
         };
         let mut right = Containers {
             ignored_containers: None,
             runtime: Some(ContainerRuntime::Docker),
             system_prune: None,
             use_sudo: Some(true),
+            exec_in: None,
         };
         left.merge(&mut right);
 
@@ -2285,6 +3016,85 @@ mod test {
         }
     }
 
+    #[test]
+    fn test_remotes() {
+        let mut config = config_from_toml(
+            r#"
+            [misc]
+            remote_topgrades = ["toothless", "db", "toothless", "admin@web"]
+
+            [[remotes]]
+            host = "admin@web"
+            topgrade_path = "/opt/topgrade"
+            topgrade_arguments = "--only system"
+            env = { LANG = "C" }
+            tags = ["servers"]
+
+            [[remotes]]
+            host = "db"
+            tags = ["servers"]
+            skip = true
+
+            [[remotes]]
+            host = "admin@web"
+            "#,
+        );
+
+        let hosts = |config: &Config| config.remotes().into_iter().map(|r| r.host).collect::<Vec<_>>();
+        assert_eq!(hosts(&config), ["toothless", "admin@web"]);
+        assert_eq!(config.remotes()[1].env["LANG"], "C");
+
+        config.opt = CommandLineArgs::parse_from(["topgrade", "--remote-group", "servers"]);
+        assert_eq!(hosts(&config), ["admin@web"]);
+
+        config.opt = CommandLineArgs::parse_from(["topgrade", "--remote-group", "desktops"]);
+        assert!(hosts(&config).is_empty());
+    }
+
+    #[test]
+    fn test_remote_config_file() {
+        let config = config_from_toml(
+            r#"
+            [misc]
+            remote_topgrades = ["toothless"]
+            disable = ["containers"]
+            intervals = { cargo = "7d" }
+
+            [[remotes]]
+            host = "web"
+            push_topgrade = true
+
+            [pre_commands]
+            "Mount backups" = "mount /mnt/backup"
+
+            [post_commands]
+            "Unmount backups" = "umount /mnt/backup"
+
+            [commands]
+            "Update dotfiles" = "git -C ~/.dotfiles pull"
+
+            [[notifiers]]
+            type = "ntfy"
+            url = "https://ntfy.sh/topgrade"
+            "#,
+        );
+        assert!(config.push_remote_topgrade(&config.remotes()[1]));
+        assert!(!config.push_remote_topgrade(&config.remotes()[0]));
+
+        let remote = config_from_toml(&config.remote_config_file().unwrap());
+        assert!(remote.remotes().is_empty());
+        assert_eq!(
+            remote.config_file.misc.as_ref().unwrap().disable,
+            Some(vec![Step::Containers])
+        );
+        assert_eq!(remote.step_interval(Step::Cargo), config.step_interval(Step::Cargo));
+        assert!(config.commands().is_some());
+        assert!(remote.pre_commands().is_none());
+        assert!(remote.post_commands().is_none());
+        assert!(remote.commands().is_none());
+        assert!(matches!(remote.notifiers(), [Notifier::Desktop]));
+    }
+
     #[test]
     fn test_should_execute_remote_different_hostname() {
         assert!(config().should_execute_remote(Ok("hostname".to_string()), "remote_hostname"));
@@ -2354,11 +3164,14 @@ x = "cmd_x"
         assert_eq!(env_vars[1], ("VAR2".to_string(), "bar".to_string()));
     }
 
-    fn config_from_toml(toml_str: &str) -> Config {
+    /// A configuration read from the contents of a config file, with no command line arguments.
+    pub(crate) fn config_from_toml(toml_str: &str) -> Config {
+        let opt = CommandLineArgs::parse_from::<_, String>([]);
+        let config_file = toml::from_str(toml_str).expect("toml parse error");
         Config {
-            opt: CommandLineArgs::parse_from::<_, String>([]),
-            config_file: toml::from_str(toml_str).expect("toml parse error"),
-            allowed_steps: Vec::new(),
+            allowed_steps: Config::allowed_steps(&opt, &config_file),
+            opt,
+            config_file,
         }
     }
 
@@ -2410,4 +3223,184 @@ last = ["cargo"]
         );
         assert!(config.steps().is_err());
     }
+
+    #[test]
+    fn test_parallel_resources() {
+        let config = config_from_toml("[misc]\nparallel_steps = 4\n");
+        assert_eq!(config.jobs(), 4);
+        assert_eq!(
+            Step::Rustup.parallel_resource(&config),
+            Step::Cargo.parallel_resource(&config)
+        );
+        assert_eq!(Step::Containers.parallel_resource(&config), Some("containers"));
+        assert_eq!(Step::System.parallel_resource(&config), None);
+
+        let config = config_from_toml("[containers]\nuse_sudo = true\n");
+        assert_eq!(config.jobs(), 1);
+        assert_eq!(Step::Containers.parallel_resource(&config), None);
+    }
+
+    #[test]
+    fn test_steps_order_constraints() {
+        let config = config_from_toml(
+            r#"
+[misc]
+first = ["cargo"]
+order.rustup = { before = ["cargo"] }
+order.custom_commands = { after = ["pip3", "vim"] }
+"#,
+        );
+        let steps: Vec<Step> = config.steps().unwrap().collect();
+        let position = |step| steps.iter().position(|&s| s == step).unwrap();
+
+        assert_eq!(&steps[..2], &[Step::Rustup, Step::Cargo]);
+        assert!(position(Step::CustomCommands) > position(Step::Pip3));
+        assert!(position(Step::CustomCommands) > position(Step::Vim));
+        assert_eq!(steps.len(), crate::step::default_steps().len());
+    }
+
+    #[test]
+    fn test_steps_order_rejects_cycles() {
+        let config = config_from_toml(
+            r#"
+[misc]
+order.cargo = { before = ["rustup"] }
+order.rustup = { before = ["go"] }
+order.go = { before = ["cargo"] }
+"#,
+        );
+        let error = config.steps().err().unwrap().to_string();
+        assert!(error.contains("Rustup -> Go -> Cargo -> Rustup"), "{error}");
+    }
+
+    #[test]
+    fn test_step_timeout_overrides() {
+        let timeouts = config_from_toml(
+            r#"
+[misc]
+step_timeout = 3600
+step_timeouts = { flatpak = 600, gcloud = 0 }
+"#,
+        );
+        assert_eq!(timeouts.step_timeout(Step::Cargo), Some(Duration::from_secs(3600)));
+        assert_eq!(timeouts.step_timeout(Step::Flatpak), Some(Duration::from_secs(600)));
+        assert_eq!(timeouts.step_timeout(Step::Gcloud), None);
+        assert_eq!(config().step_timeout(Step::Cargo), None);
+    }
+
+    #[test]
+    fn test_step_intervals() {
+        let config = config_from_toml(
+            r#"
+[misc.intervals]
+containers = "7d"
+tldr = "12h"
+"#,
+        );
+        assert_eq!(
+            config.step_interval(Step::Containers),
+            Some(Interval(Duration::from_secs(7 * 24 * 3600)))
+        );
+        assert_eq!(config.step_interval(Step::Cargo), None);
+        assert!(
+            toml::from_str::<ConfigFile>(
+                "[misc.intervals]
+containers = \"7 days\"\n"
+            )
+            .is_err()
+        );
+    }
+
+    #[test]
+    fn test_step_settings() {
+        let config_file: ConfigFile = toml::from_str(
+            r#"
+[misc]
+disable = ["tldr"]
+ignore_failures = ["cargo"]
+
+[mise]
+bump = true
+jobs = 2
+
+[steps.mise]
+jobs = 8
+yes = false
+extra_args = ["--raw"]
+env = { MISE_JOBS = "8" }
+
+[steps.cargo]
+ignore_failure = false
+
+[steps.tldr]
+enabled = true
+
+[steps.pipx]
+enabled = false
+"#,
+        )
+        .unwrap();
+        let opt = CommandLineArgs::parse_from(["topgrade", "--yes"]);
+        let allowed_steps = Config::allowed_steps(&opt, &config_file);
+        let config = Config {
+            opt,
+            config_file,
+            allowed_steps,
+        };
+
+        let mise = config.step_options::<Mise>();
+        assert_eq!(mise.jobs, Some(8));
+        assert_eq!(mise.bump, Some(true));
+        assert!(!config.yes(Step::Mise));
+        assert!(config.yes(Step::Cargo));
+        assert_eq!(config.step_extra_args(Step::Mise), ["--raw"]);
+        assert_eq!(config.step_extra_args(Step::Cargo), [] as [String; 0]);
+        assert_eq!(
+            config.step_env(Step::Mise).collect::<Vec<_>>(),
+            [(&"MISE_JOBS".to_string(), &"8".to_string())]
+        );
+        assert!(!config.ignore_failure(Step::Cargo));
+        assert!(config.should_run(Step::Tldr));
+        assert!(!config.should_run(Step::Pipx));
+        assert_eq!(config.step_options::<Zigup>().target_versions(), ["master"]);
+
+        let error = toml::from_str::<ConfigFile>("[steps.mise]\njobz = 8\n").unwrap_err();
+        assert!(error.message().contains("unknown field `jobz`"), "{error}");
+        let error = toml::from_str::<ConfigFile>("[steps.cargo]\njobs = 8\n").unwrap_err();
+        assert!(
+            error.message().contains("unknown option `jobs` for the cargo step"),
+            "{error}"
+        );
+    }
+
+    #[test]
+    fn test_custom_commands() {
+        let config = config_from_toml(
+            r#"
+[commands]
+"Simple" = "echo simple"
+
+[commands."In-house"]
+command = "inhouse update"
+require = "inhouse"
+sudo = true
+after = "system"
+ignore_failure = true
+"#,
+        );
+        let commands = config.commands().as_ref().unwrap();
+        assert_eq!(commands["Simple"].command, "echo simple");
+        assert_eq!(commands["Simple"].after, None);
+        let inhouse = &commands["In-house"];
+        assert_eq!(inhouse.require.as_deref(), Some("inhouse"));
+        assert!(inhouse.sudo && inhouse.ignore_failure);
+        assert!(config.has_commands_around(Step::System));
+        assert!(!config.has_commands_around(Step::Cargo));
+
+        let error =
+            toml::from_str::<ConfigFile>("[commands.x]\ncommand = \"x\"\nbefore = \"cargo\"\nafter = \"system\"\n")
+                .unwrap_err();
+        assert!(error.message().contains("can't both be set"), "{error}");
+        assert!(toml::from_str::<ConfigFile>("[commands.x]\ncomand = \"x\"\n").is_err());
+    }
 }
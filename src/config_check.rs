@@ -0,0 +1,342 @@
+//! `--check-config`: look for errors in the configuration without running anything.
+//!
+//! When loading the configuration, files that fail to parse are logged and
+//! skipped, so a typo can silently drop a whole include. This goes through the
+//! same files as `ConfigFile::read`, reports every problem with its location
+//! and fails if any of them is an error.
+
+use std::fmt;
+use std::fs;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+
+use color_eyre::eyre::Result;
+use console::style;
+use rust_i18n::t;
+use serde::Deserialize;
+use serde::de::IntoDeserializer;
+use serde::de::value::{self, StrDeserializer};
+use toml::Spanned;
+
+use crate::config::ConfigFile;
+use crate::step::{DEPRECATED_STEPS, Step};
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+enum Severity {
+    Error,
+    Warning,
+}
+
+/// A problem found in a configuration file.
+#[derive(Debug)]
+struct Diagnostic {
+    path: PathBuf,
+    line: usize,
+    column: usize,
+    severity: Severity,
+    message: String,
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let severity = match self.severity {
+            Severity::Error => style("error").red().bold(),
+            Severity::Warning => style("warning").yellow().bold(),
+        };
+        write!(
+            f,
+            "{}:{}:{}: {severity}: {}",
+            self.path.display(),
+            self.line,
+            self.column,
+            self.message
+        )
+    }
+}
+
+/// The parts of a config file that are checked beyond what deserializing `ConfigFile` does.
+///
+/// Unlike `ConfigFile`, this accepts unknown fields, so it can be read from any
+/// file that is valid TOML.
+#[derive(Deserialize, Default)]
+struct Spans {
+    include: Option<IncludeSpans>,
+    misc: Option<StepSpans>,
+}
+
+#[derive(Deserialize, Default)]
+struct IncludeSpans {
+    paths: Option<Vec<Spanned<String>>>,
+}
+
+#[derive(Deserialize, Default)]
+struct StepSpans {
+    disable: Option<Vec<Spanned<String>>>,
+    first: Option<Vec<Spanned<String>>>,
+    last: Option<Vec<Spanned<String>>>,
+    only: Option<Vec<Spanned<String>>>,
+}
+
+/// A configuration file being checked.
+struct Source<'a> {
+    path: &'a Path,
+    contents: &'a str,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl<'a> Source<'a> {
+    fn new(path: &'a Path, contents: &'a str) -> Self {
+        Self {
+            path,
+            contents,
+            diagnostics: Vec::new(),
+        }
+    }
+
+    fn report(&mut self, offset: usize, severity: Severity, message: String) {
+        let before = &self.contents[..offset.min(self.contents.len())];
+        let line = before.matches('\n').count() + 1;
+        let column = before.rsplit('\n').next().unwrap_or_default().chars().count() + 1;
+        self.diagnostics.push(Diagnostic {
+            path: self.path.to_path_buf(),
+            line,
+            column,
+            severity,
+            message,
+        });
+    }
+
+    /// Check the part of the file starting at `offset`, returning the include paths it lists.
+    fn check_part(&mut self, offset: usize, part: &str) -> Vec<String> {
+        // Only valid TOML can be checked for anything else, and deserializing
+        // `ConfigFile` reports why it isn't.
+        let spans: Spans = toml::from_str(part).unwrap_or_default();
+
+        let mut unknown_steps = Vec::new();
+        if let Some(misc) = spans.misc {
+            let lists = [
+                ("disable", misc.disable),
+                ("first", misc.first),
+                ("last", misc.last),
+                ("only", misc.only),
+            ];
+            for (name, steps) in lists {
+                for step in steps.into_iter().flatten() {
+                    let start = offset + step.span().start;
+                    let name_deserializer: StrDeserializer<value::Error> = step.get_ref().as_str().into_deserializer();
+                    match Step::deserialize(name_deserializer) {
+                        Ok(parsed) if DEPRECATED_STEPS.contains(&parsed) => {
+                            let message = t!("`{step}` step is deprecated", step = step.get_ref());
+                            self.report(start, Severity::Warning, message.to_string());
+                        }
+                        Ok(_) => (),
+                        Err(_) => {
+                            unknown_steps.push(step.span());
+                            self.report(
+                                start,
+                                Severity::Error,
+                                format!("`misc.{name}`: unknown step `{}`", step.get_ref()),
+                            );
+                        }
+                    }
+                }
+            }
+        }
+
+        if let Err(e) = toml::from_str::<ConfigFile>(part) {
+            let span = e.span().unwrap_or(0..0);
+            // Deserializing stops at the first unknown step, which was reported above.
+            if !unknown_steps.iter().any(|step| overlaps(step, &span)) {
+                let start = offset + span.start;
+                let message = match key_at(self.contents, start) {
+                    Some(key) => format!("`{key}`: {}", e.message()),
+                    None => e.message().to_string(),
+                };
+                self.report(start, Severity::Error, message);
+            }
+        }
+
+        spans
+            .include
+            .and_then(|include| include.paths)
+            .into_iter()
+            .flatten()
+            .map(Spanned::into_inner)
+            .collect()
+    }
+}
+
+fn overlaps(a: &Range<usize>, b: &Range<usize>) -> bool {
+    a.start <= b.end && b.start <= a.end
+}
+
+/// The dotted name of the key defined on the line containing `offset`, or the
+/// closest one above it, e.g. `misc.disable`.
+fn key_at(contents: &str, offset: usize) -> Option<String> {
+    let before = &contents[..offset.min(contents.len())];
+    let line_end = contents[before.len()..]
+        .find('\n')
+        .map_or(contents.len(), |end| before.len() + end);
+
+    let mut key = None;
+    for line in contents[..line_end].lines().rev().map(str::trim) {
+        if let Some(table) = line.strip_prefix('[') {
+            let table = table
+                .trim_start_matches('[')
+                .split(']')
+                .next()
+                .unwrap_or_default()
+                .trim();
+            return Some(match key {
+                Some(key) => format!("{table}.{key}"),
+                None => table.to_string(),
+            });
+        }
+        if key.is_none()
+            && let Some((name, _)) = line.split_once('=')
+            && !name.trim_start().starts_with(['"', '#', '{', ','])
+        {
+            key = Some(name.trim().to_string());
+        }
+    }
+    key
+}
+
+fn check_file(path: &Path, main: bool) -> Vec<Diagnostic> {
+    let contents = match fs::read_to_string(path) {
+        Ok(contents) => contents,
+        Err(e) => {
+            return vec![Diagnostic {
+                path: path.to_path_buf(),
+                line: 1,
+                column: 1,
+                severity: Severity::Error,
+                message: format!("Unable to read the file: {e}"),
+            }];
+        }
+    };
+
+    let mut source = Source::new(path, &contents);
+    let mut includes = Vec::new();
+    if main {
+        for part in ConfigFile::split_includes(&contents) {
+            let offset = part.as_ptr() as usize - contents.as_ptr() as usize;
+            includes.extend(source.check_part(offset, part));
+        }
+    } else {
+        // Includes are only followed from the main file.
+        source.check_part(0, &contents);
+    }
+
+    let mut diagnostics = source.diagnostics;
+    for include in includes {
+        let include = PathBuf::from(shellexpand::tilde(&include).into_owned());
+        diagnostics.extend(check_file(&include, false));
+    }
+    diagnostics
+}
+
+/// Check the configuration files, printing any problems found.
+///
+/// Returns whether the configuration is free of errors.
+pub fn run(config_path: Option<&Path>) -> Result<bool> {
+    let (main, dir_includes) = match config_path {
+        Some(path) => (path.to_path_buf(), Vec::new()),
+        None => ConfigFile::locate()?,
+    };
+
+    let mut diagnostics = Vec::new();
+    for include in &dir_includes {
+        diagnostics.extend(check_file(include, false));
+    }
+    if main != PathBuf::default() {
+        diagnostics.extend(check_file(&main, true));
+    }
+
+    for diagnostic in &diagnostics {
+        println!("{diagnostic}");
+    }
+
+    let errors = diagnostics.iter().filter(|d| d.severity == Severity::Error).count();
+    let warnings = diagnostics.len() - errors;
+    if errors > 0 {
+        println!(
+            "{}",
+            t!(
+                "The configuration has {errors} errors and {warnings} warnings",
+                errors = errors,
+                warnings = warnings
+            )
+        );
+    } else {
+        println!("{}", t!("The configuration is valid"));
+    }
+
+    Ok(errors == 0)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn check(contents: &str) -> Vec<(usize, usize, Severity, String)> {
+        let mut source = Source::new(Path::new("topgrade.toml"), contents);
+        for part in ConfigFile::split_includes(contents) {
+            let offset = part.as_ptr() as usize - contents.as_ptr() as usize;
+            source.check_part(offset, part);
+        }
+        source
+            .diagnostics
+            .into_iter()
+            .map(|d| (d.line, d.column, d.severity, d.message))
+            .collect()
+    }
+
+    #[test]
+    fn test_valid_config() {
+        assert!(check("[misc]\ndisable = [\"cargo\"]\n\n[git]\nrepos = [\"~/src\"]\n").is_empty());
+        assert!(check(crate::config::EXAMPLE_CONFIG).is_empty());
+    }
+
+    #[test]
+    fn test_unknown_field() {
+        let diagnostics = check("[misc]\ncleanup = true\n\n[git]\nrepo = []\n");
+        assert_eq!(diagnostics.len(), 1);
+        let (line, column, severity, message) = &diagnostics[0];
+        assert_eq!((*line, *column, *severity), (5, 1, Severity::Error));
+        assert!(message.starts_with("`git.repo`: unknown field `repo`"), "{message}");
+    }
+
+    #[test]
+    fn test_invalid_type_after_include() {
+        let diagnostics = check("[include]\npaths = []\n\n[misc]\nassume_yes = \"yes\"\n");
+        assert_eq!(diagnostics.len(), 1);
+        let (line, column, _, message) = &diagnostics[0];
+        assert_eq!((*line, *column), (5, 14));
+        assert!(message.starts_with("`misc.assume_yes`: invalid type"), "{message}");
+    }
+
+    #[test]
+    fn test_step_names() {
+        let diagnostics = check("[misc]\ndisable = [\n    \"cargo\",\n    \"carg\",\n]\nonly = [\"nix_helper\"]\n");
+        assert_eq!(
+            diagnostics,
+            [
+                (
+                    4,
+                    5,
+                    Severity::Error,
+                    String::from("`misc.disable`: unknown step `carg`")
+                ),
+                (6, 9, Severity::Warning, String::from("`nix_helper` step is deprecated")),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_syntax_error() {
+        let diagnostics = check("[misc]\ncleanup = tru\n");
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!((diagnostics[0].0, diagnostics[0].2), (2, Severity::Error));
+    }
+}
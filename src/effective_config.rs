@@ -0,0 +1,145 @@
+//! `--show-effective-config`: print the configuration that is in effect after merging.
+//!
+//! Every value is annotated with the file it came from. Values that several
+//! files contribute to, such as lists that are prepended to each other, are
+//! annotated with all of those files.
+
+use std::fmt::Write;
+use std::path::{Path, PathBuf};
+
+use color_eyre::eyre::{Context, Result};
+use merge2::Merge;
+use toml::{Table, Value};
+
+use crate::config::ConfigFile;
+
+/// Print the merged configuration read from `config_path` or the default locations.
+pub fn show(config_path: Option<&Path>) -> Result<()> {
+    let sources = ConfigFile::read_sources(config_path.map(Path::to_path_buf))?;
+    print!("{}", render(sources)?);
+    Ok(())
+}
+
+/// Merge `sources` like `ConfigFile::read` does and render the result as annotated TOML.
+fn render(sources: Vec<(PathBuf, ConfigFile)>) -> Result<String> {
+    let mut values = Vec::new();
+    let mut merged = ConfigFile::default();
+    for (path, mut source) in sources {
+        let value = Table::try_from(&source).context("Failed to serialize the configuration")?;
+        values.push((path, value));
+        merged.merge(&mut source);
+    }
+    let merged = Table::try_from(&merged).context("Failed to serialize the configuration")?;
+
+    let mut output = String::new();
+    render_table(&mut output, &[], &merged, &values);
+    Ok(output)
+}
+
+fn render_table(output: &mut String, path: &[&str], table: &Table, sources: &[(PathBuf, Table)]) {
+    // Tables that only contain other tables don't need a header of their own.
+    let needs_header = table.is_empty() || table.values().any(|value| !value.is_table());
+    if !path.is_empty() && needs_header {
+        let header: Vec<String> = path.iter().map(|key| quote_key(key)).collect();
+        writeln!(output, "\n[{}]", header.join(".")).unwrap();
+    }
+
+    for (key, value) in table.iter().filter(|(_, value)| !value.is_table()) {
+        let mut key_path = path.to_vec();
+        key_path.push(key);
+        let origins: Vec<String> = origins(&key_path, value, sources)
+            .iter()
+            .map(|origin| origin.display().to_string())
+            .collect();
+        writeln!(output, "{} = {value}  # {}", quote_key(key), origins.join(", ")).unwrap();
+    }
+
+    for (key, value) in table {
+        if let Value::Table(inner) = value {
+            let mut key_path = path.to_vec();
+            key_path.push(key);
+            render_table(output, &key_path, inner, sources);
+        }
+    }
+}
+
+/// The files the merged `value` at `path` came from.
+///
+/// Depending on the merge strategy, the first or the last file setting a value
+/// wins. If none of them has the merged value, it was combined from all of them.
+fn origins<'a>(path: &[&str], value: &Value, sources: &'a [(PathBuf, Table)]) -> Vec<&'a Path> {
+    let defining: Vec<(&Path, &Value)> = sources
+        .iter()
+        .filter_map(|(source, table)| Some((source.as_path(), lookup(table, path)?)))
+        .collect();
+
+    if let Some((source, _)) = defining.iter().find(|(_, defined)| *defined == value) {
+        return vec![source];
+    }
+
+    let mut origins: Vec<&Path> = Vec::new();
+    for (source, _) in defining {
+        if !origins.contains(&source) {
+            origins.push(source);
+        }
+    }
+    origins
+}
+
+fn lookup<'a>(table: &'a Table, path: &[&str]) -> Option<&'a Value> {
+    let (last, tables) = path.split_last()?;
+    let mut table = table;
+    for key in tables {
+        table = table.get(*key)?.as_table()?;
+    }
+    table.get(*last)
+}
+
+fn quote_key(key: &str) -> String {
+    if !key.is_empty() && key.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-') {
+        key.to_string()
+    } else {
+        Value::String(key.to_string()).to_string()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn source(path: &str, contents: &str) -> (PathBuf, ConfigFile) {
+        (PathBuf::from(path), toml::from_str(contents).unwrap())
+    }
+
+    #[test]
+    fn test_render_annotates_sources() {
+        let output = render(vec![
+            source(
+                "topgrade.d/a.toml",
+                "[misc]\nassume_yes = true\ndisable = [\"cargo\"]\n\n[commands]\n\"Update foo\" = \"foo -u\"\n",
+            ),
+            source(
+                "topgrade.toml",
+                "[misc]\nassume_yes = false\ncleanup = true\ndisable = [\"go\"]\n\n[commands]\nbar = \"bar\"\n",
+            ),
+        ])
+        .unwrap();
+
+        assert!(output.contains("\n[misc]\n"), "{output}");
+        assert!(output.contains("assume_yes = true  # topgrade.d/a.toml\n"), "{output}");
+        assert!(output.contains("cleanup = true  # topgrade.toml\n"), "{output}");
+        assert!(
+            output.contains("disable = [\"go\", \"cargo\"]  # topgrade.d/a.toml, topgrade.toml\n"),
+            "{output}"
+        );
+        assert!(output.contains("\n[commands]\n"), "{output}");
+        assert!(
+            output.contains("\"Update foo\" = \"foo -u\"  # topgrade.d/a.toml\n"),
+            "{output}"
+        );
+        assert!(output.contains("bar = \"bar\"  # topgrade.toml\n"), "{output}");
+
+        // The result can be used as a configuration file itself.
+        toml::from_str::<ConfigFile>(&output).unwrap();
+    }
+}
@@ -70,6 +70,16 @@ impl Display for StepFailed {
     }
 }
 
+/// Not an error as such, `--check` found updates.
+#[derive(Error, Debug)]
+pub struct UpdatesAvailable;
+
+impl Display for UpdatesAvailable {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", t!("Updates are available"))
+    }
+}
+
 #[derive(Error, Debug)]
 pub struct UnsupportedSudo<'a> {
     pub sudo_kind: SudoKind,
@@ -2,7 +2,7 @@
 use std::env::var;
 use std::ffi::OsStr;
 use std::process::Command;
-use std::sync::{Mutex, OnceLock};
+use std::sync::{Arc, Mutex, OnceLock};
 
 use clap::ValueEnum;
 use color_eyre::eyre::Result;
@@ -12,7 +12,9 @@ use strum::EnumString;
 use crate::config::Config;
 use crate::error::{MissingSudo, SkipStep};
 use crate::executor::{DryCommand, Executor};
+use crate::fixture::{Invocation, Replay, ReplayCommand};
 use crate::powershell::Powershell;
+use crate::step_config;
 #[cfg(target_os = "linux")]
 use crate::steps::linux::Distribution;
 use crate::sudo::Sudo;
@@ -29,6 +31,10 @@ pub enum RunType {
 
     /// Executing commands will print the command and perform actual execution.
     Damp,
+
+    /// Nothing is changed: steps that support it list the updates they would
+    /// install, the other steps are skipped.
+    Check,
 }
 
 impl RunType {
@@ -38,16 +44,22 @@ impl RunType {
             RunType::Dry => true,
             RunType::Wet => false,
             RunType::Damp => false,
+            RunType::Check => true,
         }
     }
 
+    /// Tells whether we're only checking for updates.
+    pub fn check(self) -> bool {
+        matches!(self, RunType::Check)
+    }
+
     /// Create an `Executor` for the given program using this run type.
     #[expect(clippy::disallowed_methods)]
     pub fn execute<S: AsRef<OsStr>>(self, program: S) -> Executor {
         match self {
-            RunType::Dry => Executor::Dry(DryCommand::new(program)),
-            RunType::Wet => Executor::Wet(Command::new(program)),
-            RunType::Damp => Executor::Damp(Command::new(program)),
+            RunType::Dry | RunType::Check => Executor::Dry(DryCommand::new(program)),
+            RunType::Wet => Executor::Wet(Command::new(program).into()),
+            RunType::Damp => Executor::Damp(Command::new(program).into()),
         }
     }
 }
@@ -65,6 +77,8 @@ pub struct ExecutionContext<'a> {
     #[cfg(target_os = "linux")]
     distribution: &'a Result<Distribution>,
     powershell: OnceLock<Result<Powershell, SkipStep>>,
+    /// Fixture to take the output of commands from instead of executing them.
+    replay: Option<Arc<Replay>>,
 }
 
 impl<'a> ExecutionContext<'a> {
@@ -84,12 +98,49 @@ impl<'a> ExecutionContext<'a> {
             #[cfg(target_os = "linux")]
             distribution,
             powershell: OnceLock::new(),
+            replay: None,
         }
     }
 
+    /// A context that takes the output of every command from `fixture`, for testing steps.
+    #[cfg(test)]
+    pub fn replaying(config: &'a Config, fixture: &str) -> Self {
+        #[cfg(target_os = "linux")]
+        let distribution = Box::leak(Box::new(Err(color_eyre::eyre::eyre!("Not detected in tests"))));
+        Self::new(
+            RunType::Wet,
+            None,
+            config,
+            #[cfg(target_os = "linux")]
+            distribution,
+        )
+        .with_replay(Replay::parse(fixture).expect("invalid fixture"))
+    }
+
+    /// Take the output of every command from `replay` instead of executing it.
+    pub fn with_replay(mut self, replay: Replay) -> Self {
+        self.replay = Some(Arc::new(replay));
+        self
+    }
+
     /// Create an instance of `Executor` that should run `program`.
     pub fn execute<S: AsRef<OsStr>>(&self, program: S) -> Executor {
-        self.run_type.execute(program)
+        let mut executor = match &self.replay {
+            Some(replay) => Executor::Replay(ReplayCommand {
+                replay: Arc::clone(replay),
+                invocation: Invocation::new(&program.as_ref().to_string_lossy()),
+            }),
+            None => self.run_type.execute(program),
+        };
+        if let Some(step) = step_config::current_step() {
+            for (key, value) in self.config.step_env(step) {
+                executor.env(key, value);
+            }
+            if let Some(dir) = self.config.step_working_dir(step) {
+                executor.current_dir(dir);
+            }
+        }
+        executor
     }
 
     pub fn run_type(&self) -> RunType {
@@ -140,3 +191,77 @@ impl<'a> ExecutionContext<'a> {
             .map_err(|skip| SkipStep(skip.0.clone()).into())
     }
 }
+
+#[cfg(all(test, unix))]
+mod test {
+    use super::*;
+    use crate::command::CommandExt;
+    use crate::config::test::config_from_toml;
+    use crate::step::Step;
+
+    #[test]
+    fn test_step_env_and_working_dir() {
+        let config = config_from_toml(
+            r#"
+[steps.tldr]
+env = { TOPGRADE_TEST_VAR = "scoped" }
+working_dir = "/"
+"#,
+        );
+        #[cfg(target_os = "linux")]
+        let distribution = Err(color_eyre::eyre::eyre!("Not detected in tests"));
+        let ctx = ExecutionContext::new(
+            RunType::Wet,
+            None,
+            &config,
+            #[cfg(target_os = "linux")]
+            &distribution,
+        );
+        let run = || {
+            ctx.execute("sh")
+                .args(["-c", "echo \"${TOPGRADE_TEST_VAR:-unset} $PWD\""])
+                .output_checked_utf8()
+                .unwrap()
+                .stdout
+        };
+
+        assert_eq!(step_config::run(Step::Tldr, &[], run), "scoped /\n");
+        assert_ne!(step_config::run(Step::Cargo, &[], run), "scoped /\n");
+        assert!(run().starts_with("unset "));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_extra_args_are_only_appended_on_request() {
+        let config = config_from_toml("");
+        #[cfg(target_os = "linux")]
+        let distribution = Err(color_eyre::eyre::eyre!("Not detected in tests"));
+        let ctx = ExecutionContext::new(
+            RunType::Wet,
+            None,
+            &config,
+            #[cfg(target_os = "linux")]
+            &distribution,
+        );
+        let dir = tempfile::tempdir().unwrap();
+        let args = dir.path().join("args");
+        // Like a custom command, whose arguments the extra ones would end up in.
+        let run = |extra_args: bool| {
+            let mut exec = ctx.execute("sh");
+            exec.args(["-c", r#"echo "$@" > "$0""#]).arg(&args).arg("update");
+            if extra_args {
+                exec.extra_args();
+            }
+            exec.status_checked().unwrap();
+            std::fs::read_to_string(&args).unwrap()
+        };
+
+        let extra_args = [String::from("--verbose")];
+        assert_eq!(step_config::run(Step::Tldr, &extra_args, || run(false)), "update\n");
+        assert_eq!(
+            step_config::run(Step::Tldr, &extra_args, || run(true)),
+            "update --verbose\n"
+        );
+        assert_eq!(run(true), "update\n");
+    }
+}
@@ -1,24 +1,36 @@
 //! Utilities for command execution
 use std::ffi::{OsStr, OsString};
 use std::fmt::Debug;
+use std::io;
 use std::iter;
+use std::ops::{Deref, DerefMut};
 use std::path::Path;
-use std::process::{Child, Command, ExitStatus, Output, Stdio};
+use std::process::{Command, ExitStatus, Output, Stdio};
 
 use color_eyre::eyre::Result;
 use rust_i18n::t;
 use tracing::{Level, debug, enabled};
 
-use crate::command::CommandExt;
+use crate::command::{self, CommandExt};
 use crate::error::DryRun;
+use crate::fixture::{self, Invocation, ReplayCommand};
+use crate::parallel;
+use crate::plan;
+use crate::step_config;
+use crate::step_log::{self, Streams, Tee};
+use crate::sudo::SudoKind;
+use crate::terminal::print_line;
+use crate::watchdog::{self, WatchedChild};
 
 /// An enum providing a similar interface to `std::process::Command`.
 /// If the enum is set to `Wet`, execution will be performed with `std::process::Command`.
 /// If the enum is set to `Dry`, execution will just print the command with its arguments.
+/// If the enum is set to `Replay`, the output of the command is taken from a fixture.
 pub enum Executor {
-    Wet(Command),
-    Damp(Command),
+    Wet(WetCommand),
+    Damp(WetCommand),
     Dry(DryCommand),
+    Replay(ReplayCommand),
 }
 
 impl Executor {
@@ -27,11 +39,16 @@ impl Executor {
     /// Use this for read-only commands that detect environment, check versions, or query
     /// configuration. These commands need to run even during dry-run to make correct decisions.
     ///
-    /// This converts `Dry` to `Wet` (which executes), while leaving `Wet` and
-    /// `Damp` unchanged.
+    /// This converts `Dry` to `Wet` (which executes), while leaving `Wet`,
+    /// `Damp` and `Replay` unchanged.
     pub fn always(self) -> Self {
         match self {
-            Executor::Dry(c) => Executor::Wet(c.into_command()),
+            Executor::Dry(c) => {
+                let stdin_set = c.stdin.is_some();
+                let mut command = WetCommand::from(c.into_command());
+                command.stdin_set = stdin_set;
+                Executor::Wet(command)
+            }
             other => other,
         }
     }
@@ -43,6 +60,7 @@ impl Executor {
         match self {
             Executor::Wet(c) | Executor::Damp(c) => c.get_program().to_string_lossy().into_owned(),
             Executor::Dry(c) => c.program.to_string_lossy().into_owned(),
+            Executor::Replay(c) => c.invocation.program.clone(),
         }
     }
 
@@ -55,6 +73,9 @@ impl Executor {
             Executor::Dry(c) => {
                 c.args.push(arg.as_ref().into());
             }
+            Executor::Replay(c) => {
+                c.invocation.args.push(arg.as_ref().to_string_lossy().into_owned());
+            }
         }
 
         self
@@ -73,11 +94,23 @@ impl Executor {
             Executor::Dry(c) => {
                 c.args.extend(args.into_iter().map(|arg| arg.as_ref().into()));
             }
+            Executor::Replay(c) => {
+                c.invocation
+                    .args
+                    .extend(args.into_iter().map(|arg| arg.as_ref().to_string_lossy().into_owned()));
+            }
         }
 
         self
     }
 
+    /// Append the `extra_args` of the running step, see `[steps.<step>]`.
+    ///
+    /// Steps call this on the command that does their update, after its other arguments.
+    pub fn extra_args(&mut self) -> &mut Executor {
+        self.args(step_config::extra_args())
+    }
+
     #[allow(dead_code)]
     /// See `std::process::Command::current_dir`
     pub fn current_dir<P: AsRef<Path>>(&mut self, dir: P) -> &mut Executor {
@@ -86,6 +119,7 @@ impl Executor {
                 c.current_dir(dir);
             }
             Executor::Dry(c) => c.directory = Some(dir.as_ref().into()),
+            Executor::Replay(c) => c.invocation.cwd = Some(dir.as_ref().to_string_lossy().into_owned()),
         }
 
         self
@@ -98,10 +132,26 @@ impl Executor {
         match self {
             Executor::Wet(c) | Executor::Damp(c) => {
                 c.stdin(stdio);
+                c.stdin_set = true;
             }
             Executor::Dry(c) => {
                 c.stdin = Some(stdio);
             }
+            Executor::Replay(_) => (),
+        }
+
+        self
+    }
+
+    /// See `std::process::Command::stdout`
+    pub fn stdout<T: Into<Stdio>>(&mut self, cfg: T) -> &mut Executor {
+        let stdio = cfg.into();
+        match self {
+            Executor::Wet(c) | Executor::Damp(c) => {
+                c.stdout(stdio);
+                c.stdout_set = true;
+            }
+            Executor::Dry(_) | Executor::Replay(_) => (),
         }
 
         self
@@ -120,6 +170,9 @@ impl Executor {
             Executor::Dry(c) => {
                 c.env_removals.push(key.as_ref().to_os_string());
             }
+            Executor::Replay(c) => {
+                c.invocation.env.shift_remove(&*key.as_ref().to_string_lossy());
+            }
         }
 
         self
@@ -139,6 +192,12 @@ impl Executor {
             Executor::Dry(c) => {
                 c.envs.push((key.as_ref().to_os_string(), val.as_ref().to_os_string()));
             }
+            Executor::Replay(c) => {
+                c.invocation.env.insert(
+                    key.as_ref().to_string_lossy().into_owned(),
+                    val.as_ref().to_string_lossy().into_owned(),
+                );
+            }
         }
 
         self
@@ -149,13 +208,26 @@ impl Executor {
         self.log_command();
         let result = match self {
             Executor::Wet(c) | Executor::Damp(c) => {
-                debug!("Running {:?}", c);
+                debug!("Running {:?}", c.command);
                 // We should use `spawn()` here rather than `spawn_checked()` since
                 // their semantics and behaviors are different.
+                let inherited = c.inherited();
+                let teed = step_log::prepare(c, inherited);
+                c.capture_in_lane(inherited, teed);
                 #[expect(clippy::disallowed_methods)]
-                c.spawn().map(ExecutorChild::Wet)?
+                let mut child = c.spawn()?;
+                let tee = Tee::start(&mut child, teed);
+                ExecutorChild::Wet(WetChild {
+                    child: watchdog::watch(child),
+                    tee: Some(tee),
+                })
             }
             Executor::Dry(_) => ExecutorChild::Dry,
+            // There is no process to wait for when replaying.
+            Executor::Replay(c) => {
+                c.spawn_checked()?;
+                ExecutorChild::Dry
+            }
         };
 
         Ok(result)
@@ -166,12 +238,15 @@ impl Executor {
         self.log_command();
         match self {
             Executor::Wet(c) | Executor::Damp(c) => {
-                // We should use `output()` here rather than `output_checked()` since
+                // We should use `output_watched()` here rather than `output_checked()` since
                 // their semantics and behaviors are different.
-                #[expect(clippy::disallowed_methods)]
-                Ok(ExecutorOutput::Wet(c.output()?))
+                let (inherited, stdin_set) = (c.inherited(), c.stdin_set);
+                let output = command::output_watched(c, inherited, stdin_set)?;
+                fixture::record(&Invocation::of(c), &output);
+                Ok(ExecutorOutput::Wet(output))
             }
             Executor::Dry(_) => Ok(ExecutorOutput::Dry),
+            Executor::Replay(c) => Ok(ExecutorOutput::Wet(c.replay.output(&c.invocation)?)),
         }
     }
 
@@ -179,22 +254,27 @@ impl Executor {
     /// that can indicate success of a script
     #[allow(dead_code)]
     pub fn status_checked_with_codes(&mut self, codes: &[i32]) -> Result<()> {
-        self.log_command();
-        match self {
-            Executor::Wet(c) | Executor::Damp(c) => c.status_checked_with(|status| {
-                if status.success() || status.code().as_ref().is_some_and(|c| codes.contains(c)) {
-                    Ok(())
-                } else {
-                    Err(())
-                }
-            }),
-            Executor::Dry(_) => Ok(()),
+        self.status_checked_with(|status| {
+            if status.success() || status.code().as_ref().is_some_and(|c| codes.contains(c)) {
+                Ok(())
+            } else {
+                Err(())
+            }
+        })
+    }
+
+    /// Mark this command as running through `kind`, for the plan of a dry run.
+    pub fn elevated_with(mut self, kind: SudoKind) -> Self {
+        if let Executor::Dry(c) = &mut self {
+            c.sudo = Some(kind);
         }
+
+        self
     }
 
     fn log_command(&self) {
         match self {
-            Executor::Wet(_) => (),
+            Executor::Wet(_) | Executor::Replay(_) => (),
             Executor::Damp(c) => {
                 log_command(
                     "Executing: {program_name} {arguments}",
@@ -204,17 +284,89 @@ impl Executor {
                     c.get_current_dir(),
                 );
             }
-            Executor::Dry(c) => log_command(
-                "Dry running: {program_name} {arguments}",
-                &c.program,
-                &c.args,
-                iter::empty(),
-                c.directory.as_ref(),
-            ),
+            Executor::Dry(c) => {
+                log_command(
+                    "Dry running: {program_name} {arguments}",
+                    &c.program,
+                    &c.args,
+                    iter::empty(),
+                    c.directory.as_ref(),
+                );
+                plan::record(
+                    &c.program,
+                    &c.args,
+                    c.directory.as_deref(),
+                    &c.envs,
+                    &c.env_removals,
+                    c.sudo,
+                );
+            }
         }
     }
 }
 
+/// The command of a `Wet` or `Damp` executor.
+pub struct WetCommand {
+    command: Command,
+    /// Whether stdin was redirected with `Executor::stdin`.
+    stdin_set: bool,
+    /// Whether stdout was redirected with `Executor::stdout`.
+    stdout_set: bool,
+}
+
+impl WetCommand {
+    /// The streams that are left for the command to inherit.
+    fn inherited(&self) -> Streams {
+        Streams {
+            stdout: !self.stdout_set,
+            stderr: true,
+        }
+    }
+
+    /// Steps running in parallel can't share the terminal, their output is collected
+    /// and printed when they finish, and there's no one to answer prompts.
+    ///
+    /// Sends the `inherited` streams of a command started in a parallel lane to its capture.
+    /// The `teed` ones are copied there by `Tee`.
+    fn capture_in_lane(&mut self, inherited: Streams, teed: Streams) {
+        if let Some((stdout, stderr)) = parallel::captured_stdio() {
+            if inherited.stdout && !teed.stdout {
+                self.stdout(stdout);
+            }
+            if inherited.stderr && !teed.stderr {
+                self.stderr(stderr);
+            }
+            if !self.stdin_set {
+                self.stdin(Stdio::null());
+            }
+        }
+    }
+}
+
+impl From<Command> for WetCommand {
+    fn from(command: Command) -> Self {
+        Self {
+            command,
+            stdin_set: false,
+            stdout_set: false,
+        }
+    }
+}
+
+impl Deref for WetCommand {
+    type Target = Command;
+
+    fn deref(&self) -> &Command {
+        &self.command
+    }
+}
+
+impl DerefMut for WetCommand {
+    fn deref_mut(&mut self) -> &mut Command {
+        &mut self.command
+    }
+}
+
 pub enum ExecutorOutput {
     Wet(Output),
     Dry,
@@ -228,6 +380,7 @@ pub struct DryCommand {
     envs: Vec<(OsString, OsString)>,
     env_removals: Vec<OsString>,
     stdin: Option<Stdio>,
+    sudo: Option<SudoKind>,
 }
 
 impl DryCommand {
@@ -239,6 +392,7 @@ impl DryCommand {
             envs: Vec::new(),
             env_removals: Vec::new(),
             stdin: None,
+            sudo: None,
         }
     }
 
@@ -266,10 +420,41 @@ impl DryCommand {
 /// The Result of spawn. Contains an actual `std::process::Child` if executed by a wet command.
 pub enum ExecutorChild {
     // Both RunType::Wet and RunType::Damp use this variant
-    Wet(Child),
+    Wet(WetChild),
     Dry,
 }
 
+/// The process started by a `Wet` or `Damp` executor, with the copying of its output into the step log.
+pub struct WetChild {
+    child: WatchedChild,
+    tee: Option<Tee>,
+}
+
+impl WetChild {
+    /// Like [`Child::wait`](std::process::Child::wait), also waiting until all of its output is in the step log.
+    pub fn wait(&mut self) -> io::Result<ExitStatus> {
+        let status = self.child.wait();
+        if let Some(tee) = self.tee.take() {
+            tee.finish();
+        }
+        status
+    }
+}
+
+impl Deref for WetChild {
+    type Target = WatchedChild;
+
+    fn deref(&self) -> &WatchedChild {
+        &self.child
+    }
+}
+
+impl DerefMut for WetChild {
+    fn deref_mut(&mut self) -> &mut WatchedChild {
+        &mut self.child
+    }
+}
+
 impl CommandExt for Executor {
     type Child = ExecutorChild;
 
@@ -279,16 +464,39 @@ impl CommandExt for Executor {
     fn output_checked_with(&mut self, succeeded: impl Fn(&Output) -> Result<(), ()>) -> Result<Output> {
         self.log_command();
         match self {
-            Executor::Wet(c) | Executor::Damp(c) => c.output_checked_with(succeeded),
+            Executor::Wet(c) | Executor::Damp(c) => {
+                let invocation = Invocation::of(c);
+                let (inherited, stdin_set) = (c.inherited(), c.stdin_set);
+                command::output_checked_captured(c, inherited, stdin_set, |output| {
+                    fixture::record(&invocation, output);
+                    succeeded(output)
+                })
+            }
             Executor::Dry(_) => Err(DryRun().into()),
+            Executor::Replay(c) => c.output_checked_with(succeeded),
         }
     }
 
     fn status_checked_with(&mut self, succeeded: impl Fn(ExitStatus) -> Result<(), ()>) -> Result<()> {
         self.log_command();
         match self {
-            Executor::Wet(c) | Executor::Damp(c) => c.status_checked_with(succeeded),
+            Executor::Wet(c) | Executor::Damp(c) => {
+                let inherited = c.inherited();
+                let teed = step_log::prepare(c, inherited);
+                c.capture_in_lane(inherited, teed);
+                let invocation = Invocation::of(c);
+                command::status_checked_teed(c, teed, |status| {
+                    let output = Output {
+                        status,
+                        stdout: Vec::new(),
+                        stderr: Vec::new(),
+                    };
+                    fixture::record(&invocation, &output);
+                    succeeded(status)
+                })
+            }
             Executor::Dry(_) => Ok(()),
+            Executor::Replay(c) => c.status_checked_with(succeeded),
         }
     }
 
@@ -307,18 +515,15 @@ fn log_command<
     env: impl IntoIterator<Item = (&'a OsStr, Option<&'a OsStr>), IntoIter = I>,
     dir: Option<&'a (impl AsRef<Path> + ?Sized)>,
 ) {
-    println!(
-        "{}",
-        t!(
-            prefix,
-            program_name = exec.to_string_lossy(),
-            arguments = shell_words::join(args.into_iter().map(|s| s.as_ref().to_string_lossy()))
-        )
-    );
+    print_line(t!(
+        prefix,
+        program_name = exec.to_string_lossy(),
+        arguments = shell_words::join(args.into_iter().map(|s| s.as_ref().to_string_lossy()))
+    ));
 
     let env_iter = env.into_iter();
     if env_iter.len() != 0 && enabled!(Level::DEBUG) {
-        println!(
+        print_line(format!(
             "  {}",
             t!(
                 "with env: {env}",
@@ -328,11 +533,11 @@ fn log_command<
                     .collect::<Vec<_>>()
                     .join(" ")
             )
-        )
+        ))
     }
 
     if let Some(d) = dir {
-        println!("  {}", t!("in {directory}", directory = d.as_ref().display()));
+        print_line(format!("  {}", t!("in {directory}", directory = d.as_ref().display())));
     }
 }
 
@@ -433,4 +638,26 @@ mod test {
             ExecutorOutput::Dry => panic!("Expected Wet output after .always()"),
         }
     }
+
+    /// Test that the output of a command spawned in a parallel lane goes to the capture of the lane.
+    #[cfg(unix)]
+    #[test]
+    fn test_spawned_output_is_captured() {
+        #[expect(clippy::disallowed_methods)]
+        let mut executor = Executor::Wet(Command::new("sh").into());
+        executor.args(["-c", "echo out; echo err >&2"]);
+
+        let (status, output) = parallel::capture(|| {
+            let ExecutorChild::Wet(mut child) = executor.spawn().unwrap() else {
+                panic!("Expected a Wet child");
+            };
+            child.wait().unwrap()
+        })
+        .unwrap();
+
+        assert!(status.success());
+        let output = String::from_utf8_lossy(&output);
+        assert!(output.contains("out\n"), "got '{output}'");
+        assert!(output.contains("err\n"), "got '{output}'");
+    }
 }
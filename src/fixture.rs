@@ -0,0 +1,392 @@
+//! Recording commands to fixture files and replaying them.
+//!
+//! A fixture is a TOML file mapping command lines to their stdout, stderr and
+//! exit status:
+//!
+//! ```toml
+//! [[commands]]
+//! command = "docker image ls --format '{{.Repository}}:{{.Tag}} {{.ID}}'"
+//! stdout = "alpine:latest 9c6f07244728\n"
+//! status = 0
+//! ```
+//!
+//! The working directory and the environment variables a command was run with
+//! are recorded too, as `cwd` and `env`. A command of the fixture that has them
+//! only matches commands run in that directory and with those variables.
+//!
+//! With `--record-fixture`, the commands run through an `Executor` are saved
+//! to such a file. With `--replay-fixture`, or a `Replay` set on the
+//! `ExecutionContext` in tests, no command is executed and its output is taken
+//! from the fixture instead, so steps can be run without the tools installed.
+
+use std::fs;
+use std::path::Path;
+use std::process::{Command, ExitStatus, Output};
+use std::sync::{Arc, Mutex};
+
+use color_eyre::eyre::{Context, Result, eyre};
+use indexmap::IndexMap;
+use serde::{Deserialize, Serialize};
+
+use crate::command::CommandExt;
+use crate::error::TopgradeError;
+
+/// A command line and what running it produced.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct FixtureCommand {
+    /// The program and its arguments, quoted like in a shell.
+    ///
+    /// The program can be a path or only its file name.
+    pub command: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cwd: Option<String>,
+    /// Environment variables set for the command, on top of Topgrade's own environment.
+    #[serde(default, skip_serializing_if = "IndexMap::is_empty")]
+    pub env: IndexMap<String, String>,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub stdout: String,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub stderr: String,
+    #[serde(default)]
+    pub status: i32,
+}
+
+#[derive(Debug, Default, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+struct Fixture {
+    #[serde(default)]
+    commands: Vec<FixtureCommand>,
+}
+
+/// Replays the commands of a fixture.
+#[derive(Debug)]
+pub struct Replay {
+    commands: Vec<(Vec<String>, FixtureCommand)>,
+    /// Which commands were replayed already.
+    replayed: Mutex<Vec<bool>>,
+}
+
+impl Replay {
+    /// Read the fixture at `path`.
+    pub fn load(path: &Path) -> Result<Self> {
+        let contents =
+            fs::read_to_string(path).with_context(|| format!("Failed to read the fixture {}", path.display()))?;
+        Self::parse(&contents).with_context(|| format!("Failed to parse the fixture {}", path.display()))
+    }
+
+    /// Parse the contents of a fixture.
+    pub fn parse(contents: &str) -> Result<Self> {
+        let fixture: Fixture = toml::from_str(contents)?;
+        let commands = fixture
+            .commands
+            .into_iter()
+            .map(|command| {
+                let words = shell_words::split(&command.command)
+                    .with_context(|| format!("Invalid command line `{}`", command.command))?;
+                if words.is_empty() {
+                    return Err(eyre!("Empty command line"));
+                }
+                Ok((words, command))
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(Self {
+            replayed: Mutex::new(vec![false; commands.len()]),
+            commands,
+        })
+    }
+
+    /// The output of running `invocation`.
+    ///
+    /// When several commands of the fixture match, they are replayed in order,
+    /// and the last one is repeated once all of them were replayed.
+    pub fn output(&self, invocation: &Invocation) -> Result<Output> {
+        let matching: Vec<usize> = self
+            .commands
+            .iter()
+            .enumerate()
+            .filter(|(_, (words, command))| matches(words, command, invocation))
+            .map(|(index, _)| index)
+            .collect();
+
+        let mut replayed = self.replayed.lock().unwrap();
+        let index = matching
+            .iter()
+            .copied()
+            .find(|index| !replayed[*index])
+            .or_else(|| matching.last().copied())
+            .ok_or_else(|| eyre!("The fixture has no output for `{}`", invocation.command_line()))?;
+        replayed[index] = true;
+
+        let command = &self.commands[index].1;
+        Ok(Output {
+            status: exit_status(command.status),
+            stdout: command.stdout.clone().into_bytes(),
+            stderr: command.stderr.clone().into_bytes(),
+        })
+    }
+}
+
+fn matches(words: &[String], command: &FixtureCommand, invocation: &Invocation) -> bool {
+    let (fixture_program, fixture_args) = words.split_first().unwrap();
+    let program = &invocation.program;
+    let file_name = Path::new(program).file_name().and_then(|name| name.to_str());
+    (fixture_program == program || Some(fixture_program.as_str()) == file_name)
+        && *fixture_args == invocation.args
+        && command
+            .cwd
+            .as_ref()
+            .is_none_or(|cwd| invocation.cwd.as_ref() == Some(cwd))
+        && command
+            .env
+            .iter()
+            .all(|(key, value)| invocation.env.get(key) == Some(value))
+}
+
+fn command_line(program: &str, args: &[String]) -> String {
+    shell_words::join(std::iter::once(program).chain(args.iter().map(String::as_str)))
+}
+
+#[cfg(unix)]
+fn exit_status(code: i32) -> ExitStatus {
+    use std::os::unix::process::ExitStatusExt;
+    ExitStatus::from_raw(code << 8)
+}
+
+#[cfg(windows)]
+fn exit_status(code: i32) -> ExitStatus {
+    use std::os::windows::process::ExitStatusExt;
+    ExitStatus::from_raw(code as u32)
+}
+
+/// A command as it is recorded and matched against a fixture.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Invocation {
+    pub program: String,
+    pub args: Vec<String>,
+    pub cwd: Option<String>,
+    /// The environment variables set for the command.
+    pub env: IndexMap<String, String>,
+}
+
+impl Invocation {
+    pub fn new(program: &str) -> Self {
+        Self {
+            program: program.to_string(),
+            ..Self::default()
+        }
+    }
+
+    /// How `command` is run.
+    pub fn of(command: &Command) -> Self {
+        let lossy = |value: &std::ffi::OsStr| value.to_string_lossy().into_owned();
+        Self {
+            program: lossy(command.get_program()),
+            args: command.get_args().map(lossy).collect(),
+            cwd: command.get_current_dir().map(|dir| lossy(dir.as_os_str())),
+            env: command
+                .get_envs()
+                .filter_map(|(key, value)| Some((lossy(key), lossy(value?))))
+                .collect(),
+        }
+    }
+
+    fn command_line(&self) -> String {
+        command_line(&self.program, &self.args)
+    }
+}
+
+/// A replayed command, used by `Executor::Replay`.
+pub struct ReplayCommand {
+    pub replay: Arc<Replay>,
+    pub invocation: Invocation,
+}
+
+impl CommandExt for ReplayCommand {
+    type Child = ();
+
+    fn output_checked_with(&mut self, succeeded: impl Fn(&Output) -> Result<(), ()>) -> Result<Output> {
+        let output = self.replay.output(&self.invocation)?;
+        if succeeded(&output).is_ok() {
+            return Ok(output);
+        }
+
+        let stderr = String::from_utf8_lossy(&output.stderr).into_owned();
+        let err = TopgradeError::ProcessFailedWithOutput(self.invocation.program.clone(), output.status, stderr);
+        Err(err).with_context(|| format!("Command failed: `{}`", self.invocation.command_line()))
+    }
+
+    fn status_checked_with(&mut self, succeeded: impl Fn(ExitStatus) -> Result<(), ()>) -> Result<()> {
+        let output = self.replay.output(&self.invocation)?;
+        if succeeded(output.status).is_ok() {
+            return Ok(());
+        }
+
+        let err = TopgradeError::ProcessFailed(self.invocation.program.clone(), output.status);
+        Err(err).with_context(|| format!("Command failed: `{}`", self.invocation.command_line()))
+    }
+
+    fn spawn_checked(&mut self) -> Result<Self::Child> {
+        self.replay.output(&self.invocation).map(|_| ())
+    }
+}
+
+/// The commands recorded so far, if recording was requested.
+static RECORDING: Mutex<Option<Vec<FixtureCommand>>> = Mutex::new(None);
+
+/// Start recording the commands of this run.
+pub fn enable_recording() {
+    RECORDING.lock().unwrap().get_or_insert_with(Vec::new);
+}
+
+/// Record a command that was executed.
+///
+/// Programs given as a path are recorded with their file name only, so the
+/// fixture can be replayed on machines where they are installed elsewhere.
+pub fn record(invocation: &Invocation, output: &Output) {
+    if let Some(recording) = RECORDING.lock().unwrap().as_mut() {
+        let program = &invocation.program;
+        let program = Path::new(program)
+            .file_name()
+            .and_then(|name| name.to_str())
+            .unwrap_or(program);
+        recording.push(FixtureCommand {
+            command: command_line(program, &invocation.args),
+            cwd: invocation.cwd.clone(),
+            env: invocation.env.clone(),
+            stdout: String::from_utf8_lossy(&output.stdout).into_owned(),
+            stderr: String::from_utf8_lossy(&output.stderr).into_owned(),
+            status: output.status.code().unwrap_or(-1),
+        });
+    }
+}
+
+/// Write the recorded commands to `path`.
+pub fn write_recording(path: &Path) -> Result<()> {
+    let recording = RECORDING.lock().unwrap();
+    let fixture = Fixture {
+        commands: recording.clone().unwrap_or_default(),
+    };
+    let contents = toml::to_string(&fixture).context("Failed to serialize the fixture")?;
+    fs::write(path, contents).with_context(|| format!("Failed to write the fixture to {}", path.display()))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const FIXTURE: &str = r#"
+[[commands]]
+command = "/usr/bin/flatpak --version"
+stdout = "Flatpak 1.14.4\n"
+
+[[commands]]
+command = "apt list --upgradable"
+stdout = "first\n"
+
+[[commands]]
+command = "apt list --upgradable"
+stdout = "second\n"
+
+[[commands]]
+command = "false"
+stderr = "failed\n"
+status = 1
+"#;
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(ToString::to_string).collect()
+    }
+
+    fn invocation(program: &str, arguments: &[&str]) -> Invocation {
+        Invocation {
+            args: args(arguments),
+            ..Invocation::new(program)
+        }
+    }
+
+    #[test]
+    fn test_replay_matches_program_and_args() {
+        let replay = Replay::parse(FIXTURE).unwrap();
+
+        let output = replay.output(&invocation("/usr/bin/flatpak", &["--version"])).unwrap();
+        assert!(output.status.success());
+        assert_eq!(output.stdout, b"Flatpak 1.14.4\n");
+
+        // A program given by its file name in the fixture matches any path.
+        let output = replay
+            .output(&invocation("/usr/local/bin/apt", &["list", "--upgradable"]))
+            .unwrap();
+        assert_eq!(output.stdout, b"first\n");
+        let output = replay.output(&invocation("apt", &["list", "--upgradable"])).unwrap();
+        assert_eq!(output.stdout, b"second\n");
+        let output = replay.output(&invocation("apt", &["list", "--upgradable"])).unwrap();
+        assert_eq!(output.stdout, b"second\n");
+
+        // A program given by its path only matches that path.
+        assert!(replay.output(&invocation("flatpak", &["--version"])).is_err());
+        assert!(replay.output(&invocation("apt", &["list"])).is_err());
+    }
+
+    #[test]
+    fn test_replay_command_checks_status() {
+        let mut command = ReplayCommand {
+            replay: Arc::new(Replay::parse(FIXTURE).unwrap()),
+            invocation: Invocation::new("false"),
+        };
+
+        assert_eq!(command.output_checked_with(|_| Ok(())).unwrap().status.code(), Some(1));
+        let err = command.output_checked().unwrap_err();
+        assert!(format!("{err:?}").contains("Command failed: `false`"), "{err:?}");
+        assert!(command.status_checked().is_err());
+        command
+            .status_checked_with(|status| if status.code() == Some(1) { Ok(()) } else { Err(()) })
+            .unwrap();
+    }
+
+    #[test]
+    fn test_recording_round_trips() {
+        let fixture = Fixture {
+            commands: vec![FixtureCommand {
+                command: command_line("docker", &args(&["image", "ls", "--format", "{{.ID}}"])),
+                cwd: Some(String::from("/srv")),
+                env: IndexMap::from([(String::from("DOCKER_HOST"), String::from("unix:///run/docker.sock"))]),
+                stdout: String::from("a\nb\n"),
+                stderr: String::new(),
+                status: 0,
+            }],
+        };
+        let replay = Replay::parse(&toml::to_string(&fixture).unwrap()).unwrap();
+        let mut docker = invocation("/usr/bin/docker", &["image", "ls", "--format", "{{.ID}}"]);
+        docker.cwd = Some(String::from("/srv"));
+        docker
+            .env
+            .insert(String::from("DOCKER_HOST"), String::from("unix:///run/docker.sock"));
+        assert_eq!(replay.output(&docker).unwrap().stdout, b"a\nb\n");
+    }
+
+    #[test]
+    fn test_replay_matches_cwd_and_env() {
+        let replay = Replay::parse(
+            r#"
+[[commands]]
+command = "git pull"
+cwd = "/src/topgrade"
+env = { GIT_TERMINAL_PROMPT = "0" }
+"#,
+        )
+        .unwrap();
+
+        let mut git = invocation("git", &["pull"]);
+        assert!(replay.output(&git).is_err());
+        git.cwd = Some(String::from("/src/topgrade"));
+        assert!(replay.output(&git).is_err());
+        git.env.insert(String::from("GIT_TERMINAL_PROMPT"), String::from("1"));
+        assert!(replay.output(&git).is_err());
+        git.env.insert(String::from("GIT_TERMINAL_PROMPT"), String::from("0"));
+        // Variables the fixture doesn't mention don't matter.
+        git.env.insert(String::from("LANG"), String::from("C"));
+        assert!(replay.output(&git).unwrap().status.success());
+    }
+}
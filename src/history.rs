@@ -0,0 +1,217 @@
+//! A local history of Topgrade runs.
+//!
+//! The report of every run is appended as one line of JSON to a history file
+//! in Topgrade's data directory, which `topgrade history` reads back. This
+//! makes it possible to find out when a step that used to succeed started
+//! failing.
+
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use color_eyre::eyre::{Context, Result, bail};
+use console::style;
+use rust_i18n::t;
+use tempfile::NamedTempFile;
+use tracing::{debug, warn};
+
+use crate::breaking_changes::topgrade_data_dir;
+use crate::report::RunReport;
+use crate::terminal::{format_duration, print_result, print_separator};
+
+/// Return the path of the history file.
+fn history_file_path() -> PathBuf {
+    topgrade_data_dir().join("history.jsonl")
+}
+
+/// Append `report` to the history, keeping only the `limit` most recent runs.
+pub fn record(report: &RunReport, limit: usize) -> Result<()> {
+    let path = history_file_path();
+    fs::create_dir_all(topgrade_data_dir())?;
+    record_to(&path, report, limit).with_context(|| format!("Failed to update the run history at {}", path.display()))
+}
+
+fn record_to(path: &Path, report: &RunReport, limit: usize) -> Result<()> {
+    let contents = if path.exists() {
+        fs::read_to_string(path)?
+    } else {
+        String::new()
+    };
+
+    let mut lines: Vec<String> = contents
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(String::from)
+        .collect();
+    lines.push(serde_json::to_string(report)?);
+    if lines.len() > limit {
+        lines.drain(..lines.len() - limit);
+    }
+
+    // Written next to the history and renamed over it, so an interrupted write leaves the old history.
+    let directory = path.parent().unwrap_or_else(|| Path::new("."));
+    let mut file = NamedTempFile::new_in(directory)?;
+    file.write_all((lines.join("\n") + "\n").as_bytes())?;
+    file.persist(path)?;
+    Ok(())
+}
+
+/// Load the recorded runs, oldest first.
+fn load_from(path: &Path) -> Result<Vec<RunReport<'static>>> {
+    if !path.exists() {
+        debug!("No history file at {}", path.display());
+        return Ok(Vec::new());
+    }
+
+    let contents =
+        fs::read_to_string(path).with_context(|| format!("Failed to read the run history at {}", path.display()))?;
+
+    Ok(contents
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .filter_map(|(number, line)| {
+            serde_json::from_str(line)
+                .inspect_err(|e| {
+                    warn!(
+                        "Ignoring invalid entry on line {} of {}: {e}",
+                        number + 1,
+                        path.display()
+                    )
+                })
+                .ok()
+        })
+        .collect())
+}
+
+/// Print the list of past runs, or the details of a single run.
+///
+/// Runs are numbered from the most recent one, which is run 1.
+pub fn show(run: Option<usize>) -> Result<()> {
+    let mut runs = load_from(&history_file_path())?;
+    runs.reverse();
+
+    if runs.is_empty() {
+        println!("{}", t!("No runs have been recorded yet"));
+        return Ok(());
+    }
+
+    match run {
+        None => {
+            for (index, run) in runs.iter().enumerate() {
+                print_run_line(index + 1, run);
+            }
+        }
+        Some(number) => {
+            let Some(run) = number.checked_sub(1).and_then(|index| runs.get(index)) else {
+                bail!("There is no run {number} in the history, there are {} runs", runs.len());
+            };
+            print_run_details(number, run);
+        }
+    }
+
+    Ok(())
+}
+
+fn print_run_line(number: usize, run: &RunReport) {
+    let failed: Vec<&str> = run
+        .steps
+        .iter()
+        .filter(|step| step.result.failed())
+        .map(|step| step.key.as_ref())
+        .collect();
+
+    let result = if failed.is_empty() {
+        format!("{}", style(t!("OK")).bold().green())
+    } else {
+        format!("{} ({})", style(t!("FAILED")).bold().red(), failed.join(", "))
+    };
+
+    println!(
+        "{:>3}  {}  {:<16}  {:>8}  {}",
+        number,
+        run.started.format("%Y-%m-%d %H:%M:%S"),
+        run.hostname.as_deref().unwrap_or("-"),
+        format_duration(run.duration()),
+        result
+    );
+}
+
+fn print_run_details(number: usize, run: &RunReport) {
+    print_separator(t!("Run {number}", number = number));
+    println!(
+        "{}",
+        t!(
+            "Topgrade {version} on {hostname}, started {started}",
+            version = run.topgrade_version,
+            hostname = run.hostname.as_deref().unwrap_or("-"),
+            started = run.started.format("%Y-%m-%d %H:%M:%S")
+        )
+    );
+    println!();
+
+    for step in run.steps.iter() {
+        print_result(step);
+        for error in &step.errors {
+            println!("    {}", style(error).dim());
+        }
+    }
+
+    println!(
+        "{}",
+        t!("Total time: {duration}", duration = format_duration(run.duration()))
+    );
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use chrono::Local;
+
+    use super::*;
+    use crate::runner::{StepReport, StepResult};
+    use crate::step::Step;
+
+    fn step_report(key: &'static str, result: StepResult) -> StepReport<'static> {
+        StepReport::for_test(Step::Cargo, key, result, Duration::from_secs(1))
+    }
+
+    #[test]
+    fn test_record_keeps_most_recent_runs() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("history.jsonl");
+
+        for key in ["first", "second", "third"] {
+            let steps = [step_report(key, StepResult::Success)];
+            record_to(&path, &RunReport::new(Local::now(), &steps), 2).unwrap();
+        }
+
+        let runs = load_from(&path).unwrap();
+        let keys: Vec<&str> = runs.iter().map(|run| run.steps[0].key.as_ref()).collect();
+        assert_eq!(keys, ["second", "third"]);
+        // No temporary files are left behind.
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn test_load_round_trips_results() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("history.jsonl");
+
+        let mut failed = step_report("cargo", StepResult::Failure);
+        failed.errors = vec!["exit status: 1".into()];
+        let steps = [failed, step_report("pipx", StepResult::Skipped("not installed".into()))];
+        record_to(&path, &RunReport::new(Local::now(), &steps), 10).unwrap();
+        // Garbage lines are skipped rather than making the whole history unreadable.
+        fs::write(&path, fs::read_to_string(&path).unwrap() + "not json\n").unwrap();
+
+        let runs = load_from(&path).unwrap();
+        assert_eq!(runs.len(), 1);
+        assert!(runs[0].failed);
+        assert!(matches!(runs[0].steps[0].result, StepResult::Failure));
+        assert_eq!(runs[0].steps[0].errors, ["exit status: 1"]);
+        assert!(matches!(&runs[0].steps[1].result, StepResult::Skipped(reason) if reason == "not installed"));
+        assert_eq!(runs[0].steps[1].duration, Duration::from_secs(1));
+    }
+}
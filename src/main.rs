@@ -20,8 +20,8 @@ use rust_i18n::{i18n, t};
 use std::sync::LazyLock;
 use tracing::debug;
 
-use self::config::{CommandLineArgs, Config};
-use self::error::StepFailed;
+use self::config::{CommandLineArgs, Config, TopgradeCommand};
+use self::error::{StepFailed, UpdatesAvailable};
 use self::runner::StepResult;
 use self::steps::{remote::*, *};
 use self::sudo::{Sudo, SudoCreateError, SudoKind};
@@ -31,22 +31,35 @@ use self::utils::{install_color_eyre, install_tracing, is_elevated, set_wsl_use_
 mod breaking_changes;
 mod command;
 mod config;
+mod config_check;
 mod ctrlc;
+mod effective_config;
 mod error;
 mod execution_context;
 mod executor;
+mod fixture;
+mod history;
+mod notify;
+mod parallel;
+mod plan;
+mod report;
+mod resume;
 mod runner;
+mod schedule;
 #[cfg(windows)]
 mod self_renamer;
 #[cfg(feature = "self-update")]
 mod self_update;
 mod step;
+mod step_config;
+mod step_log;
 mod steps;
 mod sudo;
 mod terminal;
 #[cfg(unix)]
 mod tmux;
 mod utils;
+mod watchdog;
 
 pub(crate) static HOME_DIR: LazyLock<PathBuf> = LazyLock::new(|| home_dir().expect("No home directory"));
 #[cfg(unix)]
@@ -55,6 +68,9 @@ pub(crate) static XDG_DIRS: LazyLock<Xdg> = LazyLock::new(|| Xdg::new().expect("
 #[cfg(windows)]
 pub(crate) static WINDOWS_DIRS: LazyLock<Windows> = LazyLock::new(|| Windows::new().expect("No home directory"));
 
+/// The exit code of `--check` when there are updates to install, like `dnf check-update`.
+const UPDATES_AVAILABLE_EXIT_CODE: i32 = 100;
+
 // Init and load the i18n files
 i18n!("locales", fallback = "en");
 
@@ -103,16 +119,37 @@ fn run() -> Result<()> {
     };
 
     if opt.show_config_reference() {
-        print!("{}", config::EXAMPLE_CONFIG);
+        print!("{}{}", config::EXAMPLE_CONFIG, step_config::reference());
         return Ok(());
     }
 
+    if opt.show_effective_config() {
+        return effective_config::show(opt.config_path());
+    }
+
+    if opt.check_config() {
+        if !config_check::run(opt.config_path())? {
+            exit(1);
+        }
+        return Ok(());
+    }
+
+    if let Some(TopgradeCommand::History { run }) = opt.command {
+        return history::show(run);
+    }
+
     let config = Config::load(opt)?;
     // Update the logger with the full filter directives.
     update_tracing(&reload_handle, &config.tracing_filter_directives())?;
     set_title(config.set_title());
     display_time(config.display_time());
-    set_desktop_notifications(config.notify_each_step());
+    set_desktop_notifications(
+        config.notify_each_step()
+            && config
+                .notifiers()
+                .iter()
+                .any(|notifier| matches!(notifier, notify::Notifier::Desktop)),
+    );
     set_wsl_use_windows_path(config.wsl_use_windows_path())?;
 
     debug!("Version: {}", crate_version!());
@@ -158,15 +195,44 @@ fn run() -> Result<()> {
     let distribution = linux::Distribution::detect();
 
     let run_type = config.run_type();
-    let ctx = execution_context::ExecutionContext::new(
+    let mut ctx = execution_context::ExecutionContext::new(
         run_type,
         sudo,
         &config,
         #[cfg(target_os = "linux")]
         &distribution,
     );
+    if let Some(path) = config.replay_fixture() {
+        ctx = ctx.with_replay(fixture::Replay::load(path)?);
+    }
+    if config.record_fixture().is_some() {
+        fixture::enable_recording();
+    }
     let mut runner = runner::Runner::new(&ctx);
 
+    if config.plan_file().is_some() {
+        plan::enable();
+    }
+
+    if config.step_logs()
+        && !run_type.dry()
+        && let Err(err) = step_log::init()
+    {
+        print_warning(format!("{err:?}"));
+    }
+
+    if !run_type.dry()
+        && let Err(err) = resume::start(config.resume(), runner.started())
+    {
+        print_warning(format!("{err:?}"));
+    }
+
+    if !run_type.dry()
+        && let Err(err) = schedule::start()
+    {
+        print_warning(format!("{err:?}"));
+    }
+
     if !breaking_changes::should_skip() {
         breaking_changes::run()?;
     }
@@ -181,6 +247,7 @@ fn run() -> Result<()> {
     };
 
     if let Some(sudo) = ctx.sudo()
+        && !run_type.check()
         && (config.pre_sudo() || (config.sudo_loop() && sudo.can_refresh()))
     {
         sudo.elevate(&ctx)?;
@@ -189,26 +256,28 @@ fn run() -> Result<()> {
     // Held until `run()` returns — dropping would stop the background thread.
     let _sudo_loop_guard = spawn_sudo_loop(&ctx, &config);
 
-    if let Some(commands) = config.pre_commands() {
+    if !run_type.check()
+        && let Some(commands) = config.pre_commands()
+    {
         for (name, command) in commands {
             generic::run_custom_command(name, command, &ctx)?;
         }
     }
 
-    for step in config.steps()? {
-        match step.run(&mut runner, &ctx) {
-            Ok(()) => (),
-            Err(error)
-                if error
-                    .downcast_ref::<io::Error>()
-                    .is_some_and(|e| e.kind() == io::ErrorKind::Interrupted) =>
-            {
-                println!();
-                debug!("Interrupted (possibly with 'q' during retry prompt). Printing summary.");
-                break;
-            }
-            Err(error) => return Err(error),
+    let steps: Vec<step::Step> = config.steps()?.collect();
+    let mut interrupted = false;
+    match parallel::run_steps(&steps, &mut runner, &ctx) {
+        Ok(()) => (),
+        Err(error)
+            if error
+                .downcast_ref::<io::Error>()
+                .is_some_and(|e| e.kind() == io::ErrorKind::Interrupted) =>
+        {
+            println!();
+            debug!("Interrupted (possibly with 'q' during retry prompt). Printing summary.");
+            interrupted = true;
         }
+        Err(error) => return Err(error),
     }
 
     let mut failed = false;
@@ -219,16 +288,24 @@ fn run() -> Result<()> {
 
         let mut skipped_missing_sudo = false;
 
-        for (key, result) in report {
-            if !failed && result.failed() {
+        for step_report in report {
+            if !failed && step_report.result.failed() {
                 failed = true;
             }
-            if let StepResult::SkippedMissingSudo = result {
+            if let StepResult::SkippedMissingSudo = step_report.result {
                 skipped_missing_sudo = true;
             }
-            print_result(key, result);
+            print_result(step_report);
         }
 
+        println!(
+            "{}",
+            t!(
+                "Total time: {duration}",
+                duration = format_duration((chrono::Local::now() - runner.started()).to_std().unwrap_or_default())
+            )
+        );
+
         if skipped_missing_sudo {
             print_warning(t!(
                 "\nSome steps were skipped as sudo or equivalent could not be found."
@@ -262,6 +339,12 @@ fn run() -> Result<()> {
         }
     }
 
+    if !interrupted {
+        resume::finish();
+    } else if !run_type.dry() {
+        print_info(t!("Run `topgrade --resume` to continue where this run stopped"));
+    }
+
     #[cfg(target_os = "linux")]
     if config.show_distribution_summary()
         && let Ok(distribution) = &distribution
@@ -269,7 +352,9 @@ fn run() -> Result<()> {
         distribution.show_summary();
     }
 
-    if let Some(commands) = config.post_commands() {
+    if !run_type.check()
+        && let Some(commands) = config.post_commands()
+    {
         for (name, command) in commands {
             let result = generic::run_custom_command(name, command, &ctx);
             if !failed && result.is_err() {
@@ -278,6 +363,28 @@ fn run() -> Result<()> {
         }
     }
 
+    let run_report = report::RunReport::new(runner.started(), report);
+    if let Some((path, format)) = config.report_file() {
+        run_report.write(format, path)?;
+    }
+    // Picked up by the Topgrade that runs this one on a remote host, see `ssh::remote_step`.
+    if env::var_os(report::REMOTE_REPORT_ENV).is_some() {
+        run_report.write_embedded()?;
+    }
+    if let Some((path, format)) = config.plan_file() {
+        plan::write(format, path)?;
+    }
+    if let Some(path) = config.record_fixture() {
+        fixture::write_recording(path)?;
+    }
+    if config.history_size() > 0
+        && !run_type.dry()
+        && !report.is_empty()
+        && let Err(err) = history::record(&run_report, config.history_size())
+    {
+        print_warning(format!("{err:?}"));
+    }
+
     if config.keep_at_end() {
         print_info(t!("\n(R)eboot\n(P)oweroff\n(S)hell\n(Q)uit"));
         loop {
@@ -309,17 +416,16 @@ fn run() -> Result<()> {
     };
 
     if should_notify {
-        notify_desktop(
-            if failed {
-                t!("Topgrade finished with errors")
-            } else {
-                t!("Topgrade finished successfully")
-            },
-            Some(Duration::from_secs(10)),
-        );
+        notify::run_finished(&config, &run_report);
     }
 
-    if failed { Err(StepFailed.into()) } else { Ok(()) }
+    if failed {
+        Err(StepFailed.into())
+    } else if report.iter().any(|step_report| !step_report.updates.is_empty()) {
+        Err(UpdatesAvailable.into())
+    } else {
+        Ok(())
+    }
 }
 
 fn spawn_sudo_loop(ctx: &execution_context::ExecutionContext, config: &Config) -> Option<std::sync::mpsc::Sender<()>> {
@@ -353,6 +459,9 @@ fn main() {
         Ok(()) => {
             exit(0);
         }
+        Err(error) if error.downcast_ref::<UpdatesAvailable>().is_some() => {
+            exit(UPDATES_AVAILABLE_EXIT_CODE);
+        }
         Err(error) => {
             let skip_print = (error.downcast_ref::<StepFailed>().is_some())
                 || (error
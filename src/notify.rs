@@ -0,0 +1,472 @@
+//! Notifications about a run, sent to the backends configured in `[[notifiers]]`.
+//!
+//! When a run finishes, every notifier is told about it according to
+//! `misc.notify_end`. With `misc.notify_each_step`, they are also told about
+//! the result of every step. Without any `[[notifiers]]`, desktop notifications
+//! are used, like before notifiers could be configured.
+//!
+//! HTTP requests are made with `curl`, so Topgrade doesn't need an HTTP client
+//! of its own.
+
+use std::ffi::OsString;
+use std::io::{ErrorKind, Write};
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::thread;
+use std::time::Duration;
+
+use color_eyre::eyre::{Context, Result, eyre};
+use indexmap::IndexMap;
+use rust_i18n::t;
+use serde::{Deserialize, Serialize};
+use tempfile::NamedTempFile;
+use tracing::debug;
+
+use crate::command::CommandExt;
+use crate::config::Config;
+use crate::report::RunReport;
+use crate::runner::{StepReport, StepResult};
+use crate::terminal::{notify_desktop, print_warning};
+use crate::utils::hostname;
+
+/// How long an HTTP notification may take.
+const HTTP_TIMEOUT_SECS: &str = "30";
+
+/// A backend that notifications are sent to.
+#[derive(Deserialize, Serialize, Debug)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+pub enum Notifier {
+    /// A desktop notification.
+    Desktop,
+    /// A POST request with the JSON report as its body.
+    Webhook {
+        url: String,
+        #[serde(default)]
+        headers: IndexMap<String, String>,
+    },
+    /// A message published to an ntfy topic, e.g. `https://ntfy.sh/my-topic`.
+    Ntfy { url: String, token: Option<String> },
+    /// A message sent to a Gotify server, e.g. `https://gotify.example.com`.
+    Gotify { url: String, token: String },
+    /// An email handed to a local `sendmail`.
+    Sendmail {
+        to: String,
+        from: Option<String>,
+        sendmail: Option<String>,
+    },
+    /// A command that gets the JSON report on stdin.
+    Command { command: String },
+}
+
+/// Something that notifiers are told about.
+pub enum Event<'a, 'r> {
+    Step(&'a StepReport<'r>),
+    End(&'a RunReport<'r>),
+}
+
+impl Event<'_, '_> {
+    /// The name of the event, passed to webhooks and commands.
+    fn name(&self) -> &'static str {
+        match self {
+            Event::Step(_) => "step",
+            Event::End(_) => "end",
+        }
+    }
+
+    fn failed(&self) -> bool {
+        match self {
+            Event::Step(report) => report.result.failed(),
+            Event::End(report) => report.failed,
+        }
+    }
+
+    fn title(&self) -> String {
+        match hostname() {
+            Ok(hostname) => format!("Topgrade ({hostname})"),
+            Err(_) => String::from("Topgrade"),
+        }
+    }
+
+    /// A short, human readable description of the event.
+    fn message(&self) -> String {
+        match self {
+            Event::Step(report) => step_line(report),
+            Event::End(report) => {
+                let mut message = if report.failed {
+                    t!("Topgrade finished with errors").to_string()
+                } else {
+                    t!("Topgrade finished successfully").to_string()
+                };
+                for step in report.steps.iter() {
+                    message.push('\n');
+                    message.push_str(&step_line(step));
+                }
+                message
+            }
+        }
+    }
+
+    fn json(&self) -> Result<String> {
+        match self {
+            Event::Step(report) => serde_json::to_string(report),
+            Event::End(report) => serde_json::to_string(report),
+        }
+        .context("Failed to serialize the notification")
+    }
+}
+
+fn step_line(report: &StepReport) -> String {
+    let status = match &report.result {
+        StepResult::Success => t!("OK"),
+        StepResult::Failure => t!("FAILED"),
+        StepResult::TimedOut => t!("TIMED OUT"),
+        StepResult::Ignored => t!("IGNORED"),
+        StepResult::SkippedMissingSudo | StepResult::Skipped(_) => t!("SKIPPED"),
+    };
+    format!("{}: {status}", report.key)
+}
+
+impl Notifier {
+    fn send(&self, event: &Event) -> Result<()> {
+        match self {
+            Notifier::Desktop => {
+                // Desktop notifications about steps are sent as they start, see `terminal.rs`.
+                if let Event::End(_) = event {
+                    notify_desktop(event.message(), Some(Duration::from_secs(10)));
+                }
+                Ok(())
+            }
+            Notifier::Webhook { url, headers } => {
+                let mut curl = Curl::new(url);
+                curl.header("Content-Type", "application/json");
+                curl.header("X-Topgrade-Event", event.name());
+                for (name, value) in headers {
+                    curl.header(name, value);
+                }
+                curl.send(event.json()?.as_bytes())
+            }
+            Notifier::Ntfy { url, token } => {
+                let mut curl = Curl::new(url);
+                curl.header("Title", &event.title());
+                if event.failed() {
+                    curl.header("Priority", "high").header("Tags", "warning");
+                }
+                if let Some(token) = token {
+                    curl.header("Authorization", &format!("Bearer {token}"));
+                }
+                curl.send(event.message().as_bytes())
+            }
+            Notifier::Gotify { url, token } => {
+                let mut curl = Curl::new(&format!("{}/message", url.trim_end_matches('/')));
+                curl.header("Content-Type", "application/json");
+                curl.header("X-Gotify-Key", token);
+                let body = serde_json::json!({
+                    "title": event.title(),
+                    "message": event.message(),
+                    "priority": if event.failed() { 8 } else { 4 },
+                });
+                curl.send(body.to_string().as_bytes())
+            }
+            Notifier::Sendmail { to, from, sendmail } => {
+                #[expect(clippy::disallowed_methods)]
+                let mut command = Command::new(sendmail.as_deref().unwrap_or("sendmail"));
+                command.arg("-t");
+
+                let mut mail = format!("To: {to}\n");
+                if let Some(from) = from {
+                    mail.push_str(&format!("From: {from}\n"));
+                }
+                mail.push_str(&format!("Subject: {}\n", event.title()));
+                mail.push_str("Content-Type: text/plain; charset=utf-8\n\n");
+                mail.push_str(&event.message());
+                mail.push('\n');
+                run_with_input(&mut command, mail.as_bytes())
+            }
+            Notifier::Command { command } => {
+                let mut words = shell_words::split(command)
+                    .with_context(|| format!("Failed to parse the notifier command `{command}`"))?
+                    .into_iter();
+                let program = words.next().ok_or_else(|| eyre!("The notifier command is empty"))?;
+
+                #[expect(clippy::disallowed_methods)]
+                let mut command = Command::new(program);
+                command
+                    .args(words)
+                    .env("TOPGRADE_EVENT", event.name())
+                    .env("TOPGRADE_FAILED", if event.failed() { "1" } else { "0" });
+                run_with_input(&mut command, event.json()?.as_bytes())
+            }
+        }
+    }
+}
+
+/// A `curl` command POSTing to a URL.
+///
+/// The headers are passed in a file that only the user can read rather than as
+/// arguments, so that tokens don't show up in the process list or in the logs.
+struct Curl {
+    url: String,
+    headers: Vec<String>,
+}
+
+impl Curl {
+    fn new(url: &str) -> Self {
+        Self {
+            url: url.to_string(),
+            headers: Vec::new(),
+        }
+    }
+
+    fn header(&mut self, name: &str, value: &str) -> &mut Self {
+        self.headers.push(format!("{name}: {value}"));
+        self
+    }
+
+    fn command(&self, headers_file: &Path) -> Command {
+        let mut headers = OsString::from("@");
+        headers.push(headers_file);
+
+        #[expect(clippy::disallowed_methods)]
+        let mut command = Command::new("curl");
+        command
+            .args([
+                "--silent",
+                "--show-error",
+                "--fail",
+                "--max-time",
+                HTTP_TIMEOUT_SECS,
+                "--data-binary",
+                "@-",
+                "-H",
+            ])
+            .arg(headers)
+            .arg(&self.url);
+        command
+    }
+
+    /// POST `body` and wait for the response.
+    fn send(&self, body: &[u8]) -> Result<()> {
+        if let Some(header) = self.headers.iter().find(|header| header.contains(['\r', '\n'])) {
+            return Err(eyre!("The header `{}` contains a line break", header.trim()));
+        }
+
+        // Created readable by the user only.
+        let mut headers_file = NamedTempFile::new().context("Failed to create a file for the headers")?;
+        for header in &self.headers {
+            writeln!(headers_file, "{header}")?;
+        }
+        headers_file.flush()?;
+
+        run_with_input(&mut self.command(headers_file.path()), body)
+    }
+}
+
+/// Run `command` with `input` on its stdin.
+fn run_with_input(command: &mut Command, input: &[u8]) -> Result<()> {
+    let mut child = command
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn_checked()?;
+    let mut stdin = child.stdin.take().expect("stdin is piped");
+
+    // The input is written while the output is read, or a command that answers
+    // before reading all of it could fill the pipes and never exit.
+    let (written, output) = thread::scope(|scope| {
+        let writer = scope.spawn(move || stdin.write_all(input));
+        let output = child.wait_with_output();
+        (writer.join().expect("the writer panicked"), output)
+    });
+    let output = output?;
+    if output.status.success() {
+        match written {
+            // A command may exit without reading its input.
+            Err(e) if e.kind() != ErrorKind::BrokenPipe => Err(e).context("Failed to write the notification"),
+            _ => Ok(()),
+        }
+    } else {
+        Err(eyre!(
+            "`{}` failed with {}: {}",
+            command.get_program().to_string_lossy(),
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        ))
+    }
+}
+
+fn send_all(config: &Config, event: &Event) {
+    for notifier in config.notifiers() {
+        debug!("Sending {} notification to {notifier:?}", event.name());
+        if let Err(err) = notifier.send(event) {
+            print_warning(t!("Failed to send a notification: {error}", error = format!("{err:?}")));
+        }
+    }
+}
+
+/// Tell the notifiers about a finished step, if `misc.notify_each_step` is enabled.
+pub fn step_finished(config: &Config, report: &StepReport) {
+    if config.notify_each_step() {
+        send_all(config, &Event::Step(report));
+    }
+}
+
+/// Tell the notifiers about the end of the run.
+pub fn run_finished(config: &Config, report: &RunReport) {
+    send_all(config, &Event::End(report));
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::{BufRead, BufReader, Read};
+    use std::net::TcpListener;
+
+    use chrono::Local;
+
+    use super::*;
+    use crate::step::Step;
+
+    fn steps() -> Vec<StepReport<'static>> {
+        let mut report = StepReport::for_test(Step::Cargo, "cargo", StepResult::Failure, Duration::from_secs(2));
+        report.errors = vec!["exit status: 1".into()];
+        vec![report]
+    }
+
+    /// Accept a single HTTP request on a local port, returning its URL and a
+    /// handle that gives the request head and body.
+    fn listen() -> (String, thread::JoinHandle<(String, String)>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/hook", listener.local_addr().unwrap());
+        let handle = thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream);
+
+            let mut head = String::new();
+            loop {
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                if line == "\r\n" {
+                    break;
+                }
+                head.push_str(&line);
+            }
+            let length: usize = head
+                .lines()
+                .find_map(|line| line.to_lowercase().strip_prefix("content-length: ").map(str::to_owned))
+                .unwrap()
+                .parse()
+                .unwrap();
+            let mut body = vec![0; length];
+            reader.read_exact(&mut body).unwrap();
+
+            reader
+                .get_mut()
+                .write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 0\r\nConnection: close\r\n\r\n")
+                .unwrap();
+            (head, String::from_utf8(body).unwrap())
+        });
+        (url, handle)
+    }
+
+    #[test]
+    fn test_webhook_posts_the_report() {
+        let (url, request) = listen();
+        let steps = steps();
+        let report = RunReport::new(Local::now(), &steps);
+        let notifier = Notifier::Webhook {
+            url,
+            headers: IndexMap::from([("Authorization".to_string(), "Bearer secret".to_string())]),
+        };
+
+        notifier.send(&Event::End(&report)).unwrap();
+
+        let (head, body) = request.join().unwrap();
+        assert!(head.starts_with("POST /hook "), "{head}");
+        assert!(head.contains("X-Topgrade-Event: end"), "{head}");
+        assert!(head.contains("Authorization: Bearer secret"), "{head}");
+        let json: serde_json::Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(json["failed"], true);
+        assert_eq!(json["steps"][0]["key"], "cargo");
+    }
+
+    #[test]
+    fn test_ntfy_posts_a_message() {
+        let (url, request) = listen();
+        let steps = steps();
+        let notifier = Notifier::Ntfy { url, token: None };
+
+        notifier.send(&Event::Step(&steps[0])).unwrap();
+
+        let (head, body) = request.join().unwrap();
+        assert!(head.contains("Title: Topgrade"), "{head}");
+        assert!(head.contains("Priority: high"), "{head}");
+        assert_eq!(body, "cargo: FAILED");
+    }
+
+    #[test]
+    fn test_curl_headers_are_not_arguments() {
+        let mut curl = Curl::new("https://ntfy.sh/topic");
+        curl.header("Authorization", "Bearer secret");
+        let command = curl.command(Path::new("headers"));
+        assert!(command.get_args().all(|arg| !arg.to_string_lossy().contains("secret")));
+        assert!(command.get_args().any(|arg| arg == "@headers"));
+
+        curl.header("X-Injected", "a\r\nHost: example.com");
+        assert!(curl.send(b"").is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_run_with_input_does_not_block() {
+        // More output than fits in a pipe, written before reading more input than fits in one.
+        #[expect(clippy::disallowed_methods)]
+        let mut command = Command::new("sh");
+        command.args(["-c", "head -c 1000000 /dev/zero; cat > /dev/null"]);
+        run_with_input(&mut command, &vec![b'x'; 1_000_000]).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_command_gets_the_report_on_stdin() {
+        let dir = tempfile::tempdir().unwrap();
+        let output = dir.path().join("report.json");
+        let steps = steps();
+        let report = RunReport::new(Local::now(), &steps);
+        let notifier = Notifier::Command {
+            command: format!(
+                "sh -c 'echo $TOPGRADE_EVENT $TOPGRADE_FAILED > {0}.env; cat > {0}'",
+                output.display()
+            ),
+        };
+
+        notifier.send(&Event::End(&report)).unwrap();
+
+        let json: serde_json::Value = serde_json::from_str(&std::fs::read_to_string(&output).unwrap()).unwrap();
+        assert_eq!(json["steps"][0]["status"], "failure");
+        let env = std::fs::read_to_string(output.with_extension("json.env")).unwrap();
+        assert_eq!(env.trim(), "end 1");
+    }
+
+    #[test]
+    fn test_notifier_config() {
+        let notifiers: Vec<Notifier> = toml::from_str::<IndexMap<String, Vec<Notifier>>>(
+            r#"
+notifiers = [
+    { type = "desktop" },
+    { type = "ntfy", url = "https://ntfy.sh/topic" },
+    { type = "sendmail", to = "admin@example.com" },
+]
+"#,
+        )
+        .unwrap()
+        .swap_remove("notifiers")
+        .unwrap();
+        assert!(matches!(notifiers[1], Notifier::Ntfy { ref url, token: None } if url == "https://ntfy.sh/topic"));
+
+        assert!(
+            toml::from_str::<IndexMap<String, Vec<Notifier>>>(
+                r#"notifiers = [{ type = "ntfy", url = "x", topic = "y" }]"#
+            )
+            .is_err()
+        );
+    }
+}
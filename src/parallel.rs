@@ -0,0 +1,229 @@
+//! Running independent steps concurrently.
+//!
+//! With `--jobs N` (or `misc.parallel_steps`), consecutive steps that only
+//! update user-level tooling are run on up to N worker threads, see
+//! `Step::parallel_resource`. Every other step is a barrier: it waits for the
+//! running steps to finish and then runs on its own, so anything that needs
+//! sudo or the system package manager never runs alongside another step.
+//!
+//! The output of a step run in parallel is captured and printed in one piece
+//! once the step finishes, so the logs of different steps don't interleave.
+
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::process::Stdio;
+use std::sync::Mutex;
+use std::thread;
+
+use color_eyre::eyre::{Context, Result};
+use indexmap::IndexMap;
+use tracing::debug;
+
+use crate::ctrlc;
+use crate::execution_context::ExecutionContext;
+use crate::runner::{Runner, StepReport};
+use crate::step::Step;
+
+thread_local! {
+    /// Where the output of the step running on this thread goes, if it is captured.
+    static CAPTURE: RefCell<Option<File>> = const { RefCell::new(None) };
+}
+
+/// Write to the capture buffer of this thread. Returns `false` if output isn't captured.
+pub fn write_captured(args: fmt::Arguments) -> bool {
+    CAPTURE.with_borrow_mut(|capture| match capture {
+        Some(file) => {
+            file.write_fmt(args).ok();
+            true
+        }
+        None => false,
+    })
+}
+
+/// Whether the output of this thread is being captured.
+pub fn capturing() -> bool {
+    CAPTURE.with_borrow(Option::is_some)
+}
+
+/// Handles to the capture buffer to use as stdout and stderr, if the output of this thread is captured.
+pub fn captured_file() -> Option<(File, File)> {
+    CAPTURE.with_borrow(|capture| {
+        let file = capture.as_ref()?;
+        Some((file.try_clone().ok()?, file.try_clone().ok()?))
+    })
+}
+
+/// The stdout and stderr for a child process, if the output of this thread is captured.
+pub fn captured_stdio() -> Option<(Stdio, Stdio)> {
+    captured_file().map(|(stdout, stderr)| (stdout.into(), stderr.into()))
+}
+
+/// Run `f` with the output of this thread captured, returning what it printed.
+pub fn capture<T>(f: impl FnOnce() -> T) -> Result<(T, Vec<u8>)> {
+    let file = tempfile::tempfile().context("Failed to create a buffer for step output")?;
+    CAPTURE.set(Some(file));
+    let value = f();
+    let mut file = CAPTURE.take().expect("capture buffer was removed");
+
+    let mut output = Vec::new();
+    file.seek(SeekFrom::Start(0))?;
+    file.read_to_end(&mut output)?;
+    Ok((value, output))
+}
+
+/// Run `steps` in order, running independent steps concurrently if `--jobs` allows it.
+pub fn run_steps<'a>(steps: &[Step], runner: &mut Runner<'a>, ctx: &'a ExecutionContext<'a>) -> Result<()> {
+    let jobs = ctx.config().jobs();
+    let order = ctx.config().step_order();
+    let is_parallel = |step: &Step| jobs > 1 && step.parallel_resource(ctx.config()).is_some();
+
+    let mut rest = steps;
+    while !rest.is_empty() {
+        // A step that `misc.order` puts after a step of the batch has to wait for the batch.
+        let batch_len = rest
+            .iter()
+            .enumerate()
+            .take_while(|&(index, step)| {
+                is_parallel(step) && !rest[..index].iter().any(|&before| order.contains(&(before, *step)))
+            })
+            .count();
+        if batch_len > 1 {
+            let (batch, tail) = rest.split_at(batch_len);
+            run_batch(batch, jobs, runner, ctx)?;
+            rest = tail;
+        } else {
+            rest[0].run(runner, ctx)?;
+            rest = &rest[1..];
+        }
+    }
+
+    Ok(())
+}
+
+/// Run a batch of parallel-safe steps on up to `jobs` threads.
+///
+/// Steps sharing a resource form a lane and run one after another, in order.
+/// The results are added to `runner` in the order of `batch`, regardless of
+/// which step finished first.
+fn run_batch<'a>(batch: &[Step], jobs: usize, runner: &mut Runner<'a>, ctx: &'a ExecutionContext<'a>) -> Result<()> {
+    let mut lanes: IndexMap<&str, Vec<(usize, Step)>> = IndexMap::new();
+    for (index, &step) in batch.iter().enumerate() {
+        let resource = step
+            .parallel_resource(ctx.config())
+            .expect("batch contains a serial step");
+        lanes.entry(resource).or_default().push((index, step));
+    }
+    debug!(
+        "Running {} steps in {} lanes on {jobs} threads",
+        batch.len(),
+        lanes.len()
+    );
+
+    let workers = jobs.min(lanes.len());
+    let results: Mutex<Vec<(usize, Result<Vec<StepReport<'a>>>)>> = Mutex::new(Vec::new());
+    run_lanes(lanes.into_values().collect(), workers, |(index, step)| {
+        let result = run_captured(step, ctx);
+        results.lock().unwrap().push((index, result));
+    });
+
+    let mut results = results.into_inner().unwrap();
+    results.sort_by_key(|(index, _)| *index);
+    for (_, result) in results {
+        runner.extend_report(result?);
+    }
+
+    if ctrlc::interrupted() {
+        ctrlc::unset_interrupted();
+        return Err(io::Error::from(io::ErrorKind::Interrupted)).context("Interrupted while running steps in parallel");
+    }
+
+    Ok(())
+}
+
+/// Run the items of `lanes` with `run` on `workers` threads, the items of a lane one after another.
+fn run_lanes<T: Send>(lanes: Vec<Vec<T>>, workers: usize, run: impl Fn(T) + Sync) {
+    let queue = Mutex::new(VecDeque::from(lanes));
+    thread::scope(|scope| {
+        for _ in 0..workers {
+            scope.spawn(|| {
+                loop {
+                    // Popping in the loop condition would hold the lock while the lane runs.
+                    let lane = queue.lock().unwrap().pop_front();
+                    let Some(lane) = lane else { break };
+                    for item in lane {
+                        if ctrlc::interrupted() {
+                            return;
+                        }
+                        run(item);
+                    }
+                }
+            });
+        }
+    });
+}
+
+/// Run a single step with its output captured, then print the output at once.
+fn run_captured<'a>(step: Step, ctx: &'a ExecutionContext<'a>) -> Result<Vec<StepReport<'a>>> {
+    let mut runner = Runner::unattended(ctx);
+    let (result, output) = capture(|| step.run(&mut runner, ctx))?;
+    io::stdout().lock().write_all(&output)?;
+    result?;
+    Ok(runner.into_report())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::command::CommandExt;
+    use crate::executor::{DryCommand, Executor};
+    use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn test_lanes_run_concurrently() {
+        let running = AtomicUsize::new(0);
+        let overlapped = AtomicBool::new(false);
+        run_lanes(vec![vec![1], vec![2]], 2, |_| {
+            running.fetch_add(1, Ordering::SeqCst);
+            let start = Instant::now();
+            while start.elapsed() < Duration::from_secs(5) {
+                if running.load(Ordering::SeqCst) == 2 {
+                    overlapped.store(true, Ordering::SeqCst);
+                    break;
+                }
+                thread::sleep(Duration::from_millis(1));
+            }
+            // Stay around until the other lane has seen this one.
+            thread::sleep(Duration::from_millis(20));
+            running.fetch_sub(1, Ordering::SeqCst);
+        });
+        assert!(overlapped.load(Ordering::SeqCst));
+    }
+
+    #[test]
+    fn test_capture_collects_child_and_terminal_output() {
+        let ((), output) = capture(|| {
+            crate::terminal::print_warning("from topgrade");
+            let mut command = if cfg!(windows) {
+                let mut command = Executor::Dry(DryCommand::new("cmd")).always();
+                command.args(["/C", "echo from child"]);
+                command
+            } else {
+                let mut command = Executor::Dry(DryCommand::new("echo")).always();
+                command.arg("from child");
+                command
+            };
+            command.status_checked().unwrap();
+        })
+        .unwrap();
+
+        let output = String::from_utf8(output).unwrap();
+        let warning = output.find("from topgrade").unwrap();
+        let child = output.find("from child").unwrap();
+        assert!(warning < child);
+        assert!(!capturing());
+    }
+}
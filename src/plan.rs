@@ -0,0 +1,227 @@
+//! Plans of the commands a dry run would execute.
+//!
+//! With `--plan-file`, every command that a dry run skips is
+//! recorded along with the step it belongs to, its working directory,
+//! environment and whether it would run through sudo. The plan is written as
+//! JSON, or as a POSIX shell script that runs the same commands.
+
+use std::cell::RefCell;
+use std::ffi::OsStr;
+use std::fs;
+use std::path::Path;
+use std::sync::Mutex;
+
+use chrono::Local;
+use clap::ValueEnum;
+use color_eyre::eyre::{Context, Result};
+use serde::Serialize;
+
+use crate::sudo::SudoKind;
+use crate::utils::hostname;
+
+/// Version of the JSON plan schema, bumped on incompatible changes.
+pub const SCHEMA_VERSION: u32 = 1;
+
+/// The format a plan is written in.
+#[derive(Clone, Copy, Debug, Default, ValueEnum)]
+#[clap(rename_all = "snake_case")]
+pub enum PlanFormat {
+    #[default]
+    Json,
+    /// A POSIX shell script.
+    Sh,
+}
+
+/// A command a dry run would have executed.
+#[derive(Clone, Debug, Serialize)]
+pub struct PlannedCommand {
+    /// The key of the step running the command, `None` outside of steps.
+    pub step: Option<String>,
+    pub program: String,
+    pub args: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cwd: Option<String>,
+    /// Environment variables set for the command, in the order they are set.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub env: Vec<(String, String)>,
+    /// Environment variables removed for the command.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub env_remove: Vec<String>,
+    /// The kind of sudo the command goes through, if it is run with elevated privileges.
+    pub sudo: Option<SudoKind>,
+}
+
+#[derive(Serialize)]
+struct Plan<'a> {
+    schema_version: u32,
+    topgrade_version: &'a str,
+    hostname: Option<String>,
+    commands: &'a [PlannedCommand],
+}
+
+/// The commands recorded so far, if a plan was requested.
+static PLAN: Mutex<Option<Vec<PlannedCommand>>> = Mutex::new(None);
+
+thread_local! {
+    /// The key of the step running on this thread.
+    static CURRENT_STEP: RefCell<Option<String>> = const { RefCell::new(None) };
+}
+
+/// Start recording the commands of this run.
+pub fn enable() {
+    PLAN.lock().unwrap().get_or_insert_with(Vec::new);
+}
+
+/// Run `f` with the commands it records attributed to the step with the given key.
+pub fn run<T>(key: &str, f: impl FnOnce() -> T) -> T {
+    let previous = CURRENT_STEP.replace(Some(key.to_string()));
+    let value = f();
+    CURRENT_STEP.set(previous);
+    value
+}
+
+/// Record a command that was not executed because of the dry run.
+pub fn record<'a>(
+    program: &OsStr,
+    args: impl IntoIterator<Item = &'a (impl AsRef<OsStr> + ?Sized + 'a)>,
+    cwd: Option<&OsStr>,
+    env: &[(impl AsRef<OsStr>, impl AsRef<OsStr>)],
+    env_remove: &[impl AsRef<OsStr>],
+    sudo: Option<SudoKind>,
+) {
+    let lossy = |s: &OsStr| s.to_string_lossy().into_owned();
+    if let Some(plan) = PLAN.lock().unwrap().as_mut() {
+        plan.push(PlannedCommand {
+            step: CURRENT_STEP.with_borrow(Clone::clone),
+            program: lossy(program),
+            args: args.into_iter().map(|arg| lossy(arg.as_ref())).collect(),
+            cwd: cwd.map(lossy),
+            env: env
+                .iter()
+                .map(|(key, value)| (lossy(key.as_ref()), lossy(value.as_ref())))
+                .collect(),
+            env_remove: env_remove.iter().map(|key| lossy(key.as_ref())).collect(),
+            sudo,
+        });
+    }
+}
+
+fn render(commands: &[PlannedCommand], format: PlanFormat) -> Result<String> {
+    match format {
+        PlanFormat::Json => serde_json::to_string_pretty(&Plan {
+            schema_version: SCHEMA_VERSION,
+            topgrade_version: env!("CARGO_PKG_VERSION"),
+            hostname: hostname().ok(),
+            commands,
+        })
+        .map(|json| json + "\n")
+        .context("Failed to serialize the plan"),
+        PlanFormat::Sh => Ok(render_sh(commands)),
+    }
+}
+
+fn render_sh(commands: &[PlannedCommand]) -> String {
+    let mut script = format!(
+        "#!/bin/sh\n# The commands Topgrade {} would run{}, planned on {}.\n",
+        env!("CARGO_PKG_VERSION"),
+        hostname().map(|hostname| format!(" on {hostname}")).unwrap_or_default(),
+        Local::now().format("%Y-%m-%d %H:%M:%S")
+    );
+
+    let mut step = None;
+    for command in commands {
+        if command.step != step {
+            step.clone_from(&command.step);
+            script.push_str(&format!("\n# {}\n", step.as_deref().unwrap_or("(no step)")));
+        }
+
+        let mut line = Vec::new();
+        if let Some(cwd) = &command.cwd {
+            line.push(format!("cd {} &&", shell_words::quote(cwd)));
+        }
+        for key in &command.env_remove {
+            line.push(format!("unset {} &&", shell_words::quote(key)));
+        }
+        for (key, value) in &command.env {
+            line.push(format!("{key}={}", shell_words::quote(value)));
+        }
+        line.push(shell_words::join(iter_words(command)));
+
+        // A subshell keeps the directory and environment changes to the command itself.
+        if command.cwd.is_some() || !command.env_remove.is_empty() {
+            script.push_str(&format!("({})\n", line.join(" ")));
+        } else {
+            script.push_str(&format!("{}\n", line.join(" ")));
+        }
+    }
+
+    script
+}
+
+fn iter_words(command: &PlannedCommand) -> impl Iterator<Item = &str> {
+    std::iter::once(command.program.as_str()).chain(command.args.iter().map(String::as_str))
+}
+
+/// Write the recorded plan to `path`.
+pub fn write(format: PlanFormat, path: &Path) -> Result<()> {
+    let plan = PLAN.lock().unwrap();
+    let contents = render(plan.as_deref().unwrap_or_default(), format)?;
+    fs::write(path, contents).with_context(|| format!("Failed to write the plan to {}", path.display()))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn command(step: &str, program: &str, args: &[&str]) -> PlannedCommand {
+        PlannedCommand {
+            step: Some(step.to_string()),
+            program: program.to_string(),
+            args: args.iter().map(ToString::to_string).collect(),
+            cwd: None,
+            env: Vec::new(),
+            env_remove: Vec::new(),
+            sudo: None,
+        }
+    }
+
+    #[test]
+    fn test_render_sh() {
+        let mut apt = command("System update", "/usr/bin/sudo", &["/usr/bin/apt", "dist-upgrade"]);
+        apt.sudo = Some(SudoKind::Sudo);
+        let mut git = command("Git repositories", "git", &["pull", "--ff-only"]);
+        git.cwd = Some(String::from("/home/me/my repo"));
+        git.env = vec![(String::from("GIT_TERMINAL_PROMPT"), String::from("0"))];
+        let mut git_gc = command("Git repositories", "git", &["gc"]);
+        git_gc.env_remove = vec![String::from("GIT_DIR")];
+
+        let script = render_sh(&[apt, git, git_gc]);
+        let body: Vec<&str> = script.lines().skip(2).collect();
+        assert_eq!(
+            body,
+            [
+                "",
+                "# System update",
+                "/usr/bin/sudo /usr/bin/apt dist-upgrade",
+                "",
+                "# Git repositories",
+                "(cd '/home/me/my repo' && GIT_TERMINAL_PROMPT=0 git pull --ff-only)",
+                "(unset GIT_DIR && git gc)",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_render_json() {
+        let mut apt = command("System update", "/usr/bin/sudo", &["/usr/bin/apt", "dist-upgrade"]);
+        apt.sudo = Some(SudoKind::Sudo);
+
+        let json: serde_json::Value = serde_json::from_str(&render(&[apt], PlanFormat::Json).unwrap()).unwrap();
+        assert_eq!(json["schema_version"], SCHEMA_VERSION);
+        let command = &json["commands"][0];
+        assert_eq!(command["step"], "System update");
+        assert_eq!(command["args"][1], "dist-upgrade");
+        assert_eq!(command["sudo"], "sudo");
+        assert!(command.get("cwd").is_none());
+    }
+}
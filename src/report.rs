@@ -0,0 +1,259 @@
+//! Machine-readable reports of a Topgrade run.
+//!
+//! The human-readable summary printed at the end of a run is translated and
+//! coloured, which makes it unsuitable for scripts. The report produced here
+//! has a stable schema instead, see `SCHEMA_VERSION`.
+
+use std::borrow::Cow;
+use std::fs;
+use std::io::{self, Write};
+use std::path::Path;
+use std::time::Duration;
+
+use chrono::{DateTime, Local};
+use clap::ValueEnum;
+use color_eyre::eyre::{Context, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::runner::StepReport;
+use crate::utils::hostname;
+
+/// Version of the report schema, bumped on incompatible changes.
+pub const SCHEMA_VERSION: u32 = 1;
+
+/// Set on remote runs to make them print their report for the local Topgrade, see `EmbeddedReport`.
+pub const REMOTE_REPORT_ENV: &str = "TOPGRADE_REMOTE_REPORT";
+
+const REPORT_START: &[u8] = b"-----BEGIN TOPGRADE REPORT-----";
+const REPORT_END: &[u8] = b"-----END TOPGRADE REPORT-----";
+
+/// The format a run report is written in.
+#[derive(Clone, Copy, Debug, Default, ValueEnum)]
+#[clap(rename_all = "snake_case")]
+pub enum ReportFormat {
+    #[default]
+    Json,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct RunReport<'a> {
+    pub schema_version: u32,
+    pub topgrade_version: Cow<'a, str>,
+    pub hostname: Option<String>,
+    pub started: DateTime<Local>,
+    pub finished: DateTime<Local>,
+    pub duration_secs: f64,
+    /// Whether any of the steps failed.
+    pub failed: bool,
+    pub steps: Cow<'a, [StepReport<'a>]>,
+}
+
+impl<'a> RunReport<'a> {
+    pub fn new(started: DateTime<Local>, steps: &'a [StepReport<'a>]) -> Self {
+        let finished = Local::now();
+        Self {
+            schema_version: SCHEMA_VERSION,
+            topgrade_version: Cow::Borrowed(env!("CARGO_PKG_VERSION")),
+            hostname: hostname().ok(),
+            started,
+            finished,
+            duration_secs: (finished - started).to_std().unwrap_or_default().as_secs_f64(),
+            failed: steps.iter().any(|s| s.result.failed()),
+            steps: Cow::Borrowed(steps),
+        }
+    }
+
+    /// Wall-clock time of the whole run.
+    pub fn duration(&self) -> Duration {
+        Duration::try_from_secs_f64(self.duration_secs).unwrap_or_default()
+    }
+
+    fn render(&self, format: ReportFormat) -> Result<String> {
+        match format {
+            ReportFormat::Json => serde_json::to_string_pretty(self).context("Failed to serialize the run report"),
+        }
+    }
+
+    /// Print the report between markers, so it can be told apart from the rest of the output.
+    pub fn write_embedded(&self) -> Result<()> {
+        let json = serde_json::to_string(self).context("Failed to serialize the run report")?;
+        let mut stdout = io::stdout().lock();
+        stdout.write_all(REPORT_START)?;
+        writeln!(stdout, "\n{json}")?;
+        stdout.write_all(REPORT_END)?;
+        writeln!(stdout).context("Failed to write the run report to stdout")
+    }
+
+    /// Write the report to `path`.
+    pub fn write(&self, format: ReportFormat, path: &Path) -> Result<()> {
+        let contents = self.render(format)?;
+        fs::write(path, contents + "\n")
+            .with_context(|| format!("Failed to write the run report to {}", path.display()))
+    }
+}
+
+/// Separates a report printed by `RunReport::write_embedded` from the output around it.
+///
+/// The output is fed in chunks as it arrives. Everything but the report is
+/// passed on right away, except for the end of a chunk that could be the start
+/// of a marker.
+#[derive(Default)]
+pub struct EmbeddedReport {
+    pending: Vec<u8>,
+    in_report: bool,
+    report: Vec<u8>,
+}
+
+impl EmbeddedReport {
+    /// Feed the next chunk of output, returning the part of it to show.
+    pub fn feed(&mut self, data: &[u8]) -> Vec<u8> {
+        self.pending.extend_from_slice(data);
+        let mut output = Vec::new();
+        loop {
+            let marker = if self.in_report { REPORT_END } else { REPORT_START };
+            let consumed = match find(&self.pending, marker) {
+                Some(position) => position + marker.len(),
+                None => self.pending.len() - partial_marker_len(&self.pending, marker),
+            };
+            let content = &self.pending[..consumed];
+            let found = find(content, marker).is_some();
+            let content = if found {
+                &content[..content.len() - marker.len()]
+            } else {
+                content
+            };
+            if self.in_report {
+                self.report.extend_from_slice(content);
+            } else {
+                output.extend_from_slice(content);
+            }
+            self.pending.drain(..consumed);
+
+            if !found {
+                return output;
+            }
+            if self.in_report {
+                // The line break after the end marker is part of the report.
+                let line_break = [&b"\r\n"[..], b"\n"]
+                    .into_iter()
+                    .find(|line_break| self.pending.starts_with(line_break));
+                self.pending.drain(..line_break.map_or(0, <[u8]>::len));
+            }
+            self.in_report = !self.in_report;
+        }
+    }
+
+    /// The output that was held back, and the report if a complete one was found.
+    pub fn finish(mut self) -> (Vec<u8>, Option<RunReport<'static>>) {
+        if self.in_report {
+            // Without an end marker this was not a report after all.
+            let mut output = REPORT_START.to_vec();
+            output.append(&mut self.report);
+            output.append(&mut self.pending);
+            return (output, None);
+        }
+        if self.report.is_empty() {
+            return (self.pending, None);
+        }
+
+        let report = serde_json::from_slice(&self.report)
+            .inspect_err(|e| tracing::warn!("Ignoring an invalid remote report: {e}"))
+            .ok();
+        (self.pending, report)
+    }
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack.windows(needle.len()).position(|window| window == needle)
+}
+
+/// The length of the longest end of `data` that `marker` starts with.
+fn partial_marker_len(data: &[u8], marker: &[u8]) -> usize {
+    (1..marker.len().min(data.len() + 1))
+        .rev()
+        .find(|len| data.ends_with(&marker[..*len]))
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+    use crate::runner::StepResult;
+    use crate::step::Step;
+
+    fn step_report(step: Step, key: &'static str, result: StepResult) -> StepReport<'static> {
+        StepReport::for_test(step, key, result, Duration::from_secs(3))
+    }
+
+    #[test]
+    fn test_json_report_schema() {
+        let mut failed = step_report(Step::Cargo, "cargo", StepResult::Failure);
+        failed.attempts = 2;
+        failed.attempt_durations = vec![Duration::from_millis(1500), Duration::from_millis(2500)];
+        failed.errors = vec!["Command failed: `cargo install-update`".into(), "exit status: 1".into()];
+        let steps = [
+            step_report(Step::System, "System update", StepResult::Success),
+            step_report(
+                Step::Pipx,
+                "pipx",
+                StepResult::Skipped("Cannot find pipx in PATH".into()),
+            ),
+            failed,
+        ];
+
+        let json: serde_json::Value =
+            serde_json::from_str(&RunReport::new(Local::now(), &steps).render(ReportFormat::Json).unwrap()).unwrap();
+
+        assert_eq!(json["schema_version"], SCHEMA_VERSION);
+        assert_eq!(json["failed"], true);
+
+        let steps = json["steps"].as_array().unwrap();
+        assert_eq!(steps[0]["step"], "system");
+        assert_eq!(steps[0]["key"], "System update");
+        assert_eq!(steps[0]["status"], "success");
+        assert!(steps[0].get("reason").is_none());
+
+        assert_eq!(steps[1]["status"], "skipped");
+        assert_eq!(steps[1]["reason"], "Cannot find pipx in PATH");
+
+        assert_eq!(steps[2]["status"], "failure");
+        assert_eq!(steps[2]["attempts"], 2);
+        assert_eq!(steps[2]["attempt_durations_secs"], serde_json::json!([1.5, 2.5]));
+        assert_eq!(steps[2]["duration_secs"], 3.0);
+        assert_eq!(steps[2]["errors"][1], "exit status: 1");
+        assert!(steps[2]["started"].is_string());
+    }
+
+    #[test]
+    fn test_embedded_report() {
+        let steps = [step_report(Step::Cargo, "cargo", StepResult::Failure)];
+        let json = serde_json::to_string(&RunReport::new(Local::now(), &steps)).unwrap();
+        let output =
+            format!("updating\n-----BEGIN TOPGRADE REPORT-----\n{json}\n-----END TOPGRADE REPORT-----\ndone\n-----BEG");
+
+        // Split the output in chunks ending in the middle of the markers.
+        let mut filter = EmbeddedReport::default();
+        let mut shown = Vec::new();
+        for chunk in output.as_bytes().chunks(7) {
+            shown.extend(filter.feed(chunk));
+        }
+        let (rest, report) = filter.finish();
+        shown.extend(rest);
+
+        assert_eq!(String::from_utf8(shown).unwrap(), "updating\ndone\n-----BEG");
+        let report = report.unwrap();
+        assert!(report.failed);
+        assert_eq!(report.steps[0].key, "cargo");
+    }
+
+    #[test]
+    fn test_embedded_report_missing() {
+        let mut filter = EmbeddedReport::default();
+        let mut shown = filter.feed(b"old remote\n-----BEGIN TOPGRADE REPORT-----\n{");
+        let (rest, report) = filter.finish();
+        shown.extend(rest);
+        assert_eq!(shown, b"old remote\n-----BEGIN TOPGRADE REPORT-----\n{");
+        assert!(report.is_none());
+    }
+}
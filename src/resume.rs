@@ -0,0 +1,165 @@
+//! Resuming an interrupted run with `--resume`.
+//!
+//! While steps run, the keys of the ones that succeeded are saved to a state
+//! file in Topgrade's data directory. The file is removed when a run gets to
+//! its end, so one that is left behind belongs to a run that was interrupted,
+//! and `--resume` skips the steps listed in it.
+
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use chrono::{DateTime, Local};
+use color_eyre::eyre::{Context, Result};
+use rust_i18n::t;
+use serde::{Deserialize, Serialize};
+use tracing::{debug, warn};
+
+use crate::breaking_changes::topgrade_data_dir;
+use crate::terminal::print_info;
+
+/// The progress of a run, as saved in the state file.
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct State {
+    started: Option<DateTime<Local>>,
+    /// The keys of the steps that succeeded, in the order they finished.
+    completed: Vec<String>,
+}
+
+struct Progress {
+    path: PathBuf,
+    state: State,
+    /// Steps that succeeded in the interrupted run being resumed.
+    skip: HashSet<String>,
+}
+
+/// The progress of this run, if it is tracked.
+static PROGRESS: Mutex<Option<Progress>> = Mutex::new(None);
+
+fn state_file_path() -> PathBuf {
+    topgrade_data_dir().join("resume.json")
+}
+
+fn load(path: &Path) -> Result<Option<State>> {
+    if !path.exists() {
+        return Ok(None);
+    }
+    let contents =
+        fs::read_to_string(path).with_context(|| format!("Failed to read the resume state at {}", path.display()))?;
+    let state = serde_json::from_str(&contents)
+        .with_context(|| format!("Failed to parse the resume state at {}", path.display()))?;
+    Ok(Some(state))
+}
+
+fn save(path: &Path, state: &State) -> Result<()> {
+    fs::create_dir_all(topgrade_data_dir())?;
+    let contents = serde_json::to_string_pretty(state)?;
+    fs::write(path, contents + "\n").with_context(|| format!("Failed to save the resume state to {}", path.display()))
+}
+
+/// Start tracking the progress of this run, skipping the steps that already
+/// succeeded in the interrupted run if `resume` is set.
+pub fn start(resume: bool, started: DateTime<Local>) -> Result<()> {
+    start_at(state_file_path(), resume, started)
+}
+
+fn start_at(path: PathBuf, resume: bool, started: DateTime<Local>) -> Result<()> {
+    let previous = if resume { load(&path)? } else { None };
+
+    let state = match previous {
+        Some(previous) => {
+            print_info(t!(
+                "Resuming the run started at {started}, skipping {count} steps that already succeeded",
+                started = previous
+                    .started
+                    .map(|started| started.format("%Y-%m-%d %H:%M:%S").to_string())
+                    .unwrap_or_default(),
+                count = previous.completed.len()
+            ));
+            previous
+        }
+        None => {
+            if resume {
+                print_info(t!("There is no interrupted run to resume, running every step"));
+            }
+            State {
+                started: Some(started),
+                completed: Vec::new(),
+            }
+        }
+    };
+
+    save(&path, &state)?;
+    *PROGRESS.lock().unwrap() = Some(Progress {
+        path,
+        skip: state.completed.iter().cloned().collect(),
+        state,
+    });
+    Ok(())
+}
+
+/// Whether the step with the given key succeeded in the run being resumed.
+pub fn already_succeeded(key: &str) -> bool {
+    PROGRESS
+        .lock()
+        .unwrap()
+        .as_ref()
+        .is_some_and(|progress| progress.skip.contains(key))
+}
+
+/// Save that the step with the given key succeeded.
+pub fn step_succeeded(key: &str) {
+    let mut progress = PROGRESS.lock().unwrap();
+    let Some(progress) = progress.as_mut() else {
+        return;
+    };
+    if progress.state.completed.iter().any(|completed| completed == key) {
+        return;
+    }
+
+    progress.state.completed.push(key.to_string());
+    if let Err(e) = save(&progress.path, &progress.state) {
+        warn!("{e:?}");
+    }
+}
+
+/// Forget the progress once the run got to its end, there is nothing left to resume.
+pub fn finish() {
+    if let Some(progress) = PROGRESS.lock().unwrap().take() {
+        debug!("Removing the resume state at {}", progress.path.display());
+        if let Err(e) = fs::remove_file(&progress.path) {
+            warn!("Failed to remove the resume state at {}: {e}", progress.path.display());
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_resume() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("resume.json");
+
+        start_at(path.clone(), false, Local::now()).unwrap();
+        step_succeeded("System update");
+        step_succeeded("Git repositories");
+        step_succeeded("System update");
+        assert!(!already_succeeded("System update"));
+
+        // The run is interrupted, the next one resumes it.
+        start_at(path.clone(), true, Local::now()).unwrap();
+        assert!(already_succeeded("System update"));
+        assert!(already_succeeded("Git repositories"));
+        assert!(!already_succeeded("cargo"));
+        step_succeeded("cargo");
+        let state = load(&path).unwrap().unwrap();
+        assert_eq!(state.completed, ["System update", "Git repositories", "cargo"]);
+
+        finish();
+        assert!(!path.exists());
+        assert!(!already_succeeded("System update"));
+    }
+}
@@ -1,19 +1,35 @@
+use chrono::{DateTime, Local};
 use color_eyre::eyre::{Result, WrapErr};
 use rust_i18n::t;
+use serde::{Deserialize, Serialize};
 use std::borrow::Cow;
-use std::fmt::Debug;
+use std::cell::RefCell;
+use std::fmt::{self, Debug};
 use std::io;
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
 use tracing::debug;
 
 use crate::ctrlc;
 use crate::error::{DryRun, MissingSudo, SkipStep};
 use crate::execution_context::ExecutionContext;
+use crate::notify;
+use crate::plan;
+use crate::resume;
+use crate::schedule;
 use crate::step::Step;
-use crate::terminal::{ShouldRetry, print_error, print_warning, should_retry};
+use crate::step_config;
+use crate::step_log;
+use crate::terminal::{ShouldRetry, format_duration, print_error, print_line, print_warning, should_retry};
+use crate::watchdog;
 
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(tag = "status", content = "reason", rename_all = "snake_case")]
 pub enum StepResult {
     Success,
     Failure,
+    /// The step was killed after running for longer than its timeout.
+    TimedOut,
     Ignored,
     SkippedMissingSudo,
     Skipped(String),
@@ -25,7 +41,7 @@ impl StepResult {
 
         match self {
             Success | Ignored | Skipped(_) | SkippedMissingSudo => false,
-            Failure => true,
+            Failure | TimedOut => true,
         }
     }
 }
@@ -36,11 +52,185 @@ enum RetryDecision {
     Continue(StepResult),
 }
 
-type Report<'a> = Vec<(Cow<'a, str>, StepResult)>;
+/// The outcome of a single `Runner::execute` call.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct StepReport<'a> {
+    pub step: Step,
+    pub key: Cow<'a, str>,
+    #[serde(flatten)]
+    pub result: StepResult,
+    pub started: DateTime<Local>,
+    pub finished: DateTime<Local>,
+    /// Wall-clock time between the start of the first attempt and the final result,
+    /// including any time spent at the retry prompt.
+    #[serde(rename = "duration_secs", with = "secs")]
+    pub duration: Duration,
+    /// Number of times the step was run, including retries.
+    pub attempts: u16,
+    /// How long each attempt took, in seconds.
+    #[serde(rename = "attempt_durations_secs", with = "secs_list")]
+    pub attempt_durations: Vec<Duration>,
+    /// The error chain of the last failed attempt, outermost context first.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub errors: Vec<String>,
+    /// The file the output of the step was logged to, see `misc.step_logs`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub log: Option<PathBuf>,
+    /// The updates found in `--check` mode.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub updates: Vec<PendingUpdate>,
+    /// Something the step points out in the summary, e.g. the snapshot it created.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub note: Option<String>,
+}
+
+impl StepReport<'_> {
+    /// Detach the report from the runner it was created by.
+    pub fn into_owned(self) -> StepReport<'static> {
+        StepReport {
+            step: self.step,
+            key: Cow::Owned(self.key.into_owned()),
+            result: self.result,
+            started: self.started,
+            finished: self.finished,
+            duration: self.duration,
+            attempts: self.attempts,
+            attempt_durations: self.attempt_durations,
+            errors: self.errors,
+            log: self.log,
+            updates: self.updates,
+            note: self.note,
+        }
+    }
+}
+
+#[cfg(test)]
+impl StepReport<'static> {
+    /// The report of a step that ran once for `duration`, finishing now.
+    pub fn for_test(step: Step, key: impl Into<Cow<'static, str>>, result: StepResult, duration: Duration) -> Self {
+        let now = Local::now();
+        StepReport {
+            step,
+            key: key.into(),
+            result,
+            started: now,
+            finished: now,
+            duration,
+            attempts: 1,
+            attempt_durations: vec![duration],
+            errors: Vec::new(),
+            log: None,
+            updates: Vec::new(),
+            note: None,
+        }
+    }
+}
+
+/// An update that a step would install, found in `--check` mode.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PendingUpdate {
+    pub package: String,
+    /// The installed version, if the tool tells.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub current: Option<String>,
+    /// The version that would be installed, if the tool tells.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub available: Option<String>,
+}
+
+impl PendingUpdate {
+    pub fn new(package: impl Into<String>, current: Option<&str>, available: Option<&str>) -> Self {
+        Self {
+            package: package.into(),
+            current: current.map(String::from),
+            available: available.map(String::from),
+        }
+    }
+}
+
+impl fmt::Display for PendingUpdate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.package)?;
+        match (&self.current, &self.available) {
+            (Some(current), Some(available)) => write!(f, " {current} -> {available}"),
+            (None, Some(available)) => write!(f, " -> {available}"),
+            (Some(current), None) => write!(f, " {current}"),
+            (None, None) => Ok(()),
+        }
+    }
+}
+
+/// (De)serialize a `Duration` as fractional seconds.
+mod secs {
+    use serde::{Deserialize, Deserializer, Serializer};
+    use std::time::Duration;
+
+    pub fn serialize<S: Serializer>(duration: &Duration, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_f64(duration.as_secs_f64())
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Duration, D::Error> {
+        f64::deserialize(deserializer).map(|secs| Duration::try_from_secs_f64(secs).unwrap_or_default())
+    }
+}
+
+/// (De)serialize a list of `Duration`s as fractional seconds.
+mod secs_list {
+    use serde::{Deserialize, Deserializer, Serializer};
+    use std::time::Duration;
+
+    pub fn serialize<S: Serializer>(durations: &[Duration], serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(durations.iter().map(Duration::as_secs_f64))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<Duration>, D::Error> {
+        Ok(Vec::<f64>::deserialize(deserializer)?
+            .into_iter()
+            .map(|secs| Duration::try_from_secs_f64(secs).unwrap_or_default())
+            .collect())
+    }
+}
+
+type Report<'a> = Vec<StepReport<'a>>;
+
+/// Bookkeeping for a step that is still being executed.
+struct PendingStep {
+    step: Step,
+    started: DateTime<Local>,
+    attempt_durations: Vec<Duration>,
+    errors: Vec<String>,
+    updates: Vec<PendingUpdate>,
+    note: Option<String>,
+}
+
+/// What a step gives back to the runner when it succeeds.
+#[derive(Default)]
+struct StepOutput {
+    updates: Vec<PendingUpdate>,
+    note: Option<String>,
+    /// Don't report the success, see `Runner::collect`.
+    unreported: bool,
+}
+
+impl PendingStep {
+    fn new(step: Step) -> Self {
+        Self {
+            step,
+            started: Local::now(),
+            attempt_durations: Vec::new(),
+            errors: Vec::new(),
+            updates: Vec::new(),
+            note: None,
+        }
+    }
+}
 
 pub struct Runner<'a> {
     ctx: &'a ExecutionContext<'a>,
     report: Report<'a>,
+    started: DateTime<Local>,
+    /// Never prompt, used for steps running in parallel where there is no terminal to prompt on.
+    unattended: bool,
 }
 
 impl<'a> Runner<'a> {
@@ -48,6 +238,16 @@ impl<'a> Runner<'a> {
         Runner {
             ctx,
             report: Vec::new(),
+            started: Local::now(),
+            unattended: false,
+        }
+    }
+
+    /// A runner that never prompts, failed steps are reported as failures right away.
+    pub fn unattended(ctx: &'a ExecutionContext) -> Runner<'a> {
+        Runner {
+            unattended: true,
+            ..Runner::new(ctx)
         }
     }
 
@@ -55,29 +255,136 @@ impl<'a> Runner<'a> {
         &self,
         key: &str,
         error: &color_eyre::eyre::Error,
-        ignore_failure: bool,
+        failure: StepResult,
     ) -> Result<RetryDecision> {
         print_error(key, format!("{error:?}"));
         match should_retry(key)? {
             ShouldRetry::Yes => Ok(RetryDecision::Retry),
             ShouldRetry::Quit => Ok(RetryDecision::Quit),
-            ShouldRetry::No => Ok(RetryDecision::Continue(if ignore_failure {
-                StepResult::Ignored
-            } else {
-                StepResult::Failure
-            })),
+            ShouldRetry::No => Ok(RetryDecision::Continue(failure)),
         }
     }
 
-    fn push_result(&mut self, key: Cow<'a, str>, result: StepResult) {
-        debug_assert!(!self.report.iter().any(|(k, _)| k == &key), "{key} already reported");
-        self.report.push((key, result));
+    fn push_result(&mut self, pending: PendingStep, key: Cow<'a, str>, result: StepResult) {
+        debug_assert!(!self.report.iter().any(|r| r.key == key), "{key} already reported");
+        let finished = Local::now();
+        let log = step_log::path(&key);
+        match result {
+            StepResult::Success => {
+                schedule::step_succeeded(pending.step);
+                // Steps run by an unattended runner are saved once they are added to the main one.
+                if !self.unattended {
+                    resume::step_succeeded(&key);
+                }
+            }
+            // Failures that are ignored still mean the step has to run again.
+            StepResult::Failure | StepResult::TimedOut | StepResult::Ignored => schedule::step_failed(pending.step),
+            StepResult::Skipped(_) | StepResult::SkippedMissingSudo => (),
+        }
+        self.report.push(StepReport {
+            step: pending.step,
+            key,
+            result,
+            started: pending.started,
+            finished,
+            duration: (finished - pending.started).to_std().unwrap_or_default(),
+            attempts: pending.attempt_durations.len() as u16,
+            attempt_durations: pending.attempt_durations,
+            errors: pending.errors,
+            log,
+            updates: pending.updates,
+            note: pending.note,
+        });
+        notify::step_finished(self.ctx.config(), self.report.last().unwrap());
     }
 
     pub fn execute<K, F>(&mut self, step: Step, key: K, func: F) -> Result<()>
     where
         K: Into<Cow<'a, str>> + Debug,
         F: Fn() -> Result<()>,
+    {
+        self.run(step, key, false, || func().map(|()| StepOutput::default()))
+    }
+
+    /// Like `execute`, for a step whose failure doesn't count as a failure of the run,
+    /// whether or not `step` is in `misc.ignore_failures`.
+    pub fn execute_ignoring_failure<K, F>(&mut self, step: Step, key: K, func: F) -> Result<()>
+    where
+        K: Into<Cow<'a, str>> + Debug,
+        F: Fn() -> Result<()>,
+    {
+        self.run(step, key, true, || func().map(|()| StepOutput::default()))
+    }
+
+    /// Report `key` as skipped for `reason` without running it, e.g. when what it
+    /// depends on didn't succeed.
+    pub fn skip<K>(&mut self, step: Step, key: K, reason: String)
+    where
+        K: Into<Cow<'a, str>> + Debug,
+    {
+        self.push_result(PendingStep::new(step), key.into(), StepResult::Skipped(reason));
+    }
+
+    /// Like `execute`, for finding the parts of `step` that are then run one by one,
+    /// e.g. the containers to run Topgrade in.
+    ///
+    /// Failures and skips are reported as `key` and give no parts. Successes aren't
+    /// reported, so the parts are found again when resuming an interrupted run.
+    pub fn collect<K, T, F>(&mut self, step: Step, key: K, func: F) -> Result<Vec<T>>
+    where
+        K: Into<Cow<'a, str>> + Debug,
+        F: Fn() -> Result<Vec<T>>,
+    {
+        let parts = RefCell::new(Vec::new());
+        self.run(step, key, false, || {
+            *parts.borrow_mut() = func()?;
+            Ok(StepOutput {
+                unreported: true,
+                ..StepOutput::default()
+            })
+        })?;
+        Ok(parts.into_inner())
+    }
+
+    /// Like `execute`, for a step that returns a note to show in the summary.
+    pub fn execute_with_note<K, F>(&mut self, step: Step, key: K, func: F) -> Result<()>
+    where
+        K: Into<Cow<'a, str>> + Debug,
+        F: Fn() -> Result<String>,
+    {
+        self.run(step, key, false, || {
+            Ok(StepOutput {
+                note: Some(func()?),
+                ..StepOutput::default()
+            })
+        })
+    }
+
+    /// Like `execute`, for a step in `--check` mode that returns the updates it found.
+    pub fn check<K, F>(&mut self, step: Step, key: K, func: F) -> Result<()>
+    where
+        K: Into<Cow<'a, str>> + Debug,
+        F: Fn() -> Result<Vec<PendingUpdate>>,
+    {
+        self.run(step, key, false, || {
+            let updates = func()?;
+            if updates.is_empty() {
+                print_line(t!("No updates available"));
+            }
+            for update in &updates {
+                print_line(update.to_string());
+            }
+            Ok(StepOutput {
+                updates,
+                ..StepOutput::default()
+            })
+        })
+    }
+
+    fn run<K, F>(&mut self, step: Step, key: K, ignore_failure: bool, func: F) -> Result<()>
+    where
+        K: Into<Cow<'a, str>> + Debug,
+        F: Fn() -> Result<StepOutput>,
     {
         if !self.ctx.config().should_run(step) {
             return Ok(());
@@ -86,6 +393,25 @@ impl<'a> Runner<'a> {
         let key: Cow<'a, str> = key.into();
         debug!("Step {:?}", key);
 
+        if let Some(interval) = self.ctx.config().step_interval(step)
+            && !self.ctx.config().force()
+            && let Some(ago) = schedule::ran_within(step, interval)
+        {
+            debug!("Step {:?} is not due, it ran {:?} ago", key, ago);
+            let reason = t!("ran {ago} ago", ago = schedule::format_ago(ago)).to_string();
+            self.push_result(PendingStep::new(step), key, StepResult::Skipped(reason));
+            return Ok(());
+        }
+
+        if resume::already_succeeded(&key) {
+            debug!("Step {:?} already succeeded in the interrupted run", key);
+            if self.ctx.config().verbose() || self.ctx.config().show_skipped() {
+                let reason = t!("Already succeeded in the interrupted run").to_string();
+                self.push_result(PendingStep::new(step), key, StepResult::Skipped(reason));
+            }
+            return Ok(());
+        }
+
         // alter the `func` to put it in a span
         let func = || {
             let span =
@@ -96,47 +422,71 @@ impl<'a> Runner<'a> {
 
         // Total max attempts = 1 (initial) + auto_retry count
         let max_attempts = self.ctx.config().auto_retry().saturating_add(1);
+        let timeout = self.ctx.config().step_timeout(step);
+        let extra_args = self.ctx.config().step_extra_args(step);
 
         let mut attempt = 1;
+        let mut pending = PendingStep::new(step);
 
         loop {
-            match func() {
-                Ok(()) => {
-                    self.push_result(key, StepResult::Success);
+            let attempt_started = Instant::now();
+            let (result, timed_out) = step_config::run(step, extra_args, || {
+                step_log::run(&key, || plan::run(&key, || watchdog::run(timeout, func)))
+            });
+            pending.attempt_durations.push(attempt_started.elapsed());
+            match result {
+                Ok(output) if output.unreported => break,
+                Ok(output) => {
+                    pending.updates = output.updates;
+                    pending.note = output.note;
+                    self.push_result(pending, key, StepResult::Success);
                     break;
                 }
                 Err(e) if e.downcast_ref::<DryRun>().is_some() => break,
                 Err(e) if e.downcast_ref::<MissingSudo>().is_some() => {
                     print_warning(t!("Skipping step, sudo is required"));
-                    self.push_result(key, StepResult::SkippedMissingSudo);
+                    self.push_result(pending, key, StepResult::SkippedMissingSudo);
                     break;
                 }
                 Err(e) if e.downcast_ref::<SkipStep>().is_some() => {
                     if self.ctx.config().verbose() || self.ctx.config().show_skipped() {
-                        self.push_result(key, StepResult::Skipped(e.to_string()));
+                        self.push_result(pending, key, StepResult::Skipped(e.to_string()));
                     }
                     break;
                 }
                 Err(e) => {
+                    let e = match timeout {
+                        Some(timeout) if timed_out => {
+                            e.wrap_err(format!("Timed out after {}", format_duration(timeout)))
+                        }
+                        _ => e,
+                    };
                     debug!("Step {:?} failed: {:?}", key, e);
+                    pending.errors = e.chain().map(ToString::to_string).collect();
                     let interrupted = ctrlc::interrupted();
-                    if interrupted {
+                    // An unattended runner leaves the interruption for its owner to handle.
+                    if interrupted && !self.unattended {
                         ctrlc::unset_interrupted();
                     }
 
-                    let ignore_failure = self.ctx.config().ignore_failure(step);
+                    let ignore_failure = ignore_failure || self.ctx.config().ignore_failure(step);
+                    let failure = if timed_out {
+                        StepResult::TimedOut
+                    } else {
+                        StepResult::Failure
+                    };
 
                     // Decide whether to prompt the user
                     let has_auto_retries_left = attempt < max_attempts;
                     let should_prompt = if interrupted {
-                        // If interrupted, always prompt
-                        true
+                        // If interrupted, always prompt if there is someone to prompt
+                        !self.unattended
                     } else if has_auto_retries_left {
                         // If not interrupted, auto-retry if we want to
                         attempt += 1;
                         continue;
-                    } else if ignore_failure {
-                        // If ignore_failure, never prompt (except when interrupted)
+                    } else if ignore_failure || self.unattended {
+                        // If ignore_failure or unattended, never prompt (except when interrupted)
                         false
                     } else {
                         // Otherwise, prompt when we are configured to prompt
@@ -144,29 +494,27 @@ impl<'a> Runner<'a> {
                     };
 
                     if should_prompt {
-                        match self.handle_retry_prompt(&key, &e, ignore_failure)? {
+                        let result = if ignore_failure {
+                            StepResult::Ignored
+                        } else {
+                            failure.clone()
+                        };
+                        match self.handle_retry_prompt(&key, &e, result)? {
                             RetryDecision::Retry => {
                                 continue;
                             }
                             RetryDecision::Quit => {
-                                self.push_result(key, StepResult::Failure);
+                                self.push_result(pending, key, failure);
                                 return Err(io::Error::from(io::ErrorKind::Interrupted))
                                     .context("Quit from user input");
                             }
                             RetryDecision::Continue(result) => {
-                                self.push_result(key, result);
+                                self.push_result(pending, key, result);
                                 break;
                             }
                         }
                     } else {
-                        self.push_result(
-                            key,
-                            if ignore_failure {
-                                StepResult::Ignored
-                            } else {
-                                StepResult::Failure
-                            },
-                        );
+                        self.push_result(pending, key, if ignore_failure { StepResult::Ignored } else { failure });
                         break;
                     }
                 }
@@ -179,4 +527,79 @@ impl<'a> Runner<'a> {
     pub fn report(&self) -> &Report<'_> {
         &self.report
     }
+
+    pub fn into_report(self) -> Report<'a> {
+        self.report
+    }
+
+    /// Add the results of steps that were run by another runner.
+    pub fn extend_report(&mut self, report: Report<'a>) {
+        for step_report in &report {
+            if let StepResult::Success = step_report.result {
+                resume::step_succeeded(&step_report.key);
+            }
+        }
+        self.report.extend(report);
+    }
+
+    /// When this runner was created, i.e. the start of the run.
+    pub fn started(&self) -> DateTime<Local> {
+        self.started
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use color_eyre::eyre::eyre;
+
+    use super::*;
+    use crate::config::test::config_from_toml;
+    use crate::execution_context::RunType;
+
+    /// The report of the steps `run` runs with a configuration read from `toml`.
+    fn report(toml: &str, run: impl FnOnce(&mut Runner)) -> Vec<StepReport<'static>> {
+        let config = config_from_toml(toml);
+        #[cfg(target_os = "linux")]
+        let distribution = Err(eyre!("Not detected in tests"));
+        let ctx = ExecutionContext::new(
+            RunType::Wet,
+            None,
+            &config,
+            #[cfg(target_os = "linux")]
+            &distribution,
+        );
+        let mut runner = Runner::unattended(&ctx);
+        run(&mut runner);
+        runner.into_report().into_iter().map(StepReport::into_owned).collect()
+    }
+
+    #[test]
+    fn test_collect_only_reports_failures_and_skips() {
+        let report = report("[misc]\nshow_skipped = true\n", |runner| {
+            let found = runner.collect(Step::ContainerExec, "found", || Ok(vec!["a", "b"]));
+            assert_eq!(found.unwrap(), ["a", "b"]);
+            let skipped = runner.collect(Step::ContainerExec, "skipped", || -> Result<Vec<&str>> {
+                Err(SkipStep(String::from("Nothing to run in")).into())
+            });
+            assert!(skipped.unwrap().is_empty());
+            let failed = runner.collect(Step::ContainerExec, "failed", || -> Result<Vec<&str>> {
+                Err(eyre!("broken"))
+            });
+            assert!(failed.unwrap().is_empty());
+        });
+
+        let keys: Vec<&str> = report.iter().map(|report| report.key.as_ref()).collect();
+        assert_eq!(keys, ["skipped", "failed"]);
+        assert!(matches!(&report[0].result, StepResult::Skipped(reason) if reason == "Nothing to run in"));
+        assert!(matches!(report[1].result, StepResult::Failure));
+    }
+
+    #[test]
+    fn test_skip_is_reported() {
+        let report = report("", |runner| {
+            runner.skip(Step::System, "System update", String::from("No snapshot"));
+        });
+        assert_eq!(report[0].key, "System update");
+        assert!(matches!(&report[0].result, StepResult::Skipped(reason) if reason == "No snapshot"));
+    }
 }
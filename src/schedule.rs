@@ -0,0 +1,232 @@
+//! Running steps only when they are due, see `misc.intervals`.
+//!
+//! The time every step last succeeded is saved to a file in Topgrade's data
+//! directory. A step with an interval is skipped until that long has passed
+//! since then, unless `--force` is given.
+//!
+//! A step runs under several keys at times, e.g. the system update and the
+//! snapshots around it, or every remote host. It only counts as succeeded if
+//! none of them failed in this run.
+
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::Duration;
+
+use chrono::{DateTime, Local};
+use color_eyre::eyre::{Context, Result, bail};
+use serde::{Deserialize, Serialize};
+use tracing::{debug, warn};
+
+use crate::breaking_changes::topgrade_data_dir;
+use crate::step::Step;
+
+/// How long to wait before running a step again, e.g. `12h` or `7d`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct Interval(pub Duration);
+
+impl TryFrom<String> for Interval {
+    type Error = color_eyre::eyre::Error;
+
+    fn try_from(value: String) -> Result<Self> {
+        let value = value.trim();
+        let split = value.find(|c: char| !c.is_ascii_digit()).unwrap_or(value.len());
+        let (number, unit) = value.split_at(split);
+        let Ok(number) = number.parse::<u64>() else {
+            bail!("Invalid interval `{value}`, expected a number followed by s, m, h, d or w, e.g. `7d`");
+        };
+        let secs = match unit.trim() {
+            "s" => 1,
+            "m" => 60,
+            "h" => 60 * 60,
+            "d" => 24 * 60 * 60,
+            "w" => 7 * 24 * 60 * 60,
+            _ => bail!("Invalid unit in interval `{value}`, expected s, m, h, d or w"),
+        };
+        Ok(Self(Duration::from_secs(number * secs)))
+    }
+}
+
+impl From<Interval> for String {
+    fn from(interval: Interval) -> Self {
+        interval.to_string()
+    }
+}
+
+impl fmt::Display for Interval {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let secs = self.0.as_secs();
+        let (count, unit) = [(7 * 24 * 60 * 60, "w"), (24 * 60 * 60, "d"), (60 * 60, "h"), (60, "m")]
+            .into_iter()
+            .find(|(unit_secs, _)| secs >= *unit_secs && secs.is_multiple_of(*unit_secs))
+            .map_or((secs, "s"), |(unit_secs, unit)| (secs / unit_secs, unit));
+        write!(f, "{count}{unit}")
+    }
+}
+
+/// How long ago something happened, in the largest unit that fits, e.g. `2d` or `5h`.
+pub fn format_ago(duration: Duration) -> String {
+    let secs = duration.as_secs();
+    match secs {
+        0..60 => format!("{secs}s"),
+        60..3600 => format!("{}m", secs / 60),
+        3600..86400 => format!("{}h", secs / 3600),
+        _ => format!("{}d", secs / 86400),
+    }
+}
+
+struct Store {
+    path: PathBuf,
+    /// When each step last succeeded, as of the start of this run.
+    previous: HashMap<Step, DateTime<Local>>,
+    /// The same, updated with the steps that succeeded in this run.
+    current: HashMap<Step, DateTime<Local>>,
+    /// The steps with a key that failed in this run.
+    failed: HashSet<Step>,
+}
+
+/// The last-success store, if this run keeps track of it.
+static STORE: Mutex<Option<Store>> = Mutex::new(None);
+
+fn store_file_path() -> PathBuf {
+    topgrade_data_dir().join("last_success.json")
+}
+
+fn load(path: &Path) -> Result<HashMap<Step, DateTime<Local>>> {
+    if !path.exists() {
+        return Ok(HashMap::new());
+    }
+    let contents = fs::read_to_string(path)
+        .with_context(|| format!("Failed to read the last successful runs at {}", path.display()))?;
+    serde_json::from_str(&contents)
+        .with_context(|| format!("Failed to parse the last successful runs at {}", path.display()))
+}
+
+/// Start keeping track of when steps succeed.
+pub fn start() -> Result<()> {
+    start_at(store_file_path())
+}
+
+fn start_at(path: PathBuf) -> Result<()> {
+    let previous = load(&path)?;
+    *STORE.lock().unwrap() = Some(Store {
+        path,
+        current: previous.clone(),
+        previous,
+        failed: HashSet::new(),
+    });
+    Ok(())
+}
+
+/// How long ago `step` last succeeded, if that was less than `interval` ago.
+///
+/// Only runs before this one count, so a step with several parts runs all of them.
+pub fn ran_within(step: Step, interval: Interval) -> Option<Duration> {
+    let store = STORE.lock().unwrap();
+    let last = *store.as_ref()?.previous.get(&step)?;
+    let ago = (Local::now() - last).to_std().unwrap_or_default();
+    (ago < interval.0).then_some(ago)
+}
+
+/// Save that a key of `step` succeeded just now, unless another one failed in this run.
+pub fn step_succeeded(step: Step) {
+    let mut store = STORE.lock().unwrap();
+    let Some(store) = store.as_mut() else {
+        return;
+    };
+    if store.failed.contains(&step) {
+        return;
+    }
+
+    store.current.insert(step, Local::now());
+    if let Err(e) = save(&store.path, &store.current) {
+        warn!("{e:?}");
+    }
+}
+
+/// Save that a key of `step` failed, so it doesn't count as succeeded in this run.
+pub fn step_failed(step: Step) {
+    let mut store = STORE.lock().unwrap();
+    let Some(store) = store.as_mut() else {
+        return;
+    };
+    if !store.failed.insert(step) || store.current.get(&step) == store.previous.get(&step) {
+        return;
+    }
+
+    // Forget the success of one of its other keys.
+    match store.previous.get(&step) {
+        Some(last) => store.current.insert(step, *last),
+        None => store.current.remove(&step),
+    };
+    if let Err(e) = save(&store.path, &store.current) {
+        warn!("{e:?}");
+    }
+}
+
+fn save(path: &Path, last_success: &HashMap<Step, DateTime<Local>>) -> Result<()> {
+    debug!("Saving the last successful runs to {}", path.display());
+    fs::create_dir_all(topgrade_data_dir())?;
+    let contents = serde_json::to_string_pretty(last_success)?;
+    fs::write(path, contents + "\n")
+        .with_context(|| format!("Failed to save the last successful runs to {}", path.display()))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn interval(value: &str) -> Result<Interval> {
+        Interval::try_from(value.to_string())
+    }
+
+    #[test]
+    fn test_parse_interval() {
+        assert_eq!(interval("7d").unwrap().0, Duration::from_secs(7 * 24 * 3600));
+        assert_eq!(interval("12h").unwrap().0, Duration::from_secs(12 * 3600));
+        assert_eq!(interval("90 m").unwrap().0, Duration::from_secs(90 * 60));
+        assert_eq!(interval("2w").unwrap().to_string(), "2w");
+        assert_eq!(interval("90m").unwrap().to_string(), "90m");
+        assert!(interval("7").is_err());
+        assert!(interval("d").is_err());
+        assert!(interval("7y").is_err());
+    }
+
+    #[test]
+    fn test_format_ago() {
+        assert_eq!(format_ago(Duration::from_secs(42)), "42s");
+        assert_eq!(format_ago(Duration::from_secs(5 * 3600 + 59)), "5h");
+        assert_eq!(format_ago(Duration::from_secs(2 * 86400 + 3600)), "2d");
+    }
+
+    #[test]
+    fn test_ran_within() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("last_success.json");
+        let two_days_ago = Local::now() - chrono::Duration::days(2);
+        fs::write(&path, format!("{{\"containers\": \"{}\"}}", two_days_ago.to_rfc3339())).unwrap();
+
+        start_at(path.clone()).unwrap();
+        let ago = ran_within(Step::Containers, interval("7d").unwrap()).unwrap();
+        assert_eq!(format_ago(ago), "2d");
+        assert_eq!(ran_within(Step::Containers, interval("1d").unwrap()), None);
+        assert_eq!(ran_within(Step::Tldr, interval("1d").unwrap()), None);
+
+        // Steps that succeed in this run are saved, but still run in this run.
+        step_succeeded(Step::Tldr);
+        assert_eq!(ran_within(Step::Tldr, interval("1d").unwrap()), None);
+        assert!(load(&path).unwrap().contains_key(&Step::Tldr));
+
+        // A step fails if any of its keys fails, in whatever order.
+        step_succeeded(Step::System);
+        step_failed(Step::System);
+        step_succeeded(Step::System);
+        assert!(!load(&path).unwrap().contains_key(&Step::System));
+        step_failed(Step::Containers);
+        step_succeeded(Step::Containers);
+        assert_eq!(load(&path).unwrap()[&Step::Containers], two_days_ago);
+    }
+}
@@ -1,10 +1,11 @@
+use crate::config::{Config, CustomCommand, Remote};
 use crate::execution_context::ExecutionContext;
 use crate::runner::Runner;
 use clap::ValueEnum;
 use color_eyre::Result;
 #[cfg(target_os = "linux")]
 use rust_i18n::t;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use strum::{EnumCount, EnumIter, EnumString, VariantNames};
 
 #[cfg(feature = "self-update")]
@@ -16,7 +17,19 @@ use crate::utils::hostname;
 pub const DEPRECATED_STEPS: [Step; 1] = [Step::NixHelper];
 
 #[derive(
-    ValueEnum, EnumString, VariantNames, Debug, Clone, PartialEq, Eq, Hash, Deserialize, EnumIter, Copy, EnumCount,
+    ValueEnum,
+    EnumString,
+    VariantNames,
+    Debug,
+    Clone,
+    PartialEq,
+    Eq,
+    Hash,
+    Deserialize,
+    Serialize,
+    EnumIter,
+    Copy,
+    EnumCount,
 )]
 #[clap(rename_all = "snake_case")]
 #[serde(rename_all = "snake_case")]
@@ -51,6 +64,7 @@ pub enum Step {
     Composer,
     Conda,
     ConfigUpdate,
+    ContainerExec,
     Containers,
     Cursor,
     CursorAgent,
@@ -141,6 +155,7 @@ pub enum Step {
     Pkgin,
     Pkgit,
     PlatformioCore,
+    Plugins,
     Pnpm,
     Poetry,
     Powershell,
@@ -200,8 +215,87 @@ pub enum Step {
 }
 
 impl Step {
-    #[expect(clippy::too_many_lines)]
+    /// The resource this step needs to itself when steps run in parallel, or
+    /// `None` if the step has to run on its own.
+    ///
+    /// Only steps that update user-level tooling without sudo are listed here.
+    /// Steps sharing a resource never run at the same time.
+    pub fn parallel_resource(self, config: &Config) -> Option<&'static str> {
+        use Step::*;
+
+        // Custom steps around it run in its place, and may need the terminal or sudo.
+        if config.has_commands_around(self) {
+            return None;
+        }
+
+        match self {
+            // `cargo install-update` builds with the toolchain `rustup` updates.
+            Rustup | Cargo => Some("rust"),
+            Elan => Some("elan"),
+            Ghcup => Some("ghcup"),
+            Go => Some("go"),
+            Juliaup => Some("juliaup"),
+            Pipx => Some("pipx"),
+            Pipxu => Some("pipxu"),
+            Uv => Some("uv"),
+            Vscode => Some("vscode"),
+            VscodeInsiders => Some("vscode_insiders"),
+            Vscodium => Some("vscodium"),
+            VscodiumInsiders => Some("vscodium_insiders"),
+            GitRepos => Some("git_repos"),
+            Containers if !config.containers_use_sudo() => Some("containers"),
+            _ => None,
+        }
+    }
+
+    /// List the updates of the step without installing them, see `--check`.
+    ///
+    /// Steps that can't tell what they would update are skipped.
+    fn check(&self, runner: &mut Runner, ctx: &ExecutionContext) -> Result<()> {
+        use Step::*;
+
+        match *self {
+            BrewFormula =>
+            {
+                #[cfg(any(target_os = "linux", target_os = "macos"))]
+                runner.check(*self, "Brew", || unix::check_brew_formula(ctx, unix::BrewVariant::Path))?
+            }
+            Cargo => runner.check(*self, "cargo", || generic::check_cargo(ctx))?,
+            Flatpak =>
+            {
+                #[cfg(target_os = "linux")]
+                runner.check(*self, "Flatpak", || linux::check_flatpak(ctx))?
+            }
+            Node => runner.check(*self, "npm", || node::check_npm(ctx))?,
+            Pipx => runner.check(*self, "pipx", || generic::check_pipx(ctx))?,
+            System =>
+            {
+                #[cfg(target_os = "linux")]
+                match ctx.distribution() {
+                    Ok(distribution) => runner.check(*self, "System update", || distribution.check(ctx))?,
+                    Err(e) => {
+                        println!("{}", t!("Error detecting current distribution: {error}", error = e));
+                    }
+                }
+            }
+            _ => (),
+        }
+
+        Ok(())
+    }
+
     pub fn run(&self, runner: &mut Runner, ctx: &ExecutionContext) -> Result<()> {
+        if ctx.run_type().check() {
+            return self.check(runner, ctx);
+        }
+
+        run_custom_commands(runner, ctx, |command| command.before == Some(*self))?;
+        self.run_step(runner, ctx)?;
+        run_custom_commands(runner, ctx, |command| command.after == Some(*self))
+    }
+
+    #[expect(clippy::too_many_lines)]
+    fn run_step(&self, runner: &mut Runner, ctx: &ExecutionContext) -> Result<()> {
         use Step::*;
 
         match *self {
@@ -310,21 +404,24 @@ impl Step {
                 #[cfg(target_os = "linux")]
                 runner.execute(*self, "config-update", || linux::run_config_update(ctx))?
             }
+            ContainerExec => {
+                let names = runner.collect(*self, "Containers to run Topgrade in", || {
+                    containers::collect_exec_in(ctx)
+                })?;
+                for name in names {
+                    runner.execute(*self, format!("Container ({name})"), || {
+                        containers::topgrade_in_container(ctx, &name)
+                    })?;
+                }
+            }
             Containers => runner.execute(*self, "Containers", || containers::run_containers(ctx))?,
             Cursor => runner.execute(*self, "Cursor extensions", || {
                 generic::run_cursor_extensions_update(ctx)
             })?,
             CursorAgent => runner.execute(*self, "Cursor Agent", || generic::run_cursor_agent(ctx))?,
-            CustomCommands => {
-                if let Some(commands) = ctx.config().commands() {
-                    for (name, command) in commands
-                        .iter()
-                        .filter(|(n, _)| ctx.config().should_run_custom_command(n))
-                    {
-                        runner.execute(*self, name.clone(), || generic::run_custom_command(name, command, ctx))?;
-                    }
-                }
-            }
+            CustomCommands => run_custom_commands(runner, ctx, |command| {
+                command.before.is_none() && command.after.is_none()
+            })?,
             DebGet =>
             {
                 #[cfg(target_os = "linux")]
@@ -548,6 +645,16 @@ impl Step {
                 runner.execute(*self, "pkgit", || linux::run_pkgit(ctx))?
             }
             PlatformioCore => runner.execute(*self, "PlatformIO Core", || generic::run_platform_io(ctx))?,
+            Plugins => {
+                if ctx.config().should_run(Plugins) {
+                    for plugin in plugins::discover(ctx) {
+                        runner.execute(*self, plugin.name.clone(), || {
+                            let description = plugins::describe(ctx, &plugin)?;
+                            plugins::run_plugin(ctx, &plugin, &description)
+                        })?;
+                    }
+                }
+            }
             Pnpm => runner.execute(*self, "pnpm", || node::run_pnpm_upgrade(ctx))?,
             Poetry => runner.execute(*self, "Poetry", || generic::run_poetry(ctx))?,
             Powershell => runner.execute(*self, "PowerShell Modules Update", || generic::run_powershell(ctx))?,
@@ -573,14 +680,20 @@ impl Step {
                 runner.execute(*self, "rcm", || unix::run_rcm(ctx))?
             }
             Remotes => {
-                if let Some(topgrades) = ctx.config().remote_topgrades() {
-                    for remote_topgrade in topgrades
-                        .iter()
-                        .filter(|t| ctx.config().should_execute_remote(hostname(), t))
-                    {
-                        runner.execute(*self, format!("Remote ({remote_topgrade})"), || {
-                            crate::ssh::ssh_step(ctx, remote_topgrade)
-                        })?;
+                let remotes: Vec<Remote> = ctx
+                    .config()
+                    .remotes()
+                    .into_iter()
+                    .filter(|remote| ctx.config().should_execute_remote(hostname(), &remote.host))
+                    .collect();
+                match ctx.config().remote_jobs() {
+                    Some(jobs) if ctx.config().should_run(*self) && !remotes.is_empty() => {
+                        crate::ssh::ssh_fanout(runner, ctx, &remotes, jobs)?
+                    }
+                    _ => {
+                        for remote in &remotes {
+                            crate::ssh::remote_step(runner, ctx, remote)?;
+                        }
                     }
                 }
             }
@@ -668,9 +781,12 @@ impl Step {
                     runner.execute(Shell, "packer.nu", || linux::run_packer_nu(ctx))?;
 
                     match ctx.distribution() {
-                        Ok(distribution) => {
-                            runner.execute(*self, "System update", || distribution.upgrade(ctx))?;
-                        }
+                        Ok(distribution) => match ctx.config().linux_snapshot() {
+                            Some(tool) => {
+                                snapshot::run_with_snapshots(runner, ctx, tool, || distribution.upgrade(ctx))?;
+                            }
+                            None => runner.execute(*self, "System update", || distribution.upgrade(ctx))?,
+                        },
                         Err(e) => {
                             println!("{}", t!("Error detecting current distribution: {error}", error = e));
                         }
@@ -786,6 +902,29 @@ impl Step {
     }
 }
 
+/// Run the `[commands]` entries selected by `filter`, each as its own step.
+fn run_custom_commands(
+    runner: &mut Runner,
+    ctx: &ExecutionContext,
+    filter: impl Fn(&CustomCommand) -> bool,
+) -> Result<()> {
+    let Some(commands) = ctx.config().commands() else {
+        return Ok(());
+    };
+    for (name, command) in commands
+        .iter()
+        .filter(|(name, command)| filter(command) && ctx.config().should_run_custom_command(name))
+    {
+        let run = || generic::run_custom_step(name, command, ctx);
+        if command.ignore_failure {
+            runner.execute_ignoring_failure(Step::CustomCommands, name.clone(), run)?;
+        } else {
+            runner.execute(Step::CustomCommands, name.clone(), run)?;
+        }
+    }
+    Ok(())
+}
+
 #[expect(clippy::too_many_lines)]
 pub(crate) fn default_steps() -> Vec<Step> {
     use Step::*;
@@ -908,6 +1047,7 @@ pub(crate) fn default_steps() -> Vec<Step> {
         VoltaPackages,
         VitePlus,
         Containers,
+        ContainerExec,
         Deno,
         Composer,
         Krew,
@@ -972,6 +1112,7 @@ pub(crate) fn default_steps() -> Vec<Step> {
         Typst,
         InstallRelease,
         Vagrant,
+        Plugins,
         // Steps that should run last
         // Last out of convention
         CustomCommands,
@@ -0,0 +1,193 @@
+//! `[steps.<step>]`: options that every step has, and the typed options of particular steps.
+//!
+//! Every step takes the options in `GENERIC_OPTIONS`. A step with options of its
+//! own declares them as a type implementing `StepOptions`, registered in
+//! `REGISTRY`, which validates its table and documents it in `--config-reference`.
+
+use std::cell::RefCell;
+use std::fmt::Write;
+
+use merge2::Merge;
+use serde::de::DeserializeOwned;
+use toml::{Table, Value};
+
+use crate::config::{ConfigFile, Mise, Zigup};
+use crate::step::Step;
+use crate::steps::plugins::PluginsOptions;
+
+/// An option of a `[steps.<step>]` table, for `--config-reference`.
+pub struct OptionDoc {
+    pub name: &'static str,
+    /// A value to show in the example line.
+    pub example: &'static str,
+    /// One or more lines of documentation.
+    pub description: &'static str,
+}
+
+/// The options a step reads from its `[steps.<step>]` table, besides the generic ones.
+pub trait StepOptions: DeserializeOwned + Default + Merge + Clone {
+    const STEP: Step;
+    const REFERENCE: &'static [OptionDoc];
+
+    /// The options set in the section the step used before it had a `[steps.<step>]` table.
+    ///
+    /// Options in `[steps.<step>]` take precedence over them.
+    fn legacy(_config: &ConfigFile) -> Option<&Self> {
+        None
+    }
+}
+
+pub const GENERIC_OPTIONS: &[OptionDoc] = &[
+    OptionDoc {
+        name: "enabled",
+        example: "false",
+        description: "Set to false to disable the step, like listing it in `misc.disable`.\n\
+                      Set to true to run it even if `misc.disable` lists it.",
+    },
+    OptionDoc {
+        name: "yes",
+        example: "true",
+        description: "Answer yes to the step's prompts, like `--yes <step>`",
+    },
+    OptionDoc {
+        name: "ignore_failure",
+        example: "true",
+        description: "Don't count a failure of the step as a failure of the run,\n\
+                      like listing it in `misc.ignore_failures`",
+    },
+    OptionDoc {
+        name: "extra_args",
+        example: r#"["--verbose"]"#,
+        description: "Arguments appended to the command the step updates with, e.g. `brew upgrade`.\n\
+                      Steps that run shell scripts or Topgrade itself don't take any.",
+    },
+    OptionDoc {
+        name: "env",
+        example: r#"{ HTTPS_PROXY = "http://proxy.example.com:3128" }"#,
+        description: "Environment variables for the commands the step runs, on top of the ones set\n\
+                      with `--env`. Like those, they are passed through sudo.",
+    },
+    OptionDoc {
+        name: "working_dir",
+        example: r#""~/src""#,
+        description: "The directory to run the step's commands in, unless the step picks one itself",
+    },
+];
+
+struct Registration {
+    step: Step,
+    check: fn(&Table) -> Result<(), toml::de::Error>,
+    reference: &'static [OptionDoc],
+}
+
+const fn register<T: StepOptions>() -> Registration {
+    Registration {
+        step: T::STEP,
+        check: check::<T>,
+        reference: T::REFERENCE,
+    }
+}
+
+fn check<T: StepOptions>(options: &Table) -> Result<(), toml::de::Error> {
+    parse::<T>(options).map(drop)
+}
+
+/// The steps that have options of their own.
+const REGISTRY: &[Registration] = &[register::<Mise>(), register::<PluginsOptions>(), register::<Zigup>()];
+
+/// Parse the options of a step from its table, without the generic options.
+pub fn parse<T: StepOptions>(options: &Table) -> Result<T, toml::de::Error> {
+    Value::Table(options.clone()).try_into()
+}
+
+/// Check the options of `step` that aren't generic ones.
+pub fn validate(step: Step, options: &Table) -> Result<(), String> {
+    match REGISTRY.iter().find(|registration| registration.step == step) {
+        Some(registration) => (registration.check)(options).map_err(|e| e.message().to_string()),
+        None => match options.keys().next() {
+            Some(key) => Err(format!("unknown option `{key}` for the {} step", name(step))),
+            None => Ok(()),
+        },
+    }
+}
+
+/// The part of `--config-reference` about `[steps.<step>]` tables.
+pub fn reference() -> String {
+    let mut output = String::from(
+        "\n\
+         # Every step can also be configured in a table named after it, the same name\n\
+         # as in `misc.disable`. These options are available for all steps.\n\
+         # [steps.<step>]\n",
+    );
+    write_options(&mut output, GENERIC_OPTIONS);
+
+    for registration in REGISTRY {
+        let step = name(registration.step);
+        writeln!(output, "\n# Options of the {step} step\n# [steps.{step}]").unwrap();
+        write_options(&mut output, registration.reference);
+    }
+    output
+}
+
+/// The name of `step` in the configuration.
+fn name(step: Step) -> String {
+    match serde_json::to_value(step) {
+        Ok(serde_json::Value::String(name)) => name,
+        _ => format!("{step:?}"),
+    }
+}
+
+fn write_options(output: &mut String, options: &[OptionDoc]) {
+    for option in options {
+        for line in option.description.lines() {
+            writeln!(output, "# {line}").unwrap();
+        }
+        writeln!(output, "# {} = {}", option.name, option.example).unwrap();
+    }
+}
+
+thread_local! {
+    /// The step running on this thread, and its `extra_args`.
+    static CURRENT: RefCell<Option<(Step, Vec<String>)>> = const { RefCell::new(None) };
+}
+
+/// Run `f` as `step`, whose update command gets `extra_args` appended, see `Executor::extra_args`.
+pub fn run<T>(step: Step, extra_args: &[String], f: impl FnOnce() -> T) -> T {
+    let previous = CURRENT.replace(Some((step, extra_args.to_vec())));
+    let value = f();
+    CURRENT.set(previous);
+    value
+}
+
+/// The step running on this thread, if any.
+pub fn current_step() -> Option<Step> {
+    CURRENT.with_borrow(|current| current.as_ref().map(|(step, _)| *step))
+}
+
+/// The `extra_args` of the step running on this thread.
+pub fn extra_args() -> Vec<String> {
+    CURRENT.with_borrow(|current| current.as_ref().map(|(_, args)| args.clone()).unwrap_or_default())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reference_is_valid() {
+        // Uncommenting the example lines gives a valid configuration.
+        let mut config = String::new();
+        for line in reference().lines() {
+            match line.strip_prefix("# ") {
+                Some(table) if table.starts_with("[steps.<step>]") => config.push_str("[steps.cargo]\n"),
+                Some(line) if line.starts_with('[') || line.contains(" = ") => {
+                    config.push_str(line);
+                    config.push('\n');
+                }
+                _ => (),
+            }
+        }
+        assert!(config.contains("[steps.mise]"));
+        toml::from_str::<ConfigFile>(&config).unwrap();
+    }
+}
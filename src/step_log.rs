@@ -0,0 +1,247 @@
+//! Log files with the output of each step, see `misc.step_logs`.
+//!
+//! Every run gets its own directory of logs in Topgrade's data directory, with
+//! one file per step key. The output of commands started by a step with
+//! `status_checked` or `spawn` is teed into the log of the step, and still shown
+//! as usual. Output that is captured by Topgrade or that the command's caller
+//! redirected itself is not logged.
+
+use std::cell::RefCell;
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, Stdio};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::thread::{self, JoinHandle};
+
+use chrono::Local;
+use color_eyre::eyre::{Context, Result};
+use console::strip_ansi_codes;
+use tracing::debug;
+
+use crate::breaking_changes::topgrade_data_dir;
+use crate::parallel;
+
+/// How many runs to keep the logs of.
+const RUNS_KEPT: usize = 10;
+
+/// The log directory of this run, if step logs are enabled.
+static RUN_DIR: OnceLock<PathBuf> = OnceLock::new();
+
+thread_local! {
+    /// The log file of the step running on this thread.
+    static CURRENT: RefCell<Option<Arc<Mutex<File>>>> = const { RefCell::new(None) };
+}
+
+/// Create the log directory for this run and remove the logs of old runs.
+pub fn init() -> Result<()> {
+    let logs_dir = topgrade_data_dir().join("logs");
+    let run_dir = logs_dir.join(Local::now().format("%Y-%m-%dT%H-%M-%S").to_string());
+    fs::create_dir_all(&run_dir)
+        .with_context(|| format!("Failed to create the log directory {}", run_dir.display()))?;
+    remove_old_runs(&logs_dir, RUNS_KEPT)?;
+
+    debug!("Logging step output to {}", run_dir.display());
+    RUN_DIR.set(run_dir).ok();
+    Ok(())
+}
+
+fn remove_old_runs(logs_dir: &Path, keep: usize) -> Result<()> {
+    let mut runs: Vec<PathBuf> = fs::read_dir(logs_dir)?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.is_dir())
+        .collect();
+    // The directories are named after the start of the run, so they sort by age.
+    runs.sort();
+
+    for run in runs.iter().rev().skip(keep) {
+        debug!("Removing old step logs {}", run.display());
+        fs::remove_dir_all(run).with_context(|| format!("Failed to remove old step logs {}", run.display()))?;
+    }
+
+    Ok(())
+}
+
+/// Where the log of the step with the given key goes, if step logs are enabled.
+fn log_path(key: &str) -> Option<PathBuf> {
+    let file_name: String = key
+        .chars()
+        .map(|c| {
+            if c.is_alphanumeric() || "-_.".contains(c) {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    RUN_DIR.get().map(|dir| dir.join(format!("{file_name}.log")))
+}
+
+/// The log file of the step with the given key, if it has one.
+pub fn path(key: &str) -> Option<PathBuf> {
+    log_path(key).filter(|path| path.exists())
+}
+
+/// Run `f` with the output of this thread logged to the log file of `key`.
+pub fn run<T>(key: &str, f: impl FnOnce() -> T) -> T {
+    let Some(path) = log_path(key) else {
+        return f();
+    };
+
+    let file = match File::options().create(true).append(true).open(&path) {
+        Ok(file) => file,
+        Err(e) => {
+            debug!("Failed to open the step log {}: {e}", path.display());
+            return f();
+        }
+    };
+
+    CURRENT.set(Some(Arc::new(Mutex::new(file))));
+    let value = f();
+    CURRENT.set(None);
+    value
+}
+
+/// Write Topgrade's own output to the log of the step running on this thread.
+pub fn write(args: fmt::Arguments) {
+    CURRENT.with_borrow(|log| {
+        if let Some(log) = log {
+            let text = args.to_string();
+            log.lock().unwrap().write_all(strip_ansi_codes(&text).as_bytes()).ok();
+        }
+    });
+}
+
+/// Output streams of a command.
+#[derive(Clone, Copy, Default, Debug, PartialEq, Eq)]
+pub struct Streams {
+    pub stdout: bool,
+    pub stderr: bool,
+}
+
+/// Make the streams of `command` that are left `inherited` go to pipes if they
+/// should be teed into a step log. Streams the caller set up itself are left alone.
+///
+/// Returns the streams that go to pipes, which `Tee::start` has to copy.
+pub fn prepare(command: &mut Command, inherited: Streams) -> Streams {
+    if CURRENT.with_borrow(Option::is_none) {
+        return Streams::default();
+    }
+    if inherited.stdout {
+        command.stdout(Stdio::piped());
+    }
+    if inherited.stderr {
+        command.stderr(Stdio::piped());
+    }
+    inherited
+}
+
+/// Copies the output of a child process to the step log and where it would have gone otherwise.
+pub struct Tee {
+    copiers: Vec<JoinHandle<()>>,
+}
+
+impl Tee {
+    /// Start copying the `teed` streams of `child`, as returned by `prepare`.
+    ///
+    /// The output is copied for as long as the child keeps it open.
+    pub fn start(child: &mut Child, teed: Streams) -> Self {
+        let log = CURRENT.with_borrow(Clone::clone);
+        let Some(log) = log.filter(|_| teed != Streams::default()) else {
+            return Self { copiers: Vec::new() };
+        };
+
+        // Resolved here, as the capture of parallel steps is specific to this thread.
+        let (stdout, stderr): (Box<dyn Write + Send>, Box<dyn Write + Send>) = match parallel::captured_file() {
+            Some((stdout, stderr)) => (Box::new(stdout), Box::new(stderr)),
+            None => (Box::new(io::stdout()), Box::new(io::stderr())),
+        };
+
+        let mut copiers = Vec::new();
+        if teed.stdout
+            && let Some(output) = child.stdout.take()
+        {
+            copiers.push(copy(output, stdout, Arc::clone(&log)));
+        }
+        if teed.stderr
+            && let Some(output) = child.stderr.take()
+        {
+            copiers.push(copy(output, stderr, log));
+        }
+        Self { copiers }
+    }
+
+    /// Wait until all output has been copied.
+    pub fn finish(self) {
+        for copier in self.copiers {
+            copier.join().ok();
+        }
+    }
+}
+
+fn copy(mut from: impl Read + Send + 'static, mut to: Box<dyn Write + Send>, log: Arc<Mutex<File>>) -> JoinHandle<()> {
+    thread::spawn(move || {
+        let mut buffer = [0; 8192];
+        while let Ok(read) = from.read(&mut buffer) {
+            if read == 0 {
+                break;
+            }
+            to.write_all(&buffer[..read]).ok();
+            to.flush().ok();
+            log.lock().unwrap().write_all(&buffer[..read]).ok();
+        }
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[cfg(unix)]
+    #[test]
+    fn test_tee_leaves_redirected_streams_alone() {
+        let log = tempfile::NamedTempFile::new().unwrap();
+        CURRENT.set(Some(Arc::new(Mutex::new(log.reopen().unwrap()))));
+
+        #[expect(clippy::disallowed_methods)]
+        let mut command = Command::new("sh");
+        command.args(["-c", "echo out; echo err >&2"]).stdout(Stdio::piped());
+        let teed = prepare(
+            &mut command,
+            Streams {
+                stdout: false,
+                stderr: true,
+            },
+        );
+        #[expect(clippy::disallowed_methods)]
+        let mut child = command.spawn().unwrap();
+        let tee = Tee::start(&mut child, teed);
+        let mut stdout = String::new();
+        child.stdout.take().unwrap().read_to_string(&mut stdout).unwrap();
+        child.wait().unwrap();
+        tee.finish();
+        CURRENT.set(None);
+
+        assert_eq!(stdout, "out\n");
+        assert_eq!(fs::read_to_string(log.path()).unwrap(), "err\n");
+    }
+
+    #[test]
+    fn test_remove_old_runs() {
+        let dir = tempfile::tempdir().unwrap();
+        for run in ["2026-01-01T10-00-00", "2026-01-02T10-00-00", "2026-01-03T10-00-00"] {
+            fs::create_dir(dir.path().join(run)).unwrap();
+            fs::write(dir.path().join(run).join("cargo.log"), "output").unwrap();
+        }
+
+        remove_old_runs(dir.path(), 2).unwrap();
+
+        let mut left: Vec<_> = fs::read_dir(dir.path())
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name())
+            .collect();
+        left.sort();
+        assert_eq!(left, ["2026-01-02T10-00-00", "2026-01-03T10-00-00"]);
+    }
+}
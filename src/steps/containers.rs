@@ -1,6 +1,7 @@
+use std::env;
 use std::fmt::{Display, Formatter};
 use std::io;
-use std::io::Write;
+use std::io::{IsTerminal, Write};
 use std::path::Path;
 
 use color_eyre::eyre::Context;
@@ -11,7 +12,10 @@ use wildmatch::WildMatch;
 
 use crate::command::CommandExt;
 use crate::error::{SkipStep, TopgradeError};
+use crate::executor::Executor;
+use crate::step::Step;
 use crate::terminal::print_separator;
+use crate::utils::require_option;
 use crate::{execution_context::ExecutionContext, utils::require};
 use rust_i18n::t;
 
@@ -25,6 +29,9 @@ const NONEXISTENT_REPO: &str = "repository does not exist";
 // A string found in the output of docker when Docker Desktop is not running.
 const DOCKER_NOT_RUNNING: &str = "We recommend to activate the WSL integration in Docker Desktop settings.";
 
+// Where Topgrade is copied to in the containers it runs in.
+const CONTAINER_TOPGRADE_PATH: &str = "/tmp/topgrade";
+
 /// Uniquely identifies a `Container`.
 #[derive(Debug)]
 struct Container {
@@ -238,7 +245,7 @@ pub fn run_containers(ctx: &ExecutionContext) -> Result<()> {
             ctx.execute(&crt)
         };
 
-        if let Err(e) = exec.args(&args).status_checked() {
+        if let Err(e) = exec.args(&args).extra_args().status_checked() {
             error!("Pulling container '{}' failed: {}", container, e);
 
             // Find out if this is 'skippable'
@@ -288,3 +295,157 @@ pub fn run_containers(ctx: &ExecutionContext) -> Result<()> {
 
     Ok(())
 }
+
+/// Returns the names of the running containers matching `exec_in`, to run Topgrade in.
+pub fn collect_exec_in(ctx: &ExecutionContext) -> Result<Vec<String>> {
+    let patterns: Vec<WildMatch> = require_option(
+        ctx.config().containers_exec_in(),
+        String::from(t!(
+            "No containers to run Topgrade in were specified in the configuration file"
+        )),
+    )?
+    .iter()
+    .map(|pattern| WildMatch::new(pattern))
+    .collect();
+    let crt = require(ctx.config().containers_runtime().to_string())?;
+
+    let exec = if ctx.config().containers_use_sudo() {
+        ctx.require_sudo()?.execute(ctx, &crt)?
+    } else {
+        ctx.execute(&crt)
+    };
+    let output = exec
+        .always()
+        .args(["ps", "--format", "{{.Names}}"])
+        .output_checked_utf8()?;
+
+    Ok(output
+        .stdout
+        .lines()
+        .filter(|name| patterns.iter().any(|pattern| pattern.matches(name)))
+        .map(String::from)
+        .collect())
+}
+
+/// Runs the system step of this Topgrade inside the running container `name`.
+///
+/// The binary is copied into the container for the run, so it has to be able
+/// to run there, e.g. by being statically linked.
+pub fn topgrade_in_container(ctx: &ExecutionContext, name: &str) -> Result<()> {
+    let crt = require(ctx.config().containers_runtime().to_string())?;
+    let sudo = if ctx.config().containers_use_sudo() {
+        Some(ctx.require_sudo()?)
+    } else {
+        None
+    };
+    let execute = || -> Result<Executor> {
+        match sudo {
+            Some(sudo) => sudo.execute(ctx, &crt),
+            None => Ok(ctx.execute(&crt)),
+        }
+    };
+
+    print_separator(format!("Container ({name})"));
+
+    let topgrade = env::current_exe()?;
+    execute()?
+        .arg("cp")
+        .arg(&topgrade)
+        .arg(format!("{name}:{CONTAINER_TOPGRADE_PATH}"))
+        .status_checked()?;
+
+    let prefix = format!("TOPGRADE_PREFIX={name}");
+    let mut args = vec!["exec", "-i"];
+    if io::stdin().is_terminal() {
+        args.push("-t");
+    }
+    // The user has seen the breaking changes of this version on the host.
+    args.extend(["-e", &prefix, "-e", "TOPGRADE_SKIP_BRKC_NOTIFY=true", name]);
+    args.extend([
+        CONTAINER_TOPGRADE_PATH,
+        "--only",
+        "system",
+        "--no-self-update",
+        "--allow-root",
+        "--notify-end",
+        "never",
+    ]);
+    if ctx.config().yes(Step::ContainerExec) {
+        args.push("--yes");
+    }
+    let result = execute()?.args(&args).status_checked();
+
+    if let Err(e) = execute()?
+        .args(["exec", name, "rm", "-f", CONTAINER_TOPGRADE_PATH])
+        .status_checked()
+    {
+        warn!("Failed to remove Topgrade from container '{}': {}", name, e);
+    }
+
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::test::config_from_toml;
+
+    const FIXTURE: &str = r#"
+[[commands]]
+command = "docker image ls --format '{{.Repository}}:{{.Tag}} {{.ID}}'"
+stdout = """
+alpine:latest 9c6f07244728
+localhost/mine:dev 5e4c0c3b2a1f
+<none>:<none> 1b2c3d4e5f6a
+ghcr.io/foo/bar:1.0 0a1b2c3d4e5f
+vsc-project-1234:latest 6f5e4d3c2b1a
+"""
+
+[[commands]]
+command = "docker image inspect 9c6f07244728 --format '{{.Os}}/{{.Architecture}}'"
+stdout = "linux/arm64\n"
+"#;
+
+    #[test]
+    fn test_list_containers() {
+        let config = config_from_toml("[containers]\nignored_containers = [\"ghcr.io/*\"]\n");
+        let ctx = ExecutionContext::replaying(&config, FIXTURE);
+
+        let containers = list_containers(&ctx, Path::new("/usr/bin/docker")).unwrap();
+        assert_eq!(containers.len(), 1);
+        assert_eq!(containers[0].repo_tag, "alpine:latest");
+        assert_eq!(containers[0].platform, "linux/arm64");
+    }
+
+    #[test]
+    fn test_list_containers_erroneous_output() {
+        let config = config_from_toml("");
+        let ctx = ExecutionContext::replaying(
+            &config,
+            "[[commands]]\ncommand = \"docker image ls --format '{{.Repository}}:{{.Tag}} {{.ID}}'\"\nstdout = \"alpine latest 9c6f07244728\\n\"\n",
+        );
+
+        assert!(list_containers(&ctx, Path::new("docker")).is_err());
+    }
+
+    #[test]
+    fn test_collect_exec_in() {
+        let config = config_from_toml("[containers]\nexec_in = [\"devbox-*\", \"builder\"]\n");
+        let ctx = ExecutionContext::replaying(
+            &config,
+            r#"
+[[commands]]
+command = "docker ps --format '{{.Names}}'"
+stdout = """
+devbox-rust
+postgres
+builder
+devbox-node
+"""
+"#,
+        );
+
+        let names = collect_exec_in(&ctx).unwrap();
+        assert_eq!(names, ["devbox-rust", "builder", "devbox-node"]);
+    }
+}
@@ -71,7 +71,7 @@ impl Emacs {
             command.arg("--aot");
         }
 
-        command.status_checked()
+        command.extra_args().status_checked()
     }
 
     pub fn upgrade(&self, ctx: &ExecutionContext) -> Result<()> {
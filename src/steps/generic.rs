@@ -25,12 +25,14 @@ use crate::HOME_DIR;
 #[cfg(unix)]
 use crate::XDG_DIRS;
 use crate::command::{CommandExt, Utf8Output};
+use crate::config::{CustomCommand, Mise, Zigup};
 use crate::execution_context::ExecutionContext;
-use crate::executor::{ExecutorChild, ExecutorOutput};
+use crate::executor::{Executor, ExecutorChild, ExecutorOutput};
 use crate::output_changed_message;
+use crate::runner::PendingUpdate;
 use crate::step::Step;
 use crate::sudo::SudoExecuteOpts;
-use crate::terminal::{print_separator, shell};
+use crate::terminal::{print_output, print_separator, shell};
 use crate::utils::{PathExt, check_is_python_2_or_shim, require, require_one, require_option, which};
 use crate::{
     error::{DryRun, SkipStep, StepFailed, TopgradeError},
@@ -47,7 +49,8 @@ pub static IS_WSL: LazyLock<bool> = LazyLock::new(|| {
 #[cfg(not(target_os = "linux"))]
 pub static IS_WSL: LazyLock<bool> = LazyLock::new(|| false);
 
-pub fn run_cargo_update(ctx: &ExecutionContext) -> Result<()> {
+/// The cargo home directory, if it has any packages installed.
+fn cargo_dir() -> Result<PathBuf> {
     let cargo_dir = env::var_os("CARGO_HOME")
         .map_or_else(|| HOME_DIR.join(".cargo"), PathBuf::from)
         .require()?;
@@ -64,18 +67,28 @@ pub fn run_cargo_update(ctx: &ExecutionContext) -> Result<()> {
         return Err(SkipStep(format!("{} exists but empty", toml_file.display())).into());
     }
 
-    print_separator("Cargo");
+    Ok(cargo_dir)
+}
+
+fn cargo_install_update(cargo_dir: &Path) -> Result<PathBuf> {
     let cargo_update = require("cargo-install-update")
         .ok()
         .or_else(|| cargo_dir.join("bin/cargo-install-update").if_exists());
 
-    let Some(cargo_update) = cargo_update else {
+    cargo_update.ok_or_else(|| {
         let message = String::from(
             "cargo-update isn't installed so Topgrade can't upgrade cargo packages.\nInstall cargo-update by running `cargo install cargo-update`",
         );
         print_warning(&message);
-        return Err(SkipStep(message).into());
-    };
+        SkipStep(message).into()
+    })
+}
+
+pub fn run_cargo_update(ctx: &ExecutionContext) -> Result<()> {
+    let cargo_dir = cargo_dir()?;
+
+    print_separator("Cargo");
+    let cargo_update = cargo_install_update(&cargo_dir)?;
 
     let mut command = ctx.execute(cargo_update);
     command.args(["install-update", "--all"]);
@@ -88,7 +101,7 @@ pub fn run_cargo_update(ctx: &ExecutionContext) -> Result<()> {
     if ctx.config().cargo_update_locked() {
         command.arg("--locked");
     }
-    command.status_checked()?;
+    command.extra_args().status_checked()?;
 
     if ctx.config().cleanup() {
         let cargo_cache = require("cargo-cache")
@@ -107,6 +120,37 @@ pub fn run_cargo_update(ctx: &ExecutionContext) -> Result<()> {
     Ok(())
 }
 
+pub fn check_cargo(ctx: &ExecutionContext) -> Result<Vec<PendingUpdate>> {
+    let cargo_dir = cargo_dir()?;
+
+    print_separator("Cargo");
+    let cargo_update = cargo_install_update(&cargo_dir)?;
+
+    let mut command = ctx.execute(cargo_update).always();
+    command.args(["install-update", "--list"]);
+    if ctx.config().cargo_update_git() {
+        command.arg("--git");
+    }
+    let output = command.output_checked_utf8()?;
+
+    Ok(parse_cargo_install_update_list(&output.stdout))
+}
+
+/// Parse the table printed by `cargo install-update --list`.
+fn parse_cargo_install_update_list(output: &str) -> Vec<PendingUpdate> {
+    output
+        .lines()
+        .filter_map(|line| match line.split_whitespace().collect::<Vec<_>>()[..] {
+            [package, installed, latest, "Yes"] => Some(PendingUpdate::new(
+                package,
+                Some(installed.trim_start_matches('v')),
+                Some(latest.trim_start_matches('v')),
+            )),
+            _ => None,
+        })
+        .collect()
+}
+
 pub fn run_flutter_upgrade(ctx: &ExecutionContext) -> Result<()> {
     let flutter = require("flutter")?;
 
@@ -118,7 +162,7 @@ pub fn run_flutter_upgrade(ctx: &ExecutionContext) -> Result<()> {
         command.arg("--force");
     }
 
-    command.status_checked()
+    command.extra_args().status_checked()
 }
 
 pub fn run_gem(ctx: &ExecutionContext) -> Result<()> {
@@ -136,7 +180,7 @@ pub fn run_gem(ctx: &ExecutionContext) -> Result<()> {
         debug!("Detected rbenv. Avoiding --user-install");
     }
 
-    command.status_checked()
+    command.extra_args().status_checked()
 }
 
 pub fn run_rubygems(ctx: &ExecutionContext) -> Result<()> {
@@ -150,11 +194,15 @@ pub fn run_rubygems(ctx: &ExecutionContext) -> Result<()> {
         || gem_path_str.to_str().unwrap().contains(".rbenv")
         || gem_path_str.to_str().unwrap().contains(".rvm")
     {
-        ctx.execute(gem).args(["update", "--system"]).status_checked()?;
+        ctx.execute(gem)
+            .args(["update", "--system"])
+            .extra_args()
+            .status_checked()?;
     } else if !Path::new("/usr/lib/ruby/vendor_ruby/rubygems/defaults/operating_system.rb").exists() {
         let sudo = ctx.require_sudo()?;
         sudo.execute_opts(ctx, &gem, SudoExecuteOpts::new().preserve_env().set_home())?
             .args(["update", "--system"])
+            .extra_args()
             .status_checked()?;
     }
 
@@ -181,7 +229,7 @@ pub fn run_haxelib_update(ctx: &ExecutionContext) -> Result<()> {
         sudo.execute(ctx, &haxelib)?
     };
 
-    command.arg("update").status_checked()
+    command.arg("update").extra_args().status_checked()
 }
 
 pub fn run_getnf_update(ctx: &ExecutionContext) -> Result<()> {
@@ -189,7 +237,7 @@ pub fn run_getnf_update(ctx: &ExecutionContext) -> Result<()> {
 
     print_separator("getnf");
 
-    ctx.execute(getnf).args(["-U"]).status_checked()
+    ctx.execute(getnf).args(["-U"]).extra_args().status_checked()
 }
 
 pub fn run_sheldon(ctx: &ExecutionContext) -> Result<()> {
@@ -197,7 +245,10 @@ pub fn run_sheldon(ctx: &ExecutionContext) -> Result<()> {
 
     print_separator("Sheldon");
 
-    ctx.execute(sheldon).args(["lock", "--update"]).status_checked()
+    ctx.execute(sheldon)
+        .args(["lock", "--update"])
+        .extra_args()
+        .status_checked()
 }
 
 pub fn run_fossil(ctx: &ExecutionContext) -> Result<()> {
@@ -205,7 +256,7 @@ pub fn run_fossil(ctx: &ExecutionContext) -> Result<()> {
 
     print_separator("Fossil");
 
-    ctx.execute(fossil).args(["all", "sync"]).status_checked()
+    ctx.execute(fossil).args(["all", "sync"]).extra_args().status_checked()
 }
 
 pub fn run_micro(ctx: &ExecutionContext) -> Result<()> {
@@ -283,7 +334,10 @@ pub fn run_apm(ctx: &ExecutionContext) -> Result<()> {
 
     print_separator("Atom Package Manager");
 
-    ctx.execute(apm).args(["upgrade", "--confirm=false"]).status_checked()
+    ctx.execute(apm)
+        .args(["upgrade", "--confirm=false"])
+        .extra_args()
+        .status_checked()
 }
 
 enum Aqua {
@@ -335,6 +389,7 @@ pub fn run_aqua(ctx: &ExecutionContext) -> Result<()> {
     if let Ok(path) = env::var("AQUA_GLOBAL_CONFIG") {
         ctx.execute(&aqua)
             .args(["update", "--config", &path])
+            .extra_args()
             .status_checked()?;
     }
 
@@ -349,6 +404,7 @@ pub fn run_rustup(ctx: &ExecutionContext) -> Result<()> {
     ctx.execute(rustup)
         .arg("update")
         .args(ctx.config().rustup_channels())
+        .extra_args()
         .status_checked()
 }
 
@@ -356,7 +412,7 @@ pub fn run_rye(ctx: &ExecutionContext) -> Result<()> {
     let rye = require("rye")?;
 
     print_separator("Rye");
-    ctx.execute(rye).args(["self", "update"]).status_checked()
+    ctx.execute(rye).args(["self", "update"]).extra_args().status_checked()
 }
 
 pub fn run_elan(ctx: &ExecutionContext) -> Result<()> {
@@ -381,8 +437,7 @@ pub fn run_elan(ctx: &ExecutionContext) -> Result<()> {
         ExecutorOutput::Wet(command_output) => {
             if command_output.status.success() {
                 // Flush the captured output
-                std::io::stdout().lock().write_all(&command_output.stdout).unwrap();
-                std::io::stderr().lock().write_all(&command_output.stderr).unwrap();
+                print_output(&command_output.stdout, &command_output.stderr);
             } else {
                 let stderr_as_str = std::str::from_utf8(&command_output.stderr).unwrap();
                 if stderr_as_str.contains(disabled_error_msg) {
@@ -393,8 +448,7 @@ pub fn run_elan(ctx: &ExecutionContext) -> Result<()> {
                     // `elan` is NOT externally managed, `elan self update` can
                     // be performed, but the invocation failed, so we report the
                     // error to the user and error out.
-                    std::io::stdout().lock().write_all(&command_output.stdout).unwrap();
-                    std::io::stderr().lock().write_all(&command_output.stderr).unwrap();
+                    print_output(&command_output.stdout, &command_output.stderr);
 
                     return Err(StepFailed.into());
                 }
@@ -405,7 +459,7 @@ pub fn run_elan(ctx: &ExecutionContext) -> Result<()> {
 
     // In elan 4.0.0, `elan update` was removed, as toolchains are now updated automatically
     if version < Version::new(4, 0, 0) {
-        ctx.execute(&elan).arg("update").status_checked()?;
+        ctx.execute(&elan).arg("update").extra_args().status_checked()?;
     }
 
     Ok(())
@@ -420,7 +474,7 @@ pub fn run_juliaup(ctx: &ExecutionContext) -> Result<()> {
         ctx.execute(&juliaup).args(["self", "update"]).status_checked()?;
     }
 
-    ctx.execute(&juliaup).arg("update").status_checked()?;
+    ctx.execute(&juliaup).arg("update").extra_args().status_checked()?;
 
     if ctx.config().cleanup() {
         ctx.execute(&juliaup).arg("gc").status_checked()?;
@@ -435,7 +489,10 @@ pub fn run_choosenim(ctx: &ExecutionContext) -> Result<()> {
     print_separator("choosenim");
 
     ctx.execute(&choosenim).args(["update", "self"]).status_checked()?;
-    ctx.execute(&choosenim).args(["update", "stable"]).status_checked()
+    ctx.execute(&choosenim)
+        .args(["update", "stable"])
+        .extra_args()
+        .status_checked()
 }
 
 pub fn run_krew_upgrade(ctx: &ExecutionContext) -> Result<()> {
@@ -443,7 +500,7 @@ pub fn run_krew_upgrade(ctx: &ExecutionContext) -> Result<()> {
 
     print_separator("Krew");
 
-    ctx.execute(krew).args(["upgrade"]).status_checked()
+    ctx.execute(krew).args(["upgrade"]).extra_args().status_checked()
 }
 
 pub fn run_gcloud_components_update(ctx: &ExecutionContext) -> Result<()> {
@@ -491,7 +548,10 @@ pub fn run_jetpack(ctx: &ExecutionContext) -> Result<()> {
 
     print_separator("Jetpack");
 
-    ctx.execute(jetpack).args(["global", "update"]).status_checked()
+    ctx.execute(jetpack)
+        .args(["global", "update"])
+        .extra_args()
+        .status_checked()
 }
 
 pub fn run_rtcl(ctx: &ExecutionContext) -> Result<()> {
@@ -499,7 +559,7 @@ pub fn run_rtcl(ctx: &ExecutionContext) -> Result<()> {
 
     print_separator("rtcl");
 
-    ctx.execute(rupdate).status_checked()
+    ctx.execute(rupdate).extra_args().status_checked()
 }
 
 pub fn run_opam_update(ctx: &ExecutionContext) -> Result<()> {
@@ -514,7 +574,7 @@ pub fn run_opam_update(ctx: &ExecutionContext) -> Result<()> {
     if ctx.config().yes(Step::Opam) {
         command.arg("--yes");
     }
-    command.status_checked()?;
+    command.extra_args().status_checked()?;
 
     if ctx.config().cleanup() {
         ctx.execute(&opam).arg("clean").status_checked()?;
@@ -540,7 +600,7 @@ pub fn run_vcpkg_update(ctx: &ExecutionContext) -> Result<()> {
         ctx.execute(&vcpkg)
     };
 
-    command.args(["upgrade", "--no-dry-run"]).status_checked()
+    command.args(["upgrade", "--no-dry-run"]).extra_args().status_checked()
 }
 
 enum VSCodeVariant {
@@ -679,7 +739,7 @@ fn run_vscode_compatible(variant: VSCodeVariant, ctx: &ExecutionContext) -> Resu
         }
     }
 
-    cmd.arg("--update-extensions").status_checked()
+    cmd.arg("--update-extensions").extra_args().status_checked()
 }
 
 /// Make VSCodium a separate step because:
@@ -756,11 +816,13 @@ pub fn run_pi(ctx: &ExecutionContext) -> Result<()> {
         ctx.execute(&pi)
             .current_dir(temp_dir.path())
             .args(["update", "--extensions"])
+            .extra_args()
             .status_checked()
     } else {
         ctx.execute(&pi)
             .current_dir(temp_dir.path())
             .arg("update")
+            .extra_args()
             .status_checked()
     }
 }
@@ -786,14 +848,61 @@ pub fn run_pipx_update(ctx: &ExecutionContext) -> Result<()> {
         command_args.push("--quiet");
     }
 
-    ctx.execute(pipx).args(command_args).status_checked()
+    ctx.execute(pipx).args(command_args).extra_args().status_checked()
+}
+
+pub fn check_pipx(ctx: &ExecutionContext) -> Result<Vec<PendingUpdate>> {
+    let pipx = require("pipx")?;
+    print_separator("pipx");
+
+    let packages = ctx
+        .execute(&pipx)
+        .always()
+        .args(["list", "--short"])
+        .output_checked_utf8()?
+        .stdout;
+
+    let mut updates = Vec::new();
+    // pipx can't tell which packages are outdated, but the pip of each of their environments can.
+    for package in packages.lines().filter_map(|line| line.split_whitespace().next()) {
+        let output = ctx
+            .execute(&pipx)
+            .always()
+            .args(["runpip", package, "list", "--outdated", "--format=json"])
+            .output_checked_utf8()?;
+        updates.extend(parse_pip_outdated(package, &output.stdout)?);
+    }
+
+    Ok(updates)
+}
+
+/// Find `package` in the output of `pip list --outdated --format=json`.
+fn parse_pip_outdated(package: &str, output: &str) -> Result<Option<PendingUpdate>> {
+    #[derive(Deserialize)]
+    struct Outdated {
+        name: String,
+        version: String,
+        latest_version: String,
+    }
+
+    // Package names are case insensitive, and `-`, `_` and `.` are interchangeable.
+    let normalize = |name: &str| name.to_lowercase().replace(['_', '.'], "-");
+
+    let outdated: Vec<Outdated> = serde_json::from_str(output).context("Failed to parse the output of pip")?;
+    Ok(outdated
+        .into_iter()
+        .find(|outdated| normalize(&outdated.name) == normalize(package))
+        .map(|outdated| PendingUpdate::new(package, Some(&outdated.version), Some(&outdated.latest_version))))
 }
 
 pub fn run_pipxu_update(ctx: &ExecutionContext) -> Result<()> {
     let pipxu = require("pipxu")?;
     print_separator("pipxu");
 
-    ctx.execute(pipxu).args(["upgrade", "--all"]).status_checked()
+    ctx.execute(pipxu)
+        .args(["upgrade", "--all"])
+        .extra_args()
+        .status_checked()
 }
 
 pub fn run_conda_update(ctx: &ExecutionContext) -> Result<()> {
@@ -822,7 +931,7 @@ pub fn run_conda_update(ctx: &ExecutionContext) -> Result<()> {
         if ctx.config().yes(Step::Conda) {
             command.arg("--yes");
         }
-        command.status_checked()?;
+        command.extra_args().status_checked()?;
     }
 
     // Update any environments given by path
@@ -833,7 +942,7 @@ pub fn run_conda_update(ctx: &ExecutionContext) -> Result<()> {
             if ctx.config().yes(Step::Conda) {
                 command.arg("--yes");
             }
-            command.status_checked()?;
+            command.extra_args().status_checked()?;
         }
     }
 
@@ -873,7 +982,10 @@ pub fn run_pixi_update(ctx: &ExecutionContext) -> Result<()> {
         cmd.status_checked()?;
     }
 
-    ctx.execute(&pixi).args(["global", "update"]).status_checked()
+    ctx.execute(&pixi)
+        .args(["global", "update"])
+        .extra_args()
+        .status_checked()
 }
 
 pub fn run_mamba_update(ctx: &ExecutionContext) -> Result<()> {
@@ -886,7 +998,7 @@ pub fn run_mamba_update(ctx: &ExecutionContext) -> Result<()> {
     if ctx.config().yes(Step::Mamba) {
         command.arg("--yes");
     }
-    command.status_checked()?;
+    command.extra_args().status_checked()?;
 
     if ctx.config().cleanup() {
         let mut command = ctx.execute(&mamba);
@@ -904,7 +1016,10 @@ pub fn run_miktex_packages_update(ctx: &ExecutionContext) -> Result<()> {
     let miktex = require("miktex")?;
     print_separator("miktex");
 
-    ctx.execute(miktex).args(["packages", "update"]).status_checked()
+    ctx.execute(miktex)
+        .args(["packages", "update"])
+        .extra_args()
+        .status_checked()
 }
 
 pub fn run_pip3_update(ctx: &ExecutionContext) -> Result<()> {
@@ -993,6 +1108,7 @@ pub fn run_pip3_update(ctx: &ExecutionContext) -> Result<()> {
 
     ctx.execute(&python3)
         .args(["-m", "pip", "install", "--upgrade", "--user", "pip"])
+        .extra_args()
         .status_checked()
 }
 
@@ -1007,7 +1123,10 @@ pub fn run_pip_review_update(ctx: &ExecutionContext) -> Result<()> {
         );
         return Err(SkipStep(String::from("Pip-review is disabled by default")).into());
     }
-    ctx.execute(pip_review).arg("--auto").status_checked_with_codes(&[1])?;
+    ctx.execute(pip_review)
+        .arg("--auto")
+        .extra_args()
+        .status_checked_with_codes(&[1])?;
 
     Ok(())
 }
@@ -1026,6 +1145,7 @@ pub fn run_pip_review_local_update(ctx: &ExecutionContext) -> Result<()> {
     ctx.execute(pip_review)
         .arg("--local")
         .arg("--auto")
+        .extra_args()
         .status_checked_with_codes(&[1])?;
 
     Ok(())
@@ -1043,6 +1163,7 @@ pub fn run_pipupgrade_update(ctx: &ExecutionContext) -> Result<()> {
     }
     ctx.execute(pipupgrade)
         .args(ctx.config().pipupgrade_arguments().split_whitespace())
+        .extra_args()
         .status_checked()?;
 
     Ok(())
@@ -1059,14 +1180,14 @@ pub fn run_stack_update(ctx: &ExecutionContext) -> Result<()> {
     let stack = require("stack")?;
     print_separator("stack");
 
-    ctx.execute(stack).arg("upgrade").status_checked()
+    ctx.execute(stack).arg("upgrade").extra_args().status_checked()
 }
 
 pub fn run_ghcup_update(ctx: &ExecutionContext) -> Result<()> {
     let ghcup = require("ghcup")?;
     print_separator("ghcup");
 
-    ctx.execute(ghcup).arg("upgrade").status_checked()
+    ctx.execute(ghcup).arg("upgrade").extra_args().status_checked()
 }
 
 pub fn run_tldr(ctx: &ExecutionContext) -> Result<()> {
@@ -1074,7 +1195,7 @@ pub fn run_tldr(ctx: &ExecutionContext) -> Result<()> {
 
     print_separator("TLDR");
 
-    ctx.execute(tldr).arg("--update").status_checked()
+    ctx.execute(tldr).arg("--update").extra_args().status_checked()
 }
 
 pub fn run_tlmgr_update(ctx: &ExecutionContext) -> Result<()> {
@@ -1114,7 +1235,7 @@ pub fn run_tlmgr_update(ctx: &ExecutionContext) -> Result<()> {
     };
     command.args(["update", "--self", "--all"]);
 
-    command.status_checked()
+    command.extra_args().status_checked()
 }
 
 pub fn run_chezmoi_update(ctx: &ExecutionContext) -> Result<()> {
@@ -1140,7 +1261,7 @@ pub fn run_chezmoi_update(ctx: &ExecutionContext) -> Result<()> {
         cmd.arg("--exclude=encrypted");
     }
 
-    cmd.status_checked()
+    cmd.extra_args().status_checked()
 }
 
 pub fn run_myrepos_update(ctx: &ExecutionContext) -> Result<()> {
@@ -1158,12 +1279,51 @@ pub fn run_myrepos_update(ctx: &ExecutionContext) -> Result<()> {
         .arg("--directory")
         .arg(&*HOME_DIR)
         .arg("update")
+        .extra_args()
         .status_checked()
 }
 
 pub fn run_custom_command(name: &str, command: &str, ctx: &ExecutionContext) -> Result<()> {
     print_separator(name);
-    let mut exec = ctx.execute(shell());
+    custom_command(ctx, command, false)?.status_checked()
+}
+
+/// Run a `[commands]` entry.
+pub fn run_custom_step(name: &str, custom: &CustomCommand, ctx: &ExecutionContext) -> Result<()> {
+    if let Some(binary) = &custom.require {
+        require(binary)?;
+    }
+    if let Some(condition) = &custom.only_if {
+        let result = ctx.execute(shell()).always().arg("-c").arg(condition).output_checked();
+        if result.is_err() {
+            return Err(SkipStep(t!("`{condition}` failed", condition = condition).to_string()).into());
+        }
+    }
+
+    print_separator(name);
+    let mut command = custom.command.clone();
+    if let Some(yes_args) = &custom.yes_args
+        && ctx.config().yes(Step::CustomCommands)
+    {
+        command = format!("{command} {yes_args}");
+    }
+    custom_command(ctx, &command, custom.sudo)?.status_checked()?;
+
+    if let Some(cleanup) = &custom.cleanup
+        && ctx.config().cleanup()
+    {
+        custom_command(ctx, cleanup, custom.sudo)?.status_checked()?;
+    }
+    Ok(())
+}
+
+/// A shell running `command`, which is run in an interactive shell if it starts with `-i `.
+fn custom_command(ctx: &ExecutionContext, command: &str, sudo: bool) -> Result<Executor> {
+    let mut exec = if sudo {
+        ctx.require_sudo()?.execute(ctx, shell())?
+    } else {
+        ctx.execute(shell())
+    };
     #[cfg(unix)]
     let command = if let Some(command) = command.strip_prefix("-i ") {
         exec.arg("-i");
@@ -1177,7 +1337,8 @@ pub fn run_custom_command(name: &str, command: &str, ctx: &ExecutionContext) ->
     if ctx.config().cleanup() {
         exec.env("TOPGRADE_CLEANUP", "1");
     }
-    exec.arg("-c").arg(command).status_checked()
+    exec.arg("-c").arg(command);
+    Ok(exec)
 }
 
 pub fn run_composer_update(ctx: &ExecutionContext) -> Result<()> {
@@ -1221,7 +1382,11 @@ pub fn run_composer_update(ctx: &ExecutionContext) -> Result<()> {
         }
     }
 
-    let output = ctx.execute(&composer).args(["global", "update"]).output()?;
+    let output = ctx
+        .execute(&composer)
+        .args(["global", "update"])
+        .extra_args()
+        .output()?;
     if let ExecutorOutput::Wet(output) = output {
         let output: Utf8Output = output.try_into()?;
         print!("{}\n{}", output.stdout, output.stderr);
@@ -1292,6 +1457,7 @@ pub fn run_dotnet_upgrade(ctx: &ExecutionContext) -> Result<()> {
         let package_name = package.split_whitespace().next().unwrap();
         ctx.execute(&dotnet)
             .args(["tool", "update", package_name, "--global"])
+            .extra_args()
             .status_checked()
             .with_context(|| format!("Failed to update .NET package {package_name:?}"))?;
     }
@@ -1371,6 +1537,37 @@ mod powershell_tests {
     }
 }
 
+#[cfg(test)]
+mod check_tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_cargo_install_update_list() {
+        let output = "    Polling registry 'https://index.crates.io/'.......
+
+Package       Installed  Latest   Needs update
+bat           v0.24.0    v0.25.0  Yes
+cargo-update  v16.0.0    v16.0.0  No
+";
+        assert_eq!(
+            parse_cargo_install_update_list(output),
+            [PendingUpdate::new("bat", Some("0.24.0"), Some("0.25.0"))]
+        );
+    }
+
+    #[test]
+    fn test_parse_pip_outdated() {
+        let output = r#"[{"name": "Black", "version": "24.1.0", "latest_version": "24.2.0", "latest_filetype": "wheel"},
+            {"name": "click", "version": "8.1.6", "latest_version": "8.1.7", "latest_filetype": "wheel"}]"#;
+        assert_eq!(
+            parse_pip_outdated("black", output).unwrap(),
+            Some(PendingUpdate::new("black", Some("24.1.0"), Some("24.2.0")))
+        );
+        assert_eq!(parse_pip_outdated("ruff", output).unwrap(), None);
+        assert_eq!(parse_pip_outdated("ruff", "[]").unwrap(), None);
+    }
+}
+
 enum Hx {
     Helix(PathBuf),
     HxHexdump,
@@ -1453,6 +1650,7 @@ pub fn run_helix_grammars(ctx: &ExecutionContext) -> Result<()> {
 
     ctx.execute(&helix)
         .args(["--grammar", "fetch"])
+        .extra_args()
         .status_checked()
         .with_context(|| "Failed to download helix grammars!")?;
 
@@ -1469,7 +1667,7 @@ pub fn run_helix_db(ctx: &ExecutionContext) -> Result<()> {
 
     print_separator("HelixDB");
 
-    ctx.execute(helix).arg("update").status_checked()
+    ctx.execute(helix).arg("update").extra_args().status_checked()
 }
 
 pub fn run_raco_update(ctx: &ExecutionContext) -> Result<()> {
@@ -1477,14 +1675,17 @@ pub fn run_raco_update(ctx: &ExecutionContext) -> Result<()> {
 
     print_separator(t!("Racket Package Manager"));
 
-    ctx.execute(raco).args(["pkg", "update", "--all"]).status_checked()
+    ctx.execute(raco)
+        .args(["pkg", "update", "--all"])
+        .extra_args()
+        .status_checked()
 }
 
 pub fn bin_update(ctx: &ExecutionContext) -> Result<()> {
     let bin = require("bin")?;
 
     print_separator("Bin");
-    ctx.execute(bin).arg("update").status_checked()
+    ctx.execute(bin).arg("update").extra_args().status_checked()
 }
 
 pub fn spicetify_upgrade(ctx: &ExecutionContext) -> Result<()> {
@@ -1492,7 +1693,7 @@ pub fn spicetify_upgrade(ctx: &ExecutionContext) -> Result<()> {
     let spicetify = require("spicetify").or(require("spicetify-cli"))?;
 
     print_separator("Spicetify");
-    ctx.execute(spicetify).arg("upgrade").status_checked()
+    ctx.execute(spicetify).arg("upgrade").extra_args().status_checked()
 }
 
 pub fn run_ghcli_extensions_upgrade(ctx: &ExecutionContext) -> Result<()> {
@@ -1510,6 +1711,7 @@ pub fn run_ghcli_extensions_upgrade(ctx: &ExecutionContext) -> Result<()> {
     print_separator(t!("GitHub CLI Extensions"));
     ctx.execute(&gh)
         .args(["extension", "upgrade", "--all"])
+        .extra_args()
         .status_checked()
 }
 
@@ -1537,7 +1739,7 @@ pub fn run_helm_repo_update(ctx: &ExecutionContext) -> Result<()> {
     let no_repo = "no repositories found";
     let mut success = true;
     let mut exec = ctx.execute(helm);
-    if let Err(e) = exec.arg("repo").arg("update").status_checked() {
+    if let Err(e) = exec.arg("repo").arg("update").extra_args().status_checked() {
         error!("Updating repositories failed: {e}");
         success = match exec.output_checked_utf8() {
             Ok(s) => s.stdout.contains(no_repo) || s.stderr.contains(no_repo),
@@ -1555,7 +1757,10 @@ pub fn run_stew(ctx: &ExecutionContext) -> Result<()> {
     let stew = require("stew")?;
 
     print_separator("stew");
-    ctx.execute(stew).args(["upgrade", "--all"]).status_checked()
+    ctx.execute(stew)
+        .args(["upgrade", "--all"])
+        .extra_args()
+        .status_checked()
 }
 
 pub fn run_bob(ctx: &ExecutionContext) -> Result<()> {
@@ -1563,7 +1768,7 @@ pub fn run_bob(ctx: &ExecutionContext) -> Result<()> {
 
     print_separator("Bob");
 
-    ctx.execute(bob).args(["update", "--all"]).status_checked()
+    ctx.execute(bob).args(["update", "--all"]).extra_args().status_checked()
 }
 
 pub fn run_certbot(ctx: &ExecutionContext) -> Result<()> {
@@ -1572,7 +1777,7 @@ pub fn run_certbot(ctx: &ExecutionContext) -> Result<()> {
     print_separator("Certbot");
 
     let sudo = ctx.require_sudo()?;
-    sudo.execute(ctx, &certbot)?.arg("renew").status_checked()
+    sudo.execute(ctx, &certbot)?.arg("renew").extra_args().status_checked()
 }
 
 /// Run `$ freshclam` to update ClamAV signature database
@@ -1616,7 +1821,7 @@ pub fn run_freshclam(ctx: &ExecutionContext) -> Result<()> {
     debug!("`freshclam` (without sudo) resulted in error: {:?}", output);
     let sudo = ctx.require_sudo()?;
 
-    match sudo.execute(ctx, freshclam)?.status_checked() {
+    match sudo.execute(ctx, freshclam)?.extra_args().status_checked() {
         Ok(()) => Ok(()), // Success! The output of only the sudo'ed process is written.
         Err(err) => {
             // Error! We add onto the error the output of running without sudo for more information.
@@ -1644,7 +1849,7 @@ pub fn run_platform_io(ctx: &ExecutionContext) -> Result<()> {
 
     print_separator("PlatformIO Core");
 
-    ctx.execute(bin_path).arg("upgrade").status_checked()
+    ctx.execute(bin_path).arg("upgrade").extra_args().status_checked()
 }
 
 /// Run `lensfun-update-data` to update lensfun database.
@@ -1662,9 +1867,11 @@ pub fn run_lensfun_update_data(ctx: &ExecutionContext) -> Result<()> {
         sudo.execute(ctx, &lensfun_update_data)?
             // `lensfun-update-data` returns 1 when there is no update available
             // which should be considered success
+            .extra_args()
             .status_checked_with_codes(&[EXIT_CODE_WHEN_NO_UPDATE])
     } else {
         ctx.execute(lensfun_update_data)
+            .extra_args()
             .status_checked_with_codes(&[EXIT_CODE_WHEN_NO_UPDATE])
     }
 }
@@ -1772,7 +1979,10 @@ pub fn run_poetry(ctx: &ExecutionContext) -> Result<()> {
     }
 
     print_separator("Poetry");
-    ctx.execute(&poetry).args(["self", "update"]).status_checked()
+    ctx.execute(&poetry)
+        .args(["self", "update"])
+        .extra_args()
+        .status_checked()
 }
 
 pub fn run_uv(ctx: &ExecutionContext) -> Result<()> {
@@ -1870,8 +2080,7 @@ pub fn run_uv(ctx: &ExecutionContext) -> Result<()> {
         } else {
             // Feature is enabled, flush the captured output so that users know we did the self-update.
 
-            std::io::stdout().write_all(&output.stdout)?;
-            std::io::stderr().write_all(&output.stderr)?;
+            print_output(&output.stdout, &output.stderr);
 
             // And, if self update failed, fail the step as well.
             if !output.status.success() {
@@ -1883,6 +2092,7 @@ pub fn run_uv(ctx: &ExecutionContext) -> Result<()> {
     // Update the installed tools
     ctx.execute(&uv_exec)
         .args(["tool", "upgrade", "--all"])
+        .extra_args()
         .status_checked()?;
 
     // Update uv-managed Python installations from uv>=0.10.0
@@ -1904,7 +2114,7 @@ pub fn run_zvm(ctx: &ExecutionContext) -> Result<()> {
 
     print_separator("ZVM");
 
-    ctx.execute(zvm).arg("upgrade").status_checked()
+    ctx.execute(zvm).arg("upgrade").extra_args().status_checked()
 }
 
 pub fn run_bun(ctx: &ExecutionContext) -> Result<()> {
@@ -1925,7 +2135,7 @@ pub fn run_bun(ctx: &ExecutionContext) -> Result<()> {
     if bun.is_descendant_of(&bun_install_env) {
         print_separator("Bun");
 
-        ctx.execute(bun).arg("upgrade").status_checked()
+        ctx.execute(bun).arg("upgrade").extra_args().status_checked()
     } else {
         Err(SkipStep("Not installed through the official script".to_string()).into())
     }
@@ -1933,28 +2143,30 @@ pub fn run_bun(ctx: &ExecutionContext) -> Result<()> {
 
 pub fn run_zigup(ctx: &ExecutionContext) -> Result<()> {
     let zigup = require("zigup")?;
-    let config = ctx.config();
+    let options = ctx.config().step_options::<Zigup>();
+    let cleanup = options.cleanup.unwrap_or(false);
 
     print_separator("zigup");
 
     let mut path_args = Vec::new();
-    if let Some(path) = config.zigup_path_link() {
+    if let Some(path) = &options.path_link {
         path_args.push("--path-link".to_owned());
         path_args.push(shellexpand::tilde(path).into_owned());
     }
-    if let Some(path) = config.zigup_install_dir() {
+    if let Some(path) = &options.install_dir {
         path_args.push("--install-dir".to_owned());
         path_args.push(shellexpand::tilde(path).into_owned());
     }
 
-    for zig_version in config.zigup_target_versions() {
+    for zig_version in options.target_versions() {
         ctx.execute(&zigup)
             .args(&path_args)
             .arg("fetch")
             .arg(&zig_version)
+            .extra_args()
             .status_checked()?;
 
-        if config.zigup_cleanup() {
+        if cleanup {
             ctx.execute(&zigup)
                 .args(&path_args)
                 .arg("keep")
@@ -1963,7 +2175,7 @@ pub fn run_zigup(ctx: &ExecutionContext) -> Result<()> {
         }
     }
 
-    if config.zigup_cleanup() {
+    if cleanup {
         ctx.execute(zigup).args(&path_args).arg("clean").status_checked()?;
     }
 
@@ -2171,7 +2383,7 @@ pub fn run_yazi(ctx: &ExecutionContext) -> Result<()> {
 
     print_separator("Yazi packages");
 
-    ctx.execute(ya).args(["pkg", "upgrade"]).status_checked()
+    ctx.execute(ya).args(["pkg", "upgrade"]).extra_args().status_checked()
 }
 
 #[derive(Deserialize)]
@@ -2211,7 +2423,7 @@ pub fn run_typst(ctx: &ExecutionContext) -> Result<()> {
 
     print_separator("Typst");
 
-    ctx.execute(typst).args(["update"]).status_checked()
+    ctx.execute(typst).args(["update"]).extra_args().status_checked()
 }
 
 pub fn run_claude_code(ctx: &ExecutionContext) -> Result<()> {
@@ -2219,7 +2431,7 @@ pub fn run_claude_code(ctx: &ExecutionContext) -> Result<()> {
 
     print_separator("Claude Code");
 
-    ctx.execute(&claude).arg("update").status_checked()
+    ctx.execute(&claude).arg("update").extra_args().status_checked()
 }
 
 pub fn run_claude_code_plugins(ctx: &ExecutionContext) -> Result<()> {
@@ -2258,7 +2470,7 @@ pub fn run_claude_code_plugins(ctx: &ExecutionContext) -> Result<()> {
         if let Some(path) = &plugin.project_path {
             cmd.current_dir(path);
         }
-        if let Err(e) = cmd.status_checked() {
+        if let Err(e) = cmd.extra_args().status_checked() {
             error!("Updating plugin {} failed: {e}", plugin.id);
             success = false;
         }
@@ -2272,7 +2484,7 @@ pub fn run_falconf(ctx: &ExecutionContext) -> Result<()> {
 
     print_separator("falconf sync");
 
-    ctx.execute(falconf).arg("sync").status_checked()
+    ctx.execute(falconf).arg("sync").extra_args().status_checked()
 }
 
 pub fn run_colima(ctx: &ExecutionContext) -> Result<()> {
@@ -2280,7 +2492,7 @@ pub fn run_colima(ctx: &ExecutionContext) -> Result<()> {
 
     print_separator("Colima");
 
-    ctx.execute(colima).arg("update").status_checked()
+    ctx.execute(colima).arg("update").extra_args().status_checked()
 }
 
 pub fn run_cursor_agent(ctx: &ExecutionContext) -> Result<()> {
@@ -2288,7 +2500,7 @@ pub fn run_cursor_agent(ctx: &ExecutionContext) -> Result<()> {
 
     print_separator("Cursor Agent");
 
-    ctx.execute(cursor_agent).arg("update").status_checked()
+    ctx.execute(cursor_agent).arg("update").extra_args().status_checked()
 }
 
 pub fn run_skills(ctx: &ExecutionContext) -> Result<()> {
@@ -2319,7 +2531,7 @@ pub fn run_skills(ctx: &ExecutionContext) -> Result<()> {
 
     command.args(["skills", "update", "--global"]);
 
-    command.status_checked()
+    command.extra_args().status_checked()
 }
 
 pub fn run_opencode(ctx: &ExecutionContext) -> Result<()> {
@@ -2333,7 +2545,7 @@ pub fn run_opencode(ctx: &ExecutionContext) -> Result<()> {
         return Err(SkipStep(t!("OpenCode not installed with the official script").to_string()).into());
     }
     print_separator("OpenCode");
-    ctx.execute(opencode).arg("upgrade").status_checked()
+    ctx.execute(opencode).arg("upgrade").extra_args().status_checked()
 }
 
 fn ollama_serve(ctx: &ExecutionContext, ollama: &Path) -> Result<ExecutorChild> {
@@ -2442,7 +2654,12 @@ pub fn run_ollama_pull(ctx: &ExecutionContext) -> Result<()> {
 
     let mut pull_result = Ok(());
     for model in remote_models {
-        if let Err(e) = ctx.execute(&ollama).args(["pull", &model.name]).status_checked() {
+        if let Err(e) = ctx
+            .execute(&ollama)
+            .args(["pull", &model.name])
+            .extra_args()
+            .status_checked()
+        {
             pull_result = Err(e);
             break;
         }
@@ -2490,28 +2707,29 @@ pub fn run_mise(ctx: &ExecutionContext) -> Result<()> {
         }
     }
 
+    let options = ctx.config().step_options::<Mise>();
     let mut cmd = ctx.execute(&mise);
 
     cmd.arg("upgrade");
     cmd.current_dir(temp_dir.path());
 
-    if ctx.config().mise_interactive() {
+    if options.interactive.unwrap_or(false) {
         cmd.arg("--interactive");
     }
 
-    if ctx.config().mise_bump() {
+    if options.bump.unwrap_or(false) {
         cmd.arg("--bump");
     }
 
-    if ctx.config().mise_silent() {
+    if options.silent.unwrap_or(false) {
         cmd.arg("--silent");
     }
 
-    if ctx.config().mise_quiet() {
+    if options.quiet.unwrap_or(false) {
         cmd.arg("--quiet");
     }
 
-    if ctx.config().mise_verbose() {
+    if options.verbose.unwrap_or(false) {
         cmd.arg("--verbose");
     }
 
@@ -2519,11 +2737,11 @@ pub fn run_mise(ctx: &ExecutionContext) -> Result<()> {
         cmd.arg("--yes");
     }
 
-    if let Some(jobs) = ctx.config().mise_jobs() {
+    if let Some(jobs) = options.jobs {
         cmd.args(["--jobs", &jobs.to_string()]);
     }
 
-    cmd.status_checked()?;
+    cmd.extra_args().status_checked()?;
 
     if ctx.config().cleanup() {
         cmd = ctx.execute(&mise);
@@ -16,7 +16,7 @@ use crate::command::CommandExt;
 use crate::execution_context::ExecutionContext;
 use crate::step::Step;
 use crate::steps::emacs::Emacs;
-use crate::terminal::print_separator;
+use crate::terminal::{print_line, print_separator};
 use crate::utils::{PathExt, require};
 use crate::{HOME_DIR, error::SkipStep, terminal::print_warning};
 use etcetera::base_strategy::BaseStrategy;
@@ -433,7 +433,7 @@ impl RepoStep {
 
         if ctx.config().verbose() {
             let action = if fetching { t!("Fetching") } else { t!("Pulling") };
-            println!("{} {}", style(action).cyan().bold(), repo.as_ref().display());
+            print_line(format!("{} {}", style(action).cyan().bold(), repo.as_ref().display()));
         }
 
         let mut command = AsyncCommand::new(&self.git);
@@ -462,18 +462,22 @@ impl RepoStep {
 
         if result.is_err() {
             let action = if fetching { t!("fetching") } else { t!("pulling") };
-            println!(
+            print_line(format!(
                 "{} {} {}",
                 style(t!("Failed")).red().bold(),
                 action,
                 repo.as_ref().display()
-            );
+            ));
         } else {
             let after_revision = get_revision(ctx, &self.git, repo.as_ref(), tracked_revision);
 
             match (&before_revision, &after_revision) {
                 (Some(before), Some(after)) if before != after => {
-                    println!("{} {}", style(t!("Changed")).yellow().bold(), repo.as_ref().display());
+                    print_line(format!(
+                        "{} {}",
+                        style(t!("Changed")).yellow().bold(),
+                        repo.as_ref().display()
+                    ));
 
                     ctx.execute(&self.git)
                         .always()
@@ -487,11 +491,15 @@ impl RepoStep {
                             &format!("{before}..{after}"),
                         ])
                         .status_checked()?;
-                    println!();
+                    print_line("");
                 }
                 _ => {
                     if ctx.config().verbose() {
-                        println!("{} {}", style(t!("Up-to-date")).green().bold(), repo.as_ref().display());
+                        print_line(format!(
+                            "{} {}",
+                            style(t!("Up-to-date")).green().bold(),
+                            repo.as_ref().display()
+                        ));
                     }
                 }
             }
@@ -525,18 +533,18 @@ impl RepoStep {
                 } else {
                     t!("Would pull {repo}", repo = repo.display())
                 };
-                println!("{}", message);
+                print_line(message);
             });
 
             return Ok(());
         }
 
         if !ctx.config().verbose() {
-            println!(
+            print_line(format!(
                 "\n{} {}\n",
                 style(t!("Only")).green().bold(),
                 t!("updated repositories will be shown...")
-            );
+            ));
         }
 
         let futures_iterator = self
@@ -544,12 +552,12 @@ impl RepoStep {
             .iter()
             .filter(|repo| match self.has_remotes(ctx, repo) {
                 Some(false) => {
-                    println!(
+                    print_line(format!(
                         "{} {} {}",
                         style(t!("Skipping")).yellow().bold(),
                         repo.display(),
                         t!("because it has no remotes")
-                    );
+                    ));
                     false
                 }
                 _ => true, // repo has remotes or command to check for remotes has failed. proceed to pull anyway.
@@ -14,7 +14,7 @@ pub fn run_go_global_update(ctx: &ExecutionContext) -> Result<()> {
 
     print_separator("go-global-update");
 
-    ctx.execute(go_global_update).status_checked()
+    ctx.execute(go_global_update).extra_args().status_checked()
 }
 
 /// <https://github.com/nao1215/gup>
@@ -30,7 +30,7 @@ pub fn run_go_gup(ctx: &ExecutionContext) -> Result<()> {
         command.args(["--exclude", exclude]);
     }
 
-    command.status_checked()
+    command.extra_args().status_checked()
 }
 
 /// Get the path of a Go binary.
@@ -6,6 +6,7 @@ pub mod go;
 pub mod kakoune;
 pub mod node;
 pub mod os;
+pub mod plugins;
 pub mod powershell;
 pub mod remote;
 #[cfg(unix)]
@@ -4,14 +4,17 @@ use std::os::unix::fs::MetadataExt;
 use std::path::PathBuf;
 
 use crate::HOME_DIR;
-use color_eyre::eyre::Result;
+use color_eyre::eyre::{Context, Result, bail};
+use indexmap::IndexMap;
 #[cfg(target_os = "linux")]
 use nix::unistd::Uid;
 use rust_i18n::t;
 use semver::Version;
+use serde::Deserialize;
 use tracing::debug;
 
 use crate::command::CommandExt;
+use crate::runner::PendingUpdate;
 use crate::terminal::{print_info, print_separator};
 use crate::utils::{PathExt, require};
 use crate::{error::SkipStep, execution_context::ExecutionContext};
@@ -90,9 +93,12 @@ impl Npm {
         let args = ["update", self.global_location_arg(ctx)];
         if use_sudo {
             let sudo = ctx.require_sudo()?;
-            sudo.execute(ctx, &self.command)?.args(args).status_checked()?;
+            sudo.execute(ctx, &self.command)?
+                .args(args)
+                .extra_args()
+                .status_checked()?;
         } else {
-            ctx.execute(&self.command).args(args).status_checked()?;
+            ctx.execute(&self.command).args(args).extra_args().status_checked()?;
         }
 
         Ok(())
@@ -151,9 +157,12 @@ impl Yarn {
 
         if use_sudo {
             let sudo = ctx.require_sudo()?;
-            sudo.execute(ctx, &self.command)?.args(args).status_checked()?;
+            sudo.execute(ctx, &self.command)?
+                .args(args)
+                .extra_args()
+                .status_checked()?;
         } else {
-            ctx.execute(&self.command).args(args).status_checked()?;
+            ctx.execute(&self.command).args(args).extra_args().status_checked()?;
         }
 
         Ok(())
@@ -234,7 +243,11 @@ impl Deno {
             }
         }
 
-        ctx.execute(&self.command).arg("upgrade").args(args).status_checked()?;
+        ctx.execute(&self.command)
+            .arg("upgrade")
+            .args(args)
+            .extra_args()
+            .status_checked()?;
         Ok(())
     }
 
@@ -288,9 +301,12 @@ impl VitePlus {
 
         if use_sudo {
             let sudo = ctx.require_sudo()?;
-            sudo.execute(ctx, &self.command)?.args(args).status_checked()?;
+            sudo.execute(ctx, &self.command)?
+                .args(args)
+                .extra_args()
+                .status_checked()?;
         } else {
-            ctx.execute(&self.command).args(args).status_checked()?;
+            ctx.execute(&self.command).args(args).extra_args().status_checked()?;
         }
 
         Ok(())
@@ -369,6 +385,59 @@ pub fn run_npm_upgrade(ctx: &ExecutionContext) -> Result<()> {
     }
 }
 
+pub fn check_npm(ctx: &ExecutionContext) -> Result<Vec<PendingUpdate>> {
+    let npm = require("npm").map(|b| Npm::new(b, NPMVariant::Npm))?;
+
+    print_separator(t!("Node Package Manager"));
+
+    // `npm outdated` exits with 1 if anything is outdated.
+    let output = ctx
+        .execute(&npm.command)
+        .always()
+        .args(["outdated", npm.global_location_arg(ctx), "--json"])
+        .output_checked_with(|output| match output.status.code() {
+            Some(0 | 1) => Ok(()),
+            _ => Err(()),
+        })?;
+
+    parse_npm_outdated(&String::from_utf8_lossy(&output.stdout))
+}
+
+/// Parse the output of `npm outdated --json`.
+fn parse_npm_outdated(output: &str) -> Result<Vec<PendingUpdate>> {
+    #[derive(Deserialize)]
+    struct Outdated {
+        current: Option<String>,
+        latest: Option<String>,
+    }
+
+    #[derive(Deserialize)]
+    struct Error {
+        summary: String,
+    }
+
+    /// npm exits with 1 on errors too, which it reports in the JSON output.
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Output {
+        Error { error: Error },
+        Outdated(IndexMap<String, Outdated>),
+    }
+
+    if output.trim().is_empty() {
+        return Ok(Vec::new());
+    }
+    match serde_json::from_str(output).context("Failed to parse the output of npm")? {
+        Output::Error { error } => bail!("npm failed: {}", error.summary),
+        Output::Outdated(outdated) => Ok(outdated
+            .into_iter()
+            .map(|(package, outdated)| {
+                PendingUpdate::new(package, outdated.current.as_deref(), outdated.latest.as_deref())
+            })
+            .collect()),
+    }
+}
+
 pub fn run_pnpm_upgrade(ctx: &ExecutionContext) -> Result<()> {
     let pnpm = require("pnpm").map(|b| Npm::new(b, NPMVariant::Pnpm))?;
 
@@ -479,8 +548,33 @@ pub fn run_volta_packages_upgrade(ctx: &ExecutionContext) -> Result<()> {
     }
 
     for package in &installed_packages {
-        ctx.execute(&volta).args(["install", package]).status_checked()?;
+        ctx.execute(&volta)
+            .args(["install", package])
+            .extra_args()
+            .status_checked()?;
     }
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_npm_outdated() {
+        let output = r#"{
+  "npm": {"current": "10.2.4", "wanted": "10.2.5", "latest": "10.2.5", "location": "/usr/lib/node_modules/npm"},
+  "typescript": {"current": "5.3.2", "wanted": "5.3.3", "latest": "5.3.3", "location": "/usr/lib/node_modules/typescript"}
+}"#;
+        assert_eq!(
+            parse_npm_outdated(output).unwrap(),
+            [
+                PendingUpdate::new("npm", Some("10.2.4"), Some("10.2.5")),
+                PendingUpdate::new("typescript", Some("5.3.2"), Some("5.3.3")),
+            ]
+        );
+        assert!(parse_npm_outdated("").unwrap().is_empty());
+        assert!(parse_npm_outdated(r#"{"error": {"code": "ENOTFOUND", "summary": "request failed"}}"#).is_err());
+    }
+}
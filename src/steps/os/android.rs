@@ -20,7 +20,7 @@ pub fn upgrade_packages(ctx: &ExecutionContext) -> Result<()> {
     if ctx.config().yes(Step::System) {
         command.arg("-y");
     }
-    command.status_checked()?;
+    command.extra_args().status_checked()?;
 
     if !is_nala && ctx.config().cleanup() {
         ctx.execute(&pkg).arg("clean").status_checked()?;
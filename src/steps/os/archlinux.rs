@@ -40,7 +40,7 @@ impl ArchPackageManager for YayParu {
         if ctx.config().yes(Step::System) {
             command.arg("--noconfirm");
         }
-        command.status_checked()?;
+        command.extra_args().status_checked()?;
 
         if ctx.config().cleanup() {
             let mut command = ctx.execute(&self.executable);
@@ -78,7 +78,7 @@ impl ArchPackageManager for GarudaUpdate {
             command.env("PACMAN_NOCONFIRM", "1");
         }
         command.args(ctx.config().garuda_update_arguments().split_whitespace());
-        command.status_checked()?;
+        command.extra_args().status_checked()?;
 
         Ok(())
     }
@@ -107,7 +107,7 @@ impl ArchPackageManager for Trizen {
         if ctx.config().yes(Step::System) {
             command.arg("--noconfirm");
         }
-        command.status_checked()?;
+        command.extra_args().status_checked()?;
 
         if ctx.config().cleanup() {
             let mut command = ctx.execute(&self.executable);
@@ -142,7 +142,7 @@ impl ArchPackageManager for Pacman {
         if ctx.config().yes(Step::System) {
             command.arg("--noconfirm");
         }
-        command.status_checked()?;
+        command.extra_args().status_checked()?;
 
         if ctx.config().cleanup() {
             let mut command = sudo.execute(ctx, &self.executable)?;
@@ -189,7 +189,7 @@ impl ArchPackageManager for Pikaur {
             command.arg("--noconfirm");
         }
 
-        command.status_checked()?;
+        command.extra_args().status_checked()?;
 
         if ctx.config().cleanup() {
             let mut command = ctx.execute(&self.executable);
@@ -227,7 +227,7 @@ impl ArchPackageManager for Pamac {
             command.arg("--no-confirm");
         }
 
-        command.status_checked()?;
+        command.extra_args().status_checked()?;
 
         if ctx.config().cleanup() {
             let mut command = ctx.execute(&self.executable);
@@ -289,7 +289,7 @@ impl ArchPackageManager for Aura {
             if ctx.config().yes(Step::System) {
                 cmd.arg("--noconfirm");
             }
-            cmd.status_checked()?;
+            cmd.extra_args().status_checked()?;
         } else {
             let sudo = ctx.require_sudo()?;
 
@@ -307,7 +307,7 @@ impl ArchPackageManager for Aura {
             if ctx.config().yes(Step::System) {
                 cmd.arg("--noconfirm");
             }
-            cmd.status_checked()?;
+            cmd.extra_args().status_checked()?;
         }
 
         Ok(())
@@ -358,7 +358,7 @@ impl ArchPackageManager for Shelly {
             cmd.arg("--no-confirm");
         }
 
-        cmd.status_checked()?;
+        cmd.extra_args().status_checked()?;
 
         Ok(())
     }
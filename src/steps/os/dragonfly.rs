@@ -14,7 +14,7 @@ pub fn upgrade_packages(ctx: &ExecutionContext) -> Result<()> {
     if ctx.config().yes(Step::System) {
         cmd.arg("-y");
     }
-    cmd.status_checked()
+    cmd.extra_args().status_checked()
 }
 
 pub fn audit_packages(ctx: &ExecutionContext) -> Result<()> {
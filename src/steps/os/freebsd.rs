@@ -11,6 +11,7 @@ pub fn upgrade_freebsd(ctx: &ExecutionContext) -> Result<()> {
     let sudo = ctx.require_sudo()?;
     sudo.execute(ctx, "/usr/sbin/freebsd-update")?
         .args(["fetch", "install"])
+        .extra_args()
         .status_checked()
 }
 
@@ -23,7 +24,7 @@ pub fn upgrade_packages(ctx: &ExecutionContext) -> Result<()> {
     if ctx.config().yes(Step::System) {
         command.arg("-y");
     }
-    command.status_checked()
+    command.extra_args().status_checked()
 }
 
 pub fn audit_packages(ctx: &ExecutionContext) -> Result<()> {
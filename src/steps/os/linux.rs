@@ -11,6 +11,7 @@ use crate::command::CommandExt;
 use crate::config::NixHandler;
 use crate::error::{SkipStep, TopgradeError};
 use crate::execution_context::ExecutionContext;
+use crate::runner::PendingUpdate;
 use crate::step::Step;
 use crate::steps::generic::IS_WSL;
 use crate::steps::os::archlinux;
@@ -142,6 +143,14 @@ impl Distribution {
         Err(TopgradeError::EmptyOSReleaseFile.into())
     }
 
+    /// List the updates `upgrade` would install, see `--check`.
+    pub fn check(self, ctx: &ExecutionContext) -> Result<Vec<PendingUpdate>> {
+        match self {
+            Distribution::Debian => check_debian(ctx),
+            _ => Err(SkipStep(t!("Checking for updates is not supported on this distribution").to_string()).into()),
+        }
+    }
+
     pub fn upgrade(self, ctx: &ExecutionContext) -> Result<()> {
         print_separator(t!("System update"));
 
@@ -214,7 +223,7 @@ fn upgrade_aosc(ctx: &ExecutionContext) -> Result<()> {
         cmd.arg("-y");
     }
 
-    cmd.status_checked()
+    cmd.extra_args().status_checked()
 }
 
 fn upgrade_alpine_linux(ctx: &ExecutionContext) -> Result<()> {
@@ -222,7 +231,7 @@ fn upgrade_alpine_linux(ctx: &ExecutionContext) -> Result<()> {
     let sudo = ctx.require_sudo()?;
 
     sudo.execute(ctx, &apk)?.arg("update").status_checked()?;
-    sudo.execute(ctx, &apk)?.arg("upgrade").status_checked()
+    sudo.execute(ctx, &apk)?.arg("upgrade").extra_args().status_checked()
 }
 
 fn upgrade_chimera_linux(ctx: &ExecutionContext) -> Result<()> {
@@ -230,7 +239,7 @@ fn upgrade_chimera_linux(ctx: &ExecutionContext) -> Result<()> {
     let sudo = ctx.require_sudo()?;
 
     sudo.execute(ctx, &apk)?.arg("update").status_checked()?;
-    sudo.execute(ctx, &apk)?.arg("upgrade").status_checked()
+    sudo.execute(ctx, &apk)?.arg("upgrade").extra_args().status_checked()
 }
 
 fn upgrade_wolfi_linux(ctx: &ExecutionContext) -> Result<()> {
@@ -238,7 +247,7 @@ fn upgrade_wolfi_linux(ctx: &ExecutionContext) -> Result<()> {
     let sudo = ctx.require_sudo()?;
 
     sudo.execute(ctx, &apk)?.arg("update").status_checked()?;
-    sudo.execute(ctx, &apk)?.arg("upgrade").status_checked()
+    sudo.execute(ctx, &apk)?.arg("upgrade").extra_args().status_checked()
 }
 
 fn upgrade_redhat(ctx: &ExecutionContext) -> Result<()> {
@@ -246,7 +255,7 @@ fn upgrade_redhat(ctx: &ExecutionContext) -> Result<()> {
         && ctx.config().bootc()
     {
         let sudo = ctx.require_sudo()?;
-        return sudo.execute(ctx, &bootc)?.arg("upgrade").status_checked();
+        return sudo.execute(ctx, &bootc)?.arg("upgrade").extra_args().status_checked();
     }
 
     if let Some(ostree) = which("rpm-ostree")
@@ -254,7 +263,7 @@ fn upgrade_redhat(ctx: &ExecutionContext) -> Result<()> {
     {
         let mut command = ctx.execute(ostree);
         command.arg("upgrade");
-        return command.status_checked();
+        return command.extra_args().status_checked();
     };
 
     let dnf = require_one(["dnf", "yum"])?;
@@ -275,7 +284,7 @@ fn upgrade_redhat(ctx: &ExecutionContext) -> Result<()> {
         command.arg("-y");
     }
 
-    command.status_checked()?;
+    command.extra_args().status_checked()?;
     Ok(())
 }
 
@@ -307,7 +316,7 @@ fn upgrade_nobara(ctx: &ExecutionContext) -> Result<()> {
 
     upgrade_command.arg("distro-sync");
 
-    upgrade_command.status_checked()?;
+    upgrade_command.extra_args().status_checked()?;
     Ok(())
 }
 
@@ -316,7 +325,7 @@ fn upgrade_nilrt(ctx: &ExecutionContext) -> Result<()> {
     let sudo = ctx.require_sudo()?;
 
     sudo.execute(ctx, &opkg)?.arg("update").status_checked()?;
-    sudo.execute(ctx, &opkg)?.arg("upgrade").status_checked()
+    sudo.execute(ctx, &opkg)?.arg("upgrade").extra_args().status_checked()
 }
 
 fn upgrade_fedora_immutable(ctx: &ExecutionContext) -> Result<()> {
@@ -324,13 +333,13 @@ fn upgrade_fedora_immutable(ctx: &ExecutionContext) -> Result<()> {
         && ctx.config().bootc()
     {
         let sudo = ctx.require_sudo()?;
-        return sudo.execute(ctx, &bootc)?.arg("upgrade").status_checked();
+        return sudo.execute(ctx, &bootc)?.arg("upgrade").extra_args().status_checked();
     }
 
     let ostree = require("rpm-ostree")?;
     let mut command = ctx.execute(ostree);
     command.arg("upgrade");
-    command.status_checked()?;
+    command.extra_args().status_checked()?;
     Ok(())
 }
 
@@ -338,7 +347,7 @@ fn upgrade_bedrock_strata(ctx: &ExecutionContext) -> Result<()> {
     let brl = require("brl")?;
     let sudo = ctx.require_sudo()?;
 
-    sudo.execute(ctx, &brl)?.arg("update").status_checked()?;
+    sudo.execute(ctx, &brl)?.arg("update").extra_args().status_checked()?;
 
     Ok(())
 }
@@ -359,7 +368,7 @@ fn upgrade_suse(ctx: &ExecutionContext) -> Result<()> {
         cmd.arg("-y");
     }
 
-    cmd.status_checked()?;
+    cmd.extra_args().status_checked()?;
 
     Ok(())
 }
@@ -376,7 +385,7 @@ fn upgrade_opensuse_tumbleweed(ctx: &ExecutionContext) -> Result<()> {
         cmd.arg("-y");
     }
 
-    cmd.status_checked()?;
+    cmd.extra_args().status_checked()?;
 
     Ok(())
 }
@@ -390,7 +399,7 @@ fn upgrade_suse_micro(ctx: &ExecutionContext) -> Result<()> {
         cmd.arg("-n");
     }
 
-    cmd.arg("dup").status_checked()?;
+    cmd.arg("dup").extra_args().status_checked()?;
 
     Ok(())
 }
@@ -411,7 +420,7 @@ fn upgrade_openmandriva(ctx: &ExecutionContext) -> Result<()> {
         command.arg("-y");
     }
 
-    command.status_checked()?;
+    command.extra_args().status_checked()?;
 
     Ok(())
 }
@@ -439,7 +448,7 @@ fn upgrade_pclinuxos(ctx: &ExecutionContext) -> Result<()> {
     if ctx.config().yes(Step::System) {
         cmd.arg("-y");
     }
-    cmd.status_checked()?;
+    cmd.extra_args().status_checked()?;
 
     Ok(())
 }
@@ -459,7 +468,7 @@ fn upgrade_vanilla(ctx: &ExecutionContext) -> Result<()> {
     if ctx.config().yes(Step::System) {
         upgrade.arg("-y");
     }
-    upgrade.status_checked()?;
+    upgrade.extra_args().status_checked()?;
 
     Ok(())
 }
@@ -480,7 +489,7 @@ fn upgrade_void(ctx: &ExecutionContext) -> Result<()> {
     if ctx.config().yes(Step::System) {
         command.arg("-y");
     }
-    command.status_checked()?;
+    command.extra_args().status_checked()?;
 
     Ok(())
 }
@@ -520,6 +529,7 @@ fn upgrade_gentoo(ctx: &ExecutionContext) -> Result<()> {
                 .map(|s| s.split_whitespace().collect())
                 .unwrap_or_else(|| vec!["-uDNa", "--with-bdeps=y", "world"]),
         )
+        .extra_args()
         .status_checked()?;
 
     Ok(())
@@ -557,7 +567,7 @@ fn upgrade_debian(ctx: &ExecutionContext) -> Result<()> {
     // MIST does not require `sudo`
     if let Mist = kind {
         ctx.execute(&apt).arg("update").status_checked()?;
-        ctx.execute(&apt).arg("upgrade").status_checked()?;
+        ctx.execute(&apt).arg("upgrade").extra_args().status_checked()?;
 
         // Simply return as MIST does not have `clean` and `autoremove`
         // subcommands, neither the `-y` option (for now maybe?).
@@ -583,7 +593,7 @@ fn upgrade_debian(ctx: &ExecutionContext) -> Result<()> {
     if let Some(args) = ctx.config().apt_arguments() {
         command.args(args.split_whitespace());
     }
-    command.status_checked()?;
+    command.extra_args().status_checked()?;
 
     if ctx.config().cleanup() {
         sudo.execute(ctx, &apt)?.arg("clean").status_checked()?;
@@ -599,6 +609,38 @@ fn upgrade_debian(ctx: &ExecutionContext) -> Result<()> {
     Ok(())
 }
 
+/// List the upgradable packages, as of the last `apt update`.
+fn check_debian(ctx: &ExecutionContext) -> Result<Vec<PendingUpdate>> {
+    let apt = require("apt")?;
+    print_separator(t!("System update"));
+
+    let output = ctx
+        .execute(apt)
+        .always()
+        .args(["list", "--upgradable"])
+        .output_checked_utf8()?;
+
+    Ok(parse_apt_upgradable(&output.stdout))
+}
+
+/// Parse the output of `apt list --upgradable`, lines like
+/// `bash/noble-updates 5.2.21-2ubuntu4.1 amd64 [upgradable from: 5.2.21-2ubuntu4]`.
+fn parse_apt_upgradable(output: &str) -> Vec<PendingUpdate> {
+    output
+        .lines()
+        .filter_map(|line| {
+            let (package, rest) = line.split_once('/')?;
+            let (_, current) = rest.split_once("[upgradable from: ")?;
+            let available = rest.split_whitespace().nth(1)?;
+            Some(PendingUpdate::new(
+                package,
+                Some(current.trim_end_matches(']')),
+                Some(available),
+            ))
+        })
+        .collect()
+}
+
 pub fn run_deb_get(ctx: &ExecutionContext) -> Result<()> {
     let deb_get = require("deb-get")?;
 
@@ -619,7 +661,11 @@ pub fn run_deb_get(ctx: &ExecutionContext) -> Result<()> {
     };
 
     base_cmd().arg("update").status_checked()?;
-    base_cmd().arg("upgrade").args(upgrade_opt).status_checked()?;
+    base_cmd()
+        .arg("upgrade")
+        .args(upgrade_opt)
+        .extra_args()
+        .status_checked()?;
 
     if ctx.config().cleanup() {
         let output = ctx.execute(&deb_get).arg("clean").output_checked()?;
@@ -640,7 +686,7 @@ fn upgrade_solus(ctx: &ExecutionContext) -> Result<()> {
     if ctx.config().yes(Step::System) {
         cmd.arg("-y");
     }
-    cmd.arg("upgrade").status_checked()?;
+    cmd.arg("upgrade").extra_args().status_checked()?;
 
     Ok(())
 }
@@ -658,7 +704,7 @@ pub fn run_am(ctx: &ExecutionContext) -> Result<()> {
         am.arg("-u");
     }
 
-    am.status_checked()
+    am.extra_args().status_checked()
 }
 
 pub fn run_appman(ctx: &ExecutionContext) -> Result<()> {
@@ -666,7 +712,7 @@ pub fn run_appman(ctx: &ExecutionContext) -> Result<()> {
 
     print_separator("appman");
 
-    ctx.execute(appman).arg("-u").status_checked()
+    ctx.execute(appman).arg("-u").extra_args().status_checked()
 }
 
 pub fn run_pacdef(ctx: &ExecutionContext) -> Result<()> {
@@ -684,7 +730,7 @@ pub fn run_pacdef(ctx: &ExecutionContext) -> Result<()> {
         if ctx.config().yes(Step::System) {
             cmd.arg("--noconfirm");
         }
-        cmd.status_checked()?;
+        cmd.extra_args().status_checked()?;
 
         println!();
         ctx.execute(&pacdef).args(["package", "review"]).status_checked()?;
@@ -695,7 +741,7 @@ pub fn run_pacdef(ctx: &ExecutionContext) -> Result<()> {
             cmd.arg("--noconfirm");
         }
 
-        cmd.status_checked()?;
+        cmd.extra_args().status_checked()?;
 
         println!();
         ctx.execute(&pacdef).arg("review").status_checked()?;
@@ -717,7 +763,7 @@ pub fn run_pacstall(ctx: &ExecutionContext) -> Result<()> {
     }
 
     update_cmd.arg("-U").status_checked()?;
-    upgrade_cmd.arg("-Up").status_checked()
+    upgrade_cmd.arg("-Up").extra_args().status_checked()
 }
 
 pub fn run_pkgfile(ctx: &ExecutionContext) -> Result<()> {
@@ -730,7 +776,10 @@ pub fn run_pkgfile(ctx: &ExecutionContext) -> Result<()> {
     print_separator("pkgfile");
 
     let sudo = ctx.require_sudo()?;
-    sudo.execute(ctx, pkgfile)?.arg("--update").status_checked()
+    sudo.execute(ctx, pkgfile)?
+        .arg("--update")
+        .extra_args()
+        .status_checked()
 }
 
 pub fn run_mandb(ctx: &ExecutionContext) -> Result<()> {
@@ -743,11 +792,11 @@ pub fn run_mandb(ctx: &ExecutionContext) -> Result<()> {
     print_separator(t!("System Manuals"));
 
     let sudo = ctx.require_sudo()?;
-    sudo.execute(ctx, &mandb)?.status_checked()?;
+    sudo.execute(ctx, &mandb)?.extra_args().status_checked()?;
 
     print_separator(t!("User Manuals"));
 
-    ctx.execute(&mandb).arg("--user-db").status_checked()
+    ctx.execute(&mandb).arg("--user-db").extra_args().status_checked()
 }
 
 pub fn run_packer_nu(ctx: &ExecutionContext) -> Result<()> {
@@ -777,7 +826,7 @@ fn upgrade_clearlinux(ctx: &ExecutionContext) -> Result<()> {
     if ctx.config().yes(Step::System) {
         cmd.arg("--assume=yes");
     }
-    cmd.status_checked()?;
+    cmd.extra_args().status_checked()?;
 
     Ok(())
 }
@@ -791,6 +840,7 @@ fn upgrade_exherbo(ctx: &ExecutionContext) -> Result<()> {
 
     sudo.execute(ctx, &cave)?
         .args(["resolve", "world", "-c1", "-Cs", "-km", "-Km", "-x"])
+        .extra_args()
         .status_checked()?;
 
     if ctx.config().cleanup() {
@@ -836,7 +886,7 @@ fn upgrade_nixos(ctx: &ExecutionContext) -> Result<()> {
             if let Some(args) = ctx.config().nix_arguments() {
                 command.args(args.split_whitespace());
             }
-            command.status_checked()?;
+            command.extra_args().status_checked()?;
         }
     }
 
@@ -872,7 +922,7 @@ fn upgrade_neon(ctx: &ExecutionContext) -> Result<()> {
         cmd.arg("--autoremove");
     }
     // from pkcon man, exit code 5 is 'Nothing useful was done.'
-    cmd.status_checked_with_codes(&[5])?;
+    cmd.extra_args().status_checked_with_codes(&[5])?;
 
     Ok(())
 }
@@ -881,7 +931,7 @@ fn upgrade_kde_linux(ctx: &ExecutionContext) -> Result<()> {
     let updatectl = require("updatectl")?;
     let mut command = ctx.execute(updatectl);
     command.arg("update");
-    command.status_checked()?;
+    command.extra_args().status_checked()?;
 
     Ok(())
 }
@@ -922,7 +972,7 @@ pub fn run_needrestart(ctx: &ExecutionContext) -> Result<()> {
     print_separator(t!("Check for needed restarts"));
 
     let sudo = ctx.require_sudo()?;
-    sudo.execute(ctx, &needrestart)?.status_checked()?;
+    sudo.execute(ctx, &needrestart)?.extra_args().status_checked()?;
 
     Ok(())
 }
@@ -945,7 +995,7 @@ pub fn run_fwupdmgr(ctx: &ExecutionContext) -> Result<()> {
         if ctx.config().yes(Step::System) {
             updmgr.arg("-y");
         }
-        updmgr.status_checked_with_codes(&[2])
+        updmgr.extra_args().status_checked_with_codes(&[2])
     } else {
         updmgr.arg("get-updates");
 
@@ -974,6 +1024,36 @@ pub fn run_fwupdmgr(ctx: &ExecutionContext) -> Result<()> {
     }
 }
 
+pub fn check_flatpak(ctx: &ExecutionContext) -> Result<Vec<PendingUpdate>> {
+    let flatpak = require("flatpak")?;
+    print_separator("Flatpak");
+
+    // Lists the updates of both the user and the system installation.
+    let output = ctx
+        .execute(flatpak)
+        .always()
+        .args(["remote-ls", "--updates", "--columns=application,version"])
+        .output_checked_utf8()?;
+
+    Ok(parse_flatpak_updates(&output.stdout))
+}
+
+/// Parse the output of `flatpak remote-ls --updates --columns=application,version`.
+fn parse_flatpak_updates(output: &str) -> Vec<PendingUpdate> {
+    output
+        .lines()
+        .filter_map(|line| {
+            let mut columns = line.split('\t').map(str::trim);
+            let application = columns.next().filter(|application| !application.is_empty())?;
+            if application == "Application ID" {
+                return None;
+            }
+            let version = columns.next().filter(|version| !version.is_empty());
+            Some(PendingUpdate::new(application, None, version))
+        })
+        .collect()
+}
+
 pub fn run_flatpak(ctx: &ExecutionContext) -> Result<()> {
     let flatpak = require("flatpak")?;
 
@@ -985,7 +1065,7 @@ pub fn run_flatpak(ctx: &ExecutionContext) -> Result<()> {
     if yes {
         update_args.push("-y");
     }
-    ctx.execute(&flatpak).args(&update_args).status_checked()?;
+    ctx.execute(&flatpak).args(&update_args).extra_args().status_checked()?;
 
     if cleanup {
         let mut cleanup_args = vec!["uninstall", "--user", "--unused"];
@@ -1002,7 +1082,10 @@ pub fn run_flatpak(ctx: &ExecutionContext) -> Result<()> {
         if yes {
             update_args.push("-y");
         }
-        sudo.execute(ctx, &flatpak)?.args(&update_args).status_checked()?;
+        sudo.execute(ctx, &flatpak)?
+            .args(&update_args)
+            .extra_args()
+            .status_checked()?;
         if cleanup {
             let mut cleanup_args = vec!["uninstall", "--system", "--unused"];
             if yes {
@@ -1015,7 +1098,7 @@ pub fn run_flatpak(ctx: &ExecutionContext) -> Result<()> {
         if yes {
             update_args.push("-y");
         }
-        ctx.execute(&flatpak).args(&update_args).status_checked()?;
+        ctx.execute(&flatpak).args(&update_args).extra_args().status_checked()?;
         if cleanup {
             let mut cleanup_args = vec!["uninstall", "--system", "--unused"];
             if yes {
@@ -1037,7 +1120,7 @@ pub fn run_snap(ctx: &ExecutionContext) -> Result<()> {
     print_separator("snap");
 
     let sudo = ctx.require_sudo()?;
-    sudo.execute(ctx, &snap)?.arg("refresh").status_checked()
+    sudo.execute(ctx, &snap)?.arg("refresh").extra_args().status_checked()
 }
 
 pub fn run_soar(ctx: &ExecutionContext) -> Result<()> {
@@ -1046,7 +1129,7 @@ pub fn run_soar(ctx: &ExecutionContext) -> Result<()> {
     print_separator("soar");
 
     ctx.execute(&soar).arg("sync").status_checked()?;
-    ctx.execute(&soar).arg("update").status_checked()?;
+    ctx.execute(&soar).arg("update").extra_args().status_checked()?;
     ctx.execute(&soar).args(["self", "update"]).status_checked()
 }
 
@@ -1057,7 +1140,7 @@ pub fn run_pihole_update(ctx: &ExecutionContext) -> Result<()> {
     print_separator("pihole");
 
     let sudo = ctx.require_sudo()?;
-    sudo.execute(ctx, &pihole)?.arg("-up").status_checked()
+    sudo.execute(ctx, &pihole)?.arg("-up").extra_args().status_checked()
 }
 
 pub fn run_protonup_update(ctx: &ExecutionContext) -> Result<()> {
@@ -1069,7 +1152,7 @@ pub fn run_protonup_update(ctx: &ExecutionContext) -> Result<()> {
     if ctx.config().yes(Step::Protonup) {
         cmd.arg("--yes");
     }
-    cmd.status_checked()?;
+    cmd.extra_args().status_checked()?;
 
     Ok(())
 }
@@ -1096,6 +1179,7 @@ pub fn run_distrobox_update(ctx: &ExecutionContext) -> Result<()> {
         (r, true) => r.arg("--root"),
         (r, false) => r,
     }
+    .extra_args()
     .status_checked()
 }
 
@@ -1106,7 +1190,10 @@ pub fn run_dkp_pacman_update(ctx: &ExecutionContext) -> Result<()> {
 
     let sudo = ctx.require_sudo()?;
 
-    sudo.execute(ctx, &dkp_pacman)?.arg("-Syu").status_checked()?;
+    sudo.execute(ctx, &dkp_pacman)?
+        .arg("-Syu")
+        .extra_args()
+        .status_checked()?;
 
     if ctx.config().cleanup() {
         sudo.execute(ctx, &dkp_pacman)?.arg("-Scc").status_checked()?;
@@ -1124,7 +1211,7 @@ pub fn run_config_update(ctx: &ExecutionContext) -> Result<()> {
     if let Ok(etc_update) = require("etc-update") {
         print_separator(t!("Configuration update"));
         let sudo = ctx.require_sudo()?;
-        sudo.execute(ctx, etc_update)?.status_checked()?;
+        sudo.execute(ctx, etc_update)?.extra_args().status_checked()?;
     } else if let Ok(pacdiff) = require("pacdiff") {
         // When `DIFFPROG` is unset, `pacdiff` uses `vim` by default
         if std::env::var("DIFFPROG").is_err() {
@@ -1134,6 +1221,7 @@ pub fn run_config_update(ctx: &ExecutionContext) -> Result<()> {
         print_separator(t!("Configuration update"));
         let sudo = ctx.require_sudo()?;
         sudo.execute_opts(ctx, &pacdiff, SudoExecuteOpts::new().preserve_env_list(&["DIFFPROG"]))?
+            .extra_args()
             .status_checked()?;
     }
 
@@ -1153,7 +1241,7 @@ pub fn run_lure_update(ctx: &ExecutionContext) -> Result<()> {
         exe.arg("up");
     }
 
-    exe.status_checked()
+    exe.extra_args().status_checked()
 }
 
 pub fn run_waydroid(ctx: &ExecutionContext) -> Result<()> {
@@ -1199,7 +1287,10 @@ pub fn run_waydroid(ctx: &ExecutionContext) -> Result<()> {
     }
 
     let sudo = ctx.require_sudo()?;
-    sudo.execute(ctx, &waydroid)?.arg("upgrade").status_checked()
+    sudo.execute(ctx, &waydroid)?
+        .arg("upgrade")
+        .extra_args()
+        .status_checked()
 }
 
 pub fn run_auto_cpufreq(ctx: &ExecutionContext) -> Result<()> {
@@ -1218,7 +1309,10 @@ pub fn run_auto_cpufreq(ctx: &ExecutionContext) -> Result<()> {
     print_separator("auto-cpufreq");
 
     let sudo = ctx.require_sudo()?;
-    sudo.execute(ctx, &auto_cpu_freq)?.arg("--update").status_checked()
+    sudo.execute(ctx, &auto_cpu_freq)?
+        .arg("--update")
+        .extra_args()
+        .status_checked()
 }
 
 pub fn run_gearlever(ctx: &ExecutionContext) -> Result<()> {
@@ -1261,7 +1355,7 @@ pub fn run_gearlever(ctx: &ExecutionContext) -> Result<()> {
         cmd.arg("--yes");
     }
 
-    cmd.status_checked()
+    cmd.extra_args().status_checked()
 }
 
 pub fn run_cinnamon_spices_updater(ctx: &ExecutionContext) -> Result<()> {
@@ -1269,13 +1363,16 @@ pub fn run_cinnamon_spices_updater(ctx: &ExecutionContext) -> Result<()> {
 
     print_separator("Cinnamon spices");
 
-    ctx.execute(cinnamon_spice_updater).arg("--update-all").status_checked()
+    ctx.execute(cinnamon_spice_updater)
+        .arg("--update-all")
+        .extra_args()
+        .status_checked()
 }
 
 pub fn run_pkgit(ctx: &ExecutionContext) -> Result<()> {
     let pkgit = require("pkgit")?;
     print_separator("Pkgit");
-    ctx.execute(pkgit).arg("-u").status_checked()
+    ctx.execute(pkgit).arg("-u").extra_args().status_checked()
 }
 
 pub fn run_protonplus_update(ctx: &ExecutionContext) -> Result<()> {
@@ -1303,13 +1400,40 @@ pub fn run_protonplus_update(ctx: &ExecutionContext) -> Result<()> {
 
     print_separator(if flatpak { "ProtonPlus (Flatpak)" } else { "ProtonPlus" });
 
-    cmd().args(["update", "all"]).status_checked()
+    cmd().args(["update", "all"]).extra_args().status_checked()
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_parse_apt_upgradable() {
+        let output = "Listing...
+bash/noble-updates 5.2.21-2ubuntu4.1 amd64 [upgradable from: 5.2.21-2ubuntu4]
+libc6/noble-updates,noble-security 2.39-0ubuntu8.4 amd64 [upgradable from: 2.39-0ubuntu8.3]
+";
+        assert_eq!(
+            parse_apt_upgradable(output),
+            [
+                PendingUpdate::new("bash", Some("5.2.21-2ubuntu4"), Some("5.2.21-2ubuntu4.1")),
+                PendingUpdate::new("libc6", Some("2.39-0ubuntu8.3"), Some("2.39-0ubuntu8.4")),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_flatpak_updates() {
+        let output = "org.mozilla.firefox\t128.0\norg.freedesktop.Platform.GL.default\t\n";
+        assert_eq!(
+            parse_flatpak_updates(output),
+            [
+                PendingUpdate::new("org.mozilla.firefox", None, Some("128.0")),
+                PendingUpdate::new("org.freedesktop.Platform.GL.default", None, None),
+            ]
+        );
+    }
+
     fn test_template(os_release_file: &str, expected_distribution: Distribution) {
         let os_release = Ini::load_from_str(os_release_file).unwrap();
         assert_eq!(
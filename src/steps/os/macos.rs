@@ -27,7 +27,7 @@ pub fn run_macports(ctx: &ExecutionContext) -> Result<()> {
     if yes {
         cmd.arg("-N");
     }
-    cmd.status_checked()?;
+    cmd.extra_args().status_checked()?;
 
     if ctx.config().cleanup() {
         let mut cmd = sudo.execute(ctx, &port)?;
@@ -45,7 +45,7 @@ pub fn run_mas(ctx: &ExecutionContext) -> Result<()> {
     let mas = require("mas")?;
     print_separator(t!("macOS App Store"));
 
-    ctx.execute(mas).arg("upgrade").status_checked()
+    ctx.execute(mas).arg("upgrade").extra_args().status_checked()
 }
 
 pub fn run_microsoft_office(ctx: &ExecutionContext) -> Result<()> {
@@ -66,6 +66,7 @@ pub fn run_microsoft_office(ctx: &ExecutionContext) -> Result<()> {
     // Install updates, waiting up to 600 seconds for completion
     ctx.execute(&msupdate)
         .args(["--install", "--wait", "600"])
+        .extra_args()
         .status_checked()
 }
 
@@ -112,7 +113,7 @@ pub fn upgrade_macos(ctx: &ExecutionContext) -> Result<()> {
         command.arg("--no-scan");
     }
 
-    command.status_checked()
+    command.extra_args().status_checked()
 }
 
 fn system_update_available(ctx: &ExecutionContext) -> Result<bool> {
@@ -143,7 +144,7 @@ pub fn run_sparkle(ctx: &ExecutionContext) -> Result<()> {
             let mut command = ctx.execute(&sparkle);
             command.arg(application.path());
             command.args(["--check-immediately", "--user-agent-name", "topgrade"]);
-            command.status_checked()?;
+            command.extra_args().status_checked()?;
         }
     }
     Ok(())
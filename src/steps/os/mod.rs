@@ -12,6 +12,8 @@ pub mod linux;
 pub mod macos;
 #[cfg(target_os = "openbsd")]
 pub mod openbsd;
+#[cfg(target_os = "linux")]
+pub mod snapshot;
 #[cfg(unix)]
 pub mod unix;
 #[cfg(windows)]
@@ -23,9 +23,12 @@ pub fn upgrade_openbsd(ctx: &ExecutionContext) -> Result<()> {
     let is_current = is_openbsd_current()?;
 
     if is_current {
-        sudo.execute(ctx, "/usr/sbin/sysupgrade")?.arg("-sn").status_checked()
+        sudo.execute(ctx, "/usr/sbin/sysupgrade")?
+            .arg("-sn")
+            .extra_args()
+            .status_checked()
     } else {
-        sudo.execute(ctx, "/usr/sbin/syspatch")?.status_checked()
+        sudo.execute(ctx, "/usr/sbin/syspatch")?.extra_args().status_checked()
     }
 }
 
@@ -45,5 +48,5 @@ pub fn upgrade_packages(ctx: &ExecutionContext) -> Result<()> {
     if is_current {
         command.arg("-Dsnap");
     }
-    command.status_checked()
+    command.extra_args().status_checked()
 }
@@ -0,0 +1,245 @@
+//! Filesystem snapshots around the system update, see `linux.snapshot`.
+//!
+//! A snapshot is taken before the system update and another one after it, each
+//! showing up in the summary with its ID. If the update fails, the command that
+//! rolls the system back to the first snapshot is printed.
+
+use std::cell::RefCell;
+use std::fmt;
+use std::path::Path;
+
+use chrono::Local;
+use color_eyre::eyre::{Result, eyre};
+use rust_i18n::t;
+
+use crate::command::CommandExt;
+use crate::config::SnapshotTool;
+use crate::execution_context::ExecutionContext;
+use crate::runner::Runner;
+use crate::step::Step;
+use crate::terminal::{print_separator, print_warning};
+use crate::utils::which;
+
+const SYSTEM_UPDATE: &str = "System update";
+
+#[derive(Clone, Copy)]
+enum Phase {
+    Pre,
+    Post,
+}
+
+impl Phase {
+    fn name(self) -> &'static str {
+        match self {
+            Phase::Pre => "pre",
+            Phase::Post => "post",
+        }
+    }
+
+    fn description(self) -> &'static str {
+        match self {
+            Phase::Pre => "topgrade: before the system update",
+            Phase::Post => "topgrade: after the system update",
+        }
+    }
+}
+
+/// A snapshot created by one of the tools.
+struct Snapshot {
+    tool: SnapshotTool,
+    /// The number of a snapper snapshot, the name of a Timeshift one, the path
+    /// of a btrfs one or the `dataset@name` of a ZFS one.
+    id: String,
+}
+
+impl Snapshot {
+    /// The command, to be run as root, that rolls the system back to this snapshot.
+    fn rollback_command(&self) -> String {
+        match self.tool {
+            SnapshotTool::Snapper => format!("snapper rollback {}", self.id),
+            SnapshotTool::Timeshift => format!("timeshift --restore --snapshot {}", shell_words::quote(&self.id)),
+            SnapshotTool::Btrfs => format!("btrfs subvolume set-default {} && reboot", shell_words::quote(&self.id)),
+            SnapshotTool::Zfs => format!("zfs rollback -r {}", shell_words::quote(&self.id)),
+        }
+    }
+}
+
+impl fmt::Display for Snapshot {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}", self.tool, self.id)
+    }
+}
+
+/// Run the system update with `upgrade`, between a snapshot before and one after it.
+///
+/// The system update doesn't run if the snapshot before it couldn't be created,
+/// and is reported as skipped.
+pub fn run_with_snapshots(
+    runner: &mut Runner,
+    ctx: &ExecutionContext,
+    tool: SnapshotTool,
+    upgrade: impl Fn() -> Result<()>,
+) -> Result<()> {
+    let pre = RefCell::new(None);
+    runner.execute_with_note(Step::System, "Snapshot before the system update", || {
+        let snapshot = create(ctx, tool, Phase::Pre, None)?;
+        let note = snapshot.to_string();
+        *pre.borrow_mut() = Some(snapshot);
+        Ok(note)
+    })?;
+
+    let Some(pre) = pre.into_inner() else {
+        // Dry runs don't create snapshots, but show the system update anyway.
+        if ctx.run_type().dry() {
+            runner.execute(Step::System, SYSTEM_UPDATE, upgrade)?;
+        } else {
+            let reason = t!("Not running the system update without a snapshot before it");
+            print_warning(&reason);
+            runner.skip(Step::System, SYSTEM_UPDATE, reason.to_string());
+        }
+        return Ok(());
+    };
+
+    runner.execute(Step::System, SYSTEM_UPDATE, upgrade)?;
+    let failed = runner
+        .report()
+        .last()
+        .is_some_and(|report| report.key == SYSTEM_UPDATE && report.result.failed());
+
+    runner.execute_with_note(Step::System, "Snapshot after the system update", || {
+        create(ctx, tool, Phase::Post, Some(&pre)).map(|snapshot| snapshot.to_string())
+    })?;
+
+    if failed {
+        print_warning(t!(
+            "The system update failed. To roll back to {snapshot}, run as root: {command}",
+            snapshot = pre,
+            command = pre.rollback_command()
+        ));
+    }
+
+    Ok(())
+}
+
+fn require_tool(name: &str) -> Result<std::path::PathBuf> {
+    which(name).ok_or_else(|| eyre!("Cannot find {name}, which linux.snapshot is set to"))
+}
+
+fn create(ctx: &ExecutionContext, tool: SnapshotTool, phase: Phase, pre: Option<&Snapshot>) -> Result<Snapshot> {
+    let binary = require_tool(&tool.to_string())?;
+    print_separator(match phase {
+        Phase::Pre => t!("Snapshot before the system update"),
+        Phase::Post => t!("Snapshot after the system update"),
+    });
+
+    let id = match tool {
+        SnapshotTool::Snapper => create_snapper(ctx, &binary, phase, pre)?,
+        SnapshotTool::Timeshift => create_timeshift(ctx, &binary, phase)?,
+        SnapshotTool::Btrfs => create_btrfs(ctx, &binary, phase)?,
+        SnapshotTool::Zfs => create_zfs(ctx, &binary, phase)?,
+    };
+    Ok(Snapshot { tool, id })
+}
+
+fn snapshot_name(phase: Phase) -> String {
+    format!("topgrade-{}-{}", Local::now().format("%Y-%m-%dT%H-%M-%S"), phase.name())
+}
+
+fn create_snapper(ctx: &ExecutionContext, snapper: &Path, phase: Phase, pre: Option<&Snapshot>) -> Result<String> {
+    let mut args = vec!["create", "--print-number", "--cleanup-algorithm", "number"];
+    args.extend(["--description", phase.description()]);
+    match (phase, pre) {
+        (Phase::Post, Some(pre)) => args.extend(["--type", "post", "--pre-number", &pre.id]),
+        (Phase::Post, None) => args.extend(["--type", "single"]),
+        (Phase::Pre, _) => args.extend(["--type", "pre"]),
+    }
+
+    let output = ctx
+        .require_sudo()?
+        .execute(ctx, snapper)?
+        .args(&args)
+        .output_checked_utf8()?;
+    let number = output.stdout.trim();
+    if number.is_empty() || !number.chars().all(|c| c.is_ascii_digit()) {
+        return Err(eyre!("Unexpected output from snapper: {number}"));
+    }
+    Ok(number.to_string())
+}
+
+fn create_timeshift(ctx: &ExecutionContext, timeshift: &Path, phase: Phase) -> Result<String> {
+    let output = ctx
+        .require_sudo()?
+        .execute(ctx, timeshift)?
+        .args(["--create", "--comments", phase.description(), "--tags", "O"])
+        .output_checked_utf8()?;
+    parse_timeshift_output(&output.stdout)
+        .ok_or_else(|| eyre!("Cannot find the snapshot name in the output of timeshift"))
+}
+
+/// The name of the snapshot in the output of `timeshift --create`, from a line like
+/// `Tagged snapshot '2026-10-18_19-40-00': ondemand`.
+fn parse_timeshift_output(output: &str) -> Option<String> {
+    output.lines().find_map(|line| {
+        let name = line.trim().strip_prefix("Tagged snapshot '")?;
+        name.split_once('\'').map(|(name, _)| name.to_string())
+    })
+}
+
+fn create_btrfs(ctx: &ExecutionContext, btrfs: &Path, phase: Phase) -> Result<String> {
+    // Not read-only, so the system can boot from it after a rollback.
+    let path = format!("{}/{}", ctx.config().linux_snapshot_dir(), snapshot_name(phase));
+    ctx.require_sudo()?
+        .execute(ctx, btrfs)?
+        .args(["subvolume", "snapshot", "/", &path])
+        .status_checked()?;
+    Ok(path)
+}
+
+fn create_zfs(ctx: &ExecutionContext, zfs: &Path, phase: Phase) -> Result<String> {
+    let output = ctx
+        .execute(zfs)
+        .args(["list", "-H", "-o", "name", "/"])
+        .output_checked_utf8()?;
+    let snapshot = format!("{}@{}", output.stdout.trim(), snapshot_name(phase));
+    ctx.require_sudo()?
+        .execute(ctx, zfs)?
+        .args(["snapshot", &snapshot])
+        .status_checked()?;
+    Ok(snapshot)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_timeshift_output() {
+        let output = "\
+Estimating system size...
+Creating new snapshot...(RSYNC)
+Saving to device: /dev/sda2, mounted at path: /run/timeshift/backup
+Synching files with rsync...
+Created control file: /run/timeshift/backup/timeshift/snapshots/2026-10-18_19-40-00/info.json
+RSYNC Snapshot saved successfully (41s)
+Tagged snapshot '2026-10-18_19-40-00': ondemand
+";
+        assert_eq!(parse_timeshift_output(output).as_deref(), Some("2026-10-18_19-40-00"));
+        assert_eq!(parse_timeshift_output("Nothing to see\n"), None);
+    }
+
+    #[test]
+    fn test_rollback_command() {
+        let snapshot = Snapshot {
+            tool: SnapshotTool::Zfs,
+            id: String::from("rpool/ROOT/arch@topgrade-2026-10-18T19-40-00-pre"),
+        };
+        assert_eq!(
+            snapshot.rollback_command(),
+            "zfs rollback -r rpool/ROOT/arch@topgrade-2026-10-18T19-40-00-pre"
+        );
+        assert_eq!(
+            snapshot.to_string(),
+            "zfs rpool/ROOT/arch@topgrade-2026-10-18T19-40-00-pre"
+        );
+    }
+}
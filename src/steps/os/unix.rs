@@ -30,6 +30,8 @@ use crate::error::{SkipStep, StepFailed};
 use crate::execution_context::ExecutionContext;
 #[cfg(any(target_os = "linux", target_os = "macos"))]
 use crate::executor::Executor;
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+use crate::runner::PendingUpdate;
 use crate::step::Step;
 use crate::terminal::print_separator;
 use crate::utils::{PathExt, require};
@@ -307,7 +309,7 @@ pub fn run_pkgin(ctx: &ExecutionContext) -> Result<()> {
     if ctx.config().yes(Step::Pkgin) {
         command.arg("-y");
     }
-    command.status_checked()
+    command.extra_args().status_checked()
 }
 
 pub fn run_fish_plug(ctx: &ExecutionContext) -> Result<()> {
@@ -380,6 +382,42 @@ pub fn upgrade_gnome_extensions(ctx: &ExecutionContext) -> Result<()> {
         .status_checked()
 }
 
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+pub fn check_brew_formula(ctx: &ExecutionContext, variant: BrewVariant) -> Result<Vec<PendingUpdate>> {
+    let brew = Brew::new(variant)?;
+
+    #[cfg(target_os = "macos")]
+    {
+        if variant.is_path() && !brew.is_macos_custom() {
+            return Err(SkipStep(t!("Not a custom brew for macOS").to_string()).into());
+        }
+    }
+
+    print_separator(brew.step_title());
+
+    let output = brew
+        .execute(ctx)?
+        .always()
+        .args(["outdated", "--formula", "--verbose"])
+        .output_checked_utf8()?;
+
+    Ok(parse_brew_outdated(&output.stdout))
+}
+
+/// Parse the output of `brew outdated --verbose`, lines like `curl (8.4.0, 8.5.0) < 8.6.0`.
+fn parse_brew_outdated(output: &str) -> Vec<PendingUpdate> {
+    output
+        .lines()
+        .filter_map(|line| {
+            let (package, rest) = line.split_once(" (")?;
+            let (installed, rest) = rest.split_once(") < ")?;
+            let current = installed.rsplit(", ").next()?;
+            let available = rest.split_whitespace().next()?;
+            Some(PendingUpdate::new(package, Some(current), Some(available)))
+        })
+        .collect()
+}
+
 #[cfg(any(target_os = "linux", target_os = "macos"))]
 pub fn run_brew_formula(ctx: &ExecutionContext, variant: BrewVariant) -> Result<()> {
     let brew = Brew::new(variant)?;
@@ -409,7 +447,7 @@ pub fn run_brew_formula(ctx: &ExecutionContext, variant: BrewVariant) -> Result<
         command.arg("--fetch-HEAD");
     }
 
-    command.status_checked()?;
+    command.extra_args().status_checked()?;
 
     if ctx.config().cleanup() {
         brew.execute(ctx)?.arg("cleanup").status_checked()?;
@@ -496,7 +534,7 @@ pub fn run_brew_cask(ctx: &ExecutionContext, variant: BrewVariant) -> Result<()>
         }
     }
 
-    brew.execute(ctx)?.args(&brew_args).status_checked()?;
+    brew.execute(ctx)?.args(&brew_args).extra_args().status_checked()?;
 
     if ctx.config().cleanup() {
         brew.execute(ctx)?.arg("cleanup").status_checked()?;
@@ -511,7 +549,7 @@ pub fn run_zerobrew(ctx: &ExecutionContext) -> Result<()> {
     print_separator("Zerobrew");
 
     ctx.execute(&zb).arg("update").status_checked()?;
-    ctx.execute(&zb).arg("upgrade").status_checked()?;
+    ctx.execute(&zb).arg("upgrade").extra_args().status_checked()?;
 
     if ctx.config().cleanup() {
         ctx.execute(&zb).arg("gc").status_checked()?;
@@ -526,7 +564,10 @@ pub fn run_guix(ctx: &ExecutionContext) -> Result<()> {
     print_separator("Guix");
 
     ctx.execute(&guix).arg("pull").status_checked()?;
-    ctx.execute(&guix).args(["package", "-u"]).status_checked()?;
+    ctx.execute(&guix)
+        .args(["package", "-u"])
+        .extra_args()
+        .status_checked()?;
 
     Ok(())
 }
@@ -727,6 +768,7 @@ pub fn run_nix(ctx: &ExecutionContext) -> Result<()> {
             .arg("upgrade")
             .args(&packages)
             .arg("--verbose")
+            .extra_args()
             .status_checked()
     } else {
         // a successful nix-channel run is expected to perform nix-env upgrades
@@ -749,7 +791,7 @@ pub fn run_nix(ctx: &ExecutionContext) -> Result<()> {
         if let Some(args) = ctx.config().nix_env_arguments() {
             command.args(args.split_whitespace());
         };
-        command.status_checked()
+        command.extra_args().status_checked()
     }
 }
 
@@ -788,6 +830,7 @@ pub fn run_nix_self_upgrade(ctx: &ExecutionContext) -> Result<()> {
         return sudo
             .execute_opts(ctx, nixd, SudoExecuteOpts::new().login_shell())?
             .arg("upgrade")
+            .extra_args()
             .status_checked();
     }
 
@@ -808,9 +851,14 @@ pub fn run_nix_self_upgrade(ctx: &ExecutionContext) -> Result<()> {
         sudo.execute_opts(ctx, &nix, SudoExecuteOpts::new().login_shell())?
             .args(nix_args)
             .arg("upgrade-nix")
+            .extra_args()
             .status_checked()
     } else {
-        ctx.execute(&nix).args(nix_args).arg("upgrade-nix").status_checked()
+        ctx.execute(&nix)
+            .args(nix_args)
+            .arg("upgrade-nix")
+            .extra_args()
+            .status_checked()
     }
 }
 
@@ -934,7 +982,7 @@ pub(super) fn nh_switch(ctx: &ExecutionContext, nh: &PathBuf, args: &NhSwitchArg
     if !ctx.config().yes(Step::System) {
         cmd.arg("--ask");
     }
-    cmd.status_checked()?;
+    cmd.extra_args().status_checked()?;
     Ok(())
 }
 
@@ -947,7 +995,7 @@ pub fn run_yadm(ctx: &ExecutionContext) -> Result<()> {
 
     print_separator("yadm");
 
-    ctx.execute(yadm).arg("pull").status_checked()
+    ctx.execute(yadm).arg("pull").extra_args().status_checked()
 }
 
 pub fn run_asdf(ctx: &ExecutionContext) -> Result<()> {
@@ -987,7 +1035,10 @@ pub fn run_asdf(ctx: &ExecutionContext) -> Result<()> {
         ctx.execute(&asdf).arg("update").status_checked_with_codes(&[42])?;
     }
 
-    ctx.execute(&asdf).args(["plugin", "update", "--all"]).status_checked()
+    ctx.execute(&asdf)
+        .args(["plugin", "update", "--all"])
+        .extra_args()
+        .status_checked()
 }
 
 pub fn run_home_manager(ctx: &ExecutionContext) -> Result<()> {
@@ -1030,7 +1081,7 @@ pub fn run_home_manager(ctx: &ExecutionContext) -> Result<()> {
                 cmd.args(extra_args);
             }
 
-            cmd.status_checked()
+            cmd.extra_args().status_checked()
         }
         (Err(_), _, Err(_)) => unreachable!("require_one([\"home-manager\", \"nh\"])?; was called, so either tool must be available"),
     }
@@ -1040,7 +1091,7 @@ pub fn run_pearl(ctx: &ExecutionContext) -> Result<()> {
     let pearl = require("pearl")?;
     print_separator("pearl");
 
-    ctx.execute(pearl).arg("update").status_checked()
+    ctx.execute(pearl).arg("update").extra_args().status_checked()
 }
 
 pub fn run_pyenv(ctx: &ExecutionContext) -> Result<()> {
@@ -1061,7 +1112,7 @@ pub fn run_pyenv(ctx: &ExecutionContext) -> Result<()> {
         return Err(SkipStep(t!("pyenv-update plugin is not installed").to_string()).into());
     }
 
-    ctx.execute(pyenv).arg("update").status_checked()
+    ctx.execute(pyenv).arg("update").extra_args().status_checked()
 }
 
 pub fn run_sdkman(ctx: &ExecutionContext) -> Result<()> {
@@ -1129,7 +1180,7 @@ pub fn run_bun_packages(ctx: &ExecutionContext) -> Result<()> {
         return Ok(());
     }
 
-    ctx.execute(bun).args(["-g", "update"]).status_checked()
+    ctx.execute(bun).args(["-g", "update"]).extra_args().status_checked()
 }
 
 /// Update dotfiles with `rcm(7)`.
@@ -1139,14 +1190,14 @@ pub fn run_rcm(ctx: &ExecutionContext) -> Result<()> {
     let rcup = require("rcup")?;
 
     print_separator("rcm");
-    ctx.execute(rcup).arg("-v").status_checked()
+    ctx.execute(rcup).arg("-v").extra_args().status_checked()
 }
 
 pub fn run_maza(ctx: &ExecutionContext) -> Result<()> {
     let maza = require("maza")?;
 
     print_separator("maza");
-    ctx.execute(maza).arg("update").status_checked()
+    ctx.execute(maza).arg("update").extra_args().status_checked()
 }
 
 pub fn run_hyprpm(ctx: &ExecutionContext) -> Result<()> {
@@ -1154,7 +1205,7 @@ pub fn run_hyprpm(ctx: &ExecutionContext) -> Result<()> {
 
     print_separator("hyprpm");
 
-    ctx.execute(hyprpm).arg("update").status_checked()
+    ctx.execute(hyprpm).arg("update").extra_args().status_checked()
 }
 
 pub fn run_atuin(ctx: &ExecutionContext) -> Result<()> {
@@ -1162,7 +1213,7 @@ pub fn run_atuin(ctx: &ExecutionContext) -> Result<()> {
 
     print_separator("atuin");
 
-    ctx.execute(atuin).status_checked()
+    ctx.execute(atuin).extra_args().status_checked()
 }
 
 #[cfg(not(any(target_os = "android", target_os = "macos")))]
@@ -1171,7 +1222,7 @@ pub fn run_sera(ctx: &ExecutionContext) -> Result<()> {
 
     print_separator("sera");
 
-    ctx.execute(sera).arg("upgrade").status_checked()
+    ctx.execute(sera).arg("upgrade").extra_args().status_checked()
 }
 
 pub fn reboot(ctx: &ExecutionContext) -> Result<()> {
@@ -1207,13 +1258,31 @@ pub fn run_install_release(ctx: &ExecutionContext) -> Result<()> {
     if ctx.config().yes(Step::InstallRelease) {
         command.arg("-y");
     }
-    command.status_checked()?;
+    command.extra_args().status_checked()?;
 
     let mut command = ctx.execute(&ir);
     command.arg("upgrade");
     if ctx.config().yes(Step::InstallRelease) {
         command.arg("-y");
     }
-    command.status_checked()?;
+    command.extra_args().status_checked()?;
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(any(target_os = "linux", target_os = "macos"))]
+    #[test]
+    fn test_parse_brew_outdated() {
+        let output = "curl (8.4.0, 8.5.0) < 8.6.0\nnode (21.5.0) < 21.6.1 [pinned at 21.5.0]\n";
+        assert_eq!(
+            parse_brew_outdated(output),
+            [
+                PendingUpdate::new("curl", Some("8.5.0"), Some("8.6.0")),
+                PendingUpdate::new("node", Some("21.5.0"), Some("21.6.1")),
+            ]
+        );
+    }
+}
@@ -29,7 +29,7 @@ pub fn run_chocolatey(ctx: &ExecutionContext) -> Result<()> {
         command.arg("--yes");
     }
 
-    command.status_checked()
+    command.extra_args().status_checked()
 }
 
 pub fn run_winget(ctx: &ExecutionContext) -> Result<()> {
@@ -51,7 +51,7 @@ pub fn run_winget(ctx: &ExecutionContext) -> Result<()> {
         args.push("--silent");
     }
 
-    command.args(args).status_checked()?;
+    command.args(args).extra_args().status_checked()?;
 
     Ok(())
 }
@@ -62,7 +62,10 @@ pub fn run_scoop(ctx: &ExecutionContext) -> Result<()> {
     print_separator("Scoop");
 
     ctx.execute(&scoop).args(["update"]).status_checked()?;
-    ctx.execute(&scoop).args(["update", "*"]).status_checked()?;
+    ctx.execute(&scoop)
+        .args(["update", "*"])
+        .extra_args()
+        .status_checked()?;
 
     if ctx.config().cleanup() {
         ctx.execute(&scoop).args(["cleanup", "*"]).status_checked()?;
@@ -90,7 +93,7 @@ pub fn update_wsl(ctx: &ExecutionContext) -> Result<()> {
     if ctx.config().wsl_update_use_web_download() {
         wsl_command.args(["--web-download"]);
     }
-    wsl_command.status_checked()?;
+    wsl_command.extra_args().status_checked()?;
     Ok(())
 }
 
@@ -0,0 +1,258 @@
+//! External steps: executables named `topgrade-step-<name>` on `PATH`, or any
+//! executable in `topgrade.d/steps/` next to the configuration file.
+//!
+//! A plugin is first called with `--describe`, and prints its metadata as JSON:
+//!
+//! ```json
+//! { "name": "In-house tools", "sudo": false, "platforms": ["linux", "macos"], "dry_run": true }
+//! ```
+//!
+//! Only `name` is required. An empty or missing `platforms` means every platform,
+//! otherwise it lists values of Rust's `std::env::consts::OS`.
+//!
+//! It is then called with `run`, with `TOPGRADE_YES`, `TOPGRADE_CLEANUP` and
+//! `TOPGRADE_DRY_RUN` set to `1` when they apply. Plugins are only run in a dry
+//! run if they declare `dry_run`. Exiting with 0 means success, with
+//! `SKIP_EXIT_CODE` that the plugin had nothing to do here, and with anything
+//! else failure.
+
+use std::cell::Cell;
+use std::collections::HashSet;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use color_eyre::eyre::{Context, Result, eyre};
+use merge2::Merge;
+use rust_i18n::t;
+use serde::{Deserialize, Serialize};
+use tracing::debug;
+
+use crate::command::CommandExt;
+use crate::config;
+use crate::error::SkipStep;
+use crate::execution_context::ExecutionContext;
+use crate::step::Step;
+use crate::step_config::{OptionDoc, StepOptions};
+use crate::sudo::SudoExecuteOpts;
+use crate::terminal::print_separator;
+
+const PREFIX: &str = "topgrade-step-";
+
+/// The exit code of a plugin that had nothing to do on this system (`EX_UNAVAILABLE`).
+pub const SKIP_EXIT_CODE: i32 = 69;
+
+/// The options in `[steps.plugins]`.
+#[derive(Deserialize, Serialize, Default, Debug, Merge, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct PluginsOptions {
+    #[merge(strategy = crate::utils::merge_strategies::vec_prepend_opt)]
+    pub disable: Option<Vec<String>>,
+}
+
+impl StepOptions for PluginsOptions {
+    const STEP: Step = Step::Plugins;
+    const REFERENCE: &'static [OptionDoc] = &[OptionDoc {
+        name: "disable",
+        example: r#"["foo"]"#,
+        description: "Plugins not to run, by the name of the file without `topgrade-step-`",
+    }];
+}
+
+/// An executable found by `discover`.
+pub struct Plugin {
+    /// The name of the file, without `topgrade-step-`.
+    pub name: String,
+    pub path: PathBuf,
+}
+
+/// What a plugin prints when called with `--describe`.
+#[derive(Deserialize, Debug, PartialEq)]
+pub struct Description {
+    pub name: String,
+    #[serde(default)]
+    pub sudo: bool,
+    #[serde(default)]
+    pub platforms: Vec<String>,
+    #[serde(default)]
+    pub dry_run: bool,
+}
+
+/// Find the plugins on `PATH` and in `topgrade.d/steps/`, leaving out the disabled ones.
+///
+/// Like for commands, the first plugin found with a name wins.
+pub fn discover(ctx: &ExecutionContext) -> Vec<Plugin> {
+    let disabled = ctx
+        .config()
+        .step_options::<PluginsOptions>()
+        .disable
+        .unwrap_or_default();
+    let mut seen = HashSet::new();
+    let mut plugins = Vec::new();
+
+    let path = env::var_os("PATH").unwrap_or_default();
+    let directories = env::split_paths(&path)
+        .map(|directory| (directory, true))
+        .chain([(config::plugin_directory(), false)]);
+    for (directory, prefixed) in directories {
+        for plugin in find_in(&directory, prefixed) {
+            if !disabled.contains(&plugin.name) && seen.insert(plugin.name.clone()) {
+                plugins.push(plugin);
+            }
+        }
+    }
+    plugins
+}
+
+/// The plugins in `directory`, sorted by name. Unless `prefixed` is false, only
+/// executables starting with `topgrade-step-` are plugins.
+fn find_in(directory: &Path, prefixed: bool) -> Vec<Plugin> {
+    let Ok(entries) = fs::read_dir(directory) else {
+        return Vec::new();
+    };
+    let mut plugins: Vec<Plugin> = entries
+        .filter_map(|entry| {
+            let path = entry.ok()?.path();
+            let name = path.file_name()?.to_str()?;
+            let name = if prefixed { name.strip_prefix(PREFIX)? } else { name };
+            #[cfg(windows)]
+            let name = name.strip_suffix(".exe")?;
+            if name.is_empty() || !is_executable(&path) {
+                return None;
+            }
+            Some(Plugin {
+                name: name.to_string(),
+                path,
+            })
+        })
+        .collect();
+    plugins.sort_by(|a, b| a.name.cmp(&b.name));
+    plugins
+}
+
+#[cfg(unix)]
+fn is_executable(path: &Path) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+
+    fs::metadata(path).is_ok_and(|metadata| metadata.is_file() && metadata.permissions().mode() & 0o111 != 0)
+}
+
+#[cfg(windows)]
+fn is_executable(path: &Path) -> bool {
+    path.is_file()
+}
+
+/// Ask `plugin` to describe itself.
+pub fn describe(ctx: &ExecutionContext, plugin: &Plugin) -> Result<Description> {
+    let output = ctx
+        .execute(&plugin.path)
+        .always()
+        .arg("--describe")
+        .output_checked_utf8()?;
+    let description = parse_description(&output.stdout)
+        .with_context(|| format!("Invalid output of {} --describe", plugin.path.display()))?;
+    debug!("Plugin {}: {description:?}", plugin.path.display());
+    Ok(description)
+}
+
+fn parse_description(output: &str) -> Result<Description> {
+    let description: Description = serde_json::from_str(output)?;
+    if description.name.trim().is_empty() {
+        return Err(eyre!("The name is empty"));
+    }
+    Ok(description)
+}
+
+/// Run `plugin`, which describes itself as `description`.
+pub fn run_plugin(ctx: &ExecutionContext, plugin: &Plugin, description: &Description) -> Result<()> {
+    if !description.platforms.is_empty() && !description.platforms.iter().any(|os| os == env::consts::OS) {
+        return Err(SkipStep(t!("Not supported on {os}", os = env::consts::OS).to_string()).into());
+    }
+
+    print_separator(&description.name);
+
+    let dry_run = ctx.run_type().dry() && description.dry_run;
+    let variables = [
+        ("TOPGRADE_YES", ctx.config().yes(Step::Plugins)),
+        ("TOPGRADE_CLEANUP", ctx.config().cleanup()),
+        ("TOPGRADE_DRY_RUN", dry_run),
+    ];
+    let mut exec = if description.sudo {
+        // sudo doesn't pass the variables on unless told to.
+        let names = ["TOPGRADE_YES", "TOPGRADE_CLEANUP", "TOPGRADE_DRY_RUN"];
+        ctx.require_sudo()?
+            .execute_opts(ctx, &plugin.path, SudoExecuteOpts::new().preserve_env_list(&names))?
+    } else {
+        ctx.execute(&plugin.path)
+    };
+    if dry_run {
+        exec = exec.always();
+    }
+    for (name, set) in variables {
+        if set {
+            exec.env(name, "1");
+        }
+    }
+
+    let skipped = Cell::new(false);
+    exec.arg("run").status_checked_with(|status| {
+        if status.code() == Some(SKIP_EXIT_CODE) {
+            skipped.set(true);
+        }
+        if status.success() || skipped.get() {
+            Ok(())
+        } else {
+            Err(())
+        }
+    })?;
+    if skipped.get() {
+        return Err(SkipStep(t!("Nothing to do for {plugin}", plugin = description.name).to_string()).into());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_description() {
+        assert_eq!(
+            parse_description(r#"{"name": "In-house tools", "sudo": true, "platforms": ["linux"]}"#).unwrap(),
+            Description {
+                name: String::from("In-house tools"),
+                sudo: true,
+                platforms: vec![String::from("linux")],
+                dry_run: false,
+            }
+        );
+        assert!(parse_description(r#"{"name": " "}"#).is_err());
+        assert!(parse_description("In-house tools").is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_find_in() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let directory = tempfile::tempdir().unwrap();
+        let create = |name: &str, mode: u32| {
+            let path = directory.path().join(name);
+            fs::write(&path, "#!/bin/sh\n").unwrap();
+            fs::set_permissions(&path, fs::Permissions::from_mode(mode)).unwrap();
+        };
+        create("topgrade-step-b", 0o755);
+        create("topgrade-step-a", 0o755);
+        create("topgrade-step-not-executable", 0o644);
+        create("topgrade", 0o755);
+
+        let names = |prefixed| -> Vec<String> {
+            find_in(directory.path(), prefixed)
+                .into_iter()
+                .map(|plugin| plugin.name)
+                .collect()
+        };
+        assert_eq!(names(true), ["a", "b"]);
+        assert_eq!(names(false), ["topgrade", "topgrade-step-a", "topgrade-step-b"]);
+    }
+}
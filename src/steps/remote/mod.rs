@@ -1,2 +1,3 @@
+pub mod push;
 pub mod ssh;
 pub mod vagrant;
@@ -0,0 +1,314 @@
+//! Copying this Topgrade to remote hosts that don't have it, see `misc.push_remote_topgrade`.
+//!
+//! Before running Topgrade on a host, its OS, architecture, C library and
+//! Topgrade version are checked. If Topgrade is missing or older than this one
+//! and the host can run this binary, it is copied to a temporary directory on
+//! the host and run from there. The directory is removed afterwards. Otherwise
+//! the Topgrade installed on the host is run.
+//!
+//! On Linux, a binary that isn't statically linked needs the same C library on
+//! the host: the same or a newer glibc than the one Topgrade runs with here, or musl.
+
+use std::env;
+use std::fs::File;
+use std::io::{Seek, Write};
+use std::path::Path;
+
+use color_eyre::eyre::{Context, Result, eyre};
+use rust_i18n::t;
+use semver::Version;
+use tracing::{debug, warn};
+
+use crate::command::CommandExt;
+use crate::config::Remote;
+use crate::execution_context::ExecutionContext;
+use crate::terminal::{print_info, print_warning};
+
+/// A Topgrade copied to a remote host.
+pub struct Pushed {
+    /// The temporary directory on the host it was copied to.
+    pub dir: String,
+    /// Whether the configuration was copied along with it.
+    pub config: bool,
+}
+
+impl Pushed {
+    pub fn topgrade_path(&self) -> String {
+        format!("{}/topgrade", self.dir)
+    }
+
+    pub fn config_path(&self) -> Option<String> {
+        self.config.then(|| format!("{}/topgrade.toml", self.dir))
+    }
+}
+
+/// What a remote host runs, as found out by `prepare`.
+#[derive(Debug, PartialEq)]
+struct Probe {
+    os: String,
+    arch: String,
+    /// The first line of `ldd --version`, which names the C library.
+    libc: String,
+    version: Option<Version>,
+}
+
+/// A C library on Linux.
+#[derive(Debug, PartialEq)]
+enum Libc {
+    /// glibc, with its major and minor version.
+    Glibc(u32, u32),
+    Musl,
+}
+
+impl Libc {
+    /// The C library named by the first line of `ldd --version`.
+    fn parse(line: &str) -> Option<Self> {
+        if line.contains("musl") {
+            return Some(Self::Musl);
+        }
+        let lower = line.to_lowercase();
+        if !lower.contains("glibc") && !lower.contains("gnu libc") {
+            return None;
+        }
+        // e.g. `ldd (Debian GLIBC 2.36-9+deb12u7) 2.36`
+        let (major, minor) = line.split_whitespace().last()?.split_once('.')?;
+        Some(Self::Glibc(major.parse().ok()?, minor.parse().ok()?))
+    }
+
+    /// The C library this binary runs with.
+    #[cfg(all(target_os = "linux", target_env = "gnu"))]
+    fn local() -> Option<Self> {
+        // SAFETY: glibc returns a static, NUL-terminated string.
+        let version = unsafe { std::ffi::CStr::from_ptr(nix::libc::gnu_get_libc_version()) };
+        let (major, minor) = version.to_str().ok()?.split_once('.')?;
+        // Versions like `2.39.9000` of development snapshots.
+        let minor = minor.split('.').next()?;
+        Some(Self::Glibc(major.parse().ok()?, minor.parse().ok()?))
+    }
+
+    #[cfg(all(target_os = "linux", target_env = "musl"))]
+    fn local() -> Option<Self> {
+        Some(Self::Musl)
+    }
+
+    #[cfg(not(all(target_os = "linux", any(target_env = "gnu", target_env = "musl"))))]
+    fn local() -> Option<Self> {
+        None
+    }
+
+    /// Whether a binary linked against `local` runs with this C library.
+    fn runs(&self, local: &Self) -> bool {
+        match (local, self) {
+            (Self::Glibc(major, minor), Self::Glibc(remote_major, remote_minor)) => {
+                (remote_major, remote_minor) >= (major, minor)
+            }
+            (Self::Musl, Self::Musl) => true,
+            _ => false,
+        }
+    }
+}
+
+impl Probe {
+    fn parse(output: &str) -> Option<Self> {
+        let mut lines = output.lines().map(str::trim);
+        let os = lines.next()?.to_string();
+        let arch = lines.next()?.to_string();
+        let libc = lines.next()?.strip_prefix("libc:")?.trim().to_string();
+        // `topgrade --version` prints e.g. `topgrade 16.0.0`.
+        let version = lines
+            .find_map(|line| line.strip_prefix("topgrade "))
+            .and_then(|version| Version::parse(version.trim_start_matches('v')).ok());
+        Some(Self {
+            os,
+            arch,
+            libc,
+            version,
+        })
+    }
+
+    /// Whether the host runs the same OS on the same architecture as this binary.
+    fn matches_local(&self) -> bool {
+        let os = match self.os.as_str() {
+            "Linux" => "linux",
+            "Darwin" => "macos",
+            "FreeBSD" => "freebsd",
+            "OpenBSD" => "openbsd",
+            "NetBSD" => "netbsd",
+            "DragonFly" => "dragonfly",
+            _ => return false,
+        };
+        let arch = match self.arch.as_str() {
+            "x86_64" | "amd64" => "x86_64",
+            "aarch64" | "arm64" => "aarch64",
+            "i386" | "i686" => "x86",
+            "riscv64" => "riscv64",
+            arch if arch.starts_with("armv7") => "arm",
+            _ => return false,
+        };
+        os == env::consts::OS && arch == env::consts::ARCH
+    }
+
+    /// Whether the C library of the host can run this binary, which runs the same OS.
+    fn libc_matches_local(&self) -> bool {
+        if env::consts::OS != "linux" || cfg!(target_feature = "crt-static") {
+            return true;
+        }
+        Libc::local().is_some_and(|local| Libc::parse(&self.libc).is_some_and(|libc| libc.runs(&local)))
+    }
+}
+
+/// Copy this Topgrade to `remote` if it needs it and can run it.
+///
+/// `ssh_args` are the arguments to connect to the host, without the command to run.
+pub fn prepare(ctx: &ExecutionContext, ssh: &Path, ssh_args: &[String], remote: &Remote) -> Result<Option<Pushed>> {
+    if !ctx.config().push_remote_topgrade(remote) || ctx.run_type().dry() {
+        return Ok(None);
+    }
+    let hostname = &remote.host;
+
+    let topgrade = remote
+        .topgrade_path
+        .as_deref()
+        .unwrap_or_else(|| ctx.config().remote_topgrade_path());
+    // Run through the login shell like Topgrade itself, for the same `PATH`.
+    let probe = format!(
+        r#"uname -s; uname -m; echo "libc: $(ldd --version 2>&1 | head -n 1)"; $SHELL -lc {} 2>/dev/null; true"#,
+        shell_words::quote(&format!("{topgrade} --version"))
+    );
+    let output = ctx.execute(ssh).args(ssh_args).arg(probe).output_checked_utf8()?;
+    let probe = Probe::parse(&output.stdout).ok_or_else(|| eyre!("Unexpected output from {hostname}"))?;
+    debug!("{hostname}: {probe:?}");
+
+    let local = Version::parse(env!("CARGO_PKG_VERSION")).expect("the crate version is valid semver");
+    if probe.version.as_ref().is_some_and(|version| *version >= local) {
+        return Ok(None);
+    }
+    if !probe.matches_local() {
+        print_warning(t!(
+            "Can't copy Topgrade to {hostname}, it runs {os} on {arch}",
+            hostname = hostname,
+            os = probe.os,
+            arch = probe.arch
+        ));
+        return Ok(None);
+    }
+    if !probe.libc_matches_local() {
+        print_warning(t!(
+            "Can't copy Topgrade to {hostname}, its C library can't run this build: {libc}",
+            hostname = hostname,
+            libc = if probe.libc.is_empty() { "-" } else { &probe.libc }
+        ));
+        return Ok(None);
+    }
+
+    print_info(t!(
+        "Copying Topgrade {version} to {hostname}",
+        version = local,
+        hostname = hostname
+    ));
+    let binary = env::current_exe().context("Failed to find the Topgrade executable")?;
+    let binary = File::open(&binary).with_context(|| format!("Failed to open {}", binary.display()))?;
+    let copy = r#"dir=$(mktemp -d "${TMPDIR:-/tmp}/topgrade.XXXXXX") && cat > "$dir/topgrade" && chmod 700 "$dir/topgrade" && echo "$dir""#;
+    let output = ctx
+        .execute(ssh)
+        .args(ssh_args)
+        .arg(copy)
+        .stdin(binary)
+        .output_checked_utf8()?;
+    let pushed = Pushed {
+        dir: output.stdout.trim().to_string(),
+        config: ctx.config().push_remote_config(remote),
+    };
+
+    if let Some(path) = pushed.config_path()
+        && let Err(e) = push_config(ctx, ssh, ssh_args, &path)
+    {
+        cleanup(ctx, ssh, ssh_args, &pushed);
+        return Err(e);
+    }
+
+    Ok(Some(pushed))
+}
+
+fn push_config(ctx: &ExecutionContext, ssh: &Path, ssh_args: &[String], path: &str) -> Result<()> {
+    let mut config = tempfile::tempfile().context("Failed to create a temporary file")?;
+    config.write_all(ctx.config().remote_config_file()?.as_bytes())?;
+    config.rewind()?;
+
+    ctx.execute(ssh)
+        .args(ssh_args)
+        .arg(format!("cat > {}", shell_words::quote(path)))
+        .stdin(config)
+        .output_checked()
+        .map(|_| ())
+}
+
+/// Remove the copy of Topgrade from the host.
+pub fn cleanup(ctx: &ExecutionContext, ssh: &Path, ssh_args: &[String], pushed: &Pushed) {
+    let result = ctx
+        .execute(ssh)
+        .args(ssh_args)
+        .arg(format!("rm -rf {}", shell_words::quote(&pushed.dir)))
+        .output_checked();
+    if let Err(e) = result {
+        warn!("Failed to remove {}: {e:?}", pushed.dir);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_probe() {
+        let probe = Probe::parse("Linux\nx86_64\nlibc: ldd (GNU libc) 2.39\ntopgrade 16.0.2\n").unwrap();
+        assert_eq!(probe.os, "Linux");
+        assert_eq!(probe.arch, "x86_64");
+        assert_eq!(probe.libc, "ldd (GNU libc) 2.39");
+        assert_eq!(probe.version, Some(Version::new(16, 0, 2)));
+
+        let probe = Probe::parse("Darwin\narm64\nlibc: sh: ldd: command not found\n").unwrap();
+        assert_eq!(probe.version, None);
+        assert_eq!(
+            probe.matches_local(),
+            cfg!(all(target_os = "macos", target_arch = "aarch64"))
+        );
+
+        assert_eq!(Probe::parse("Linux\nx86_64\n"), None);
+    }
+
+    #[test]
+    fn test_parse_libc() {
+        assert_eq!(
+            Libc::parse("ldd (Debian GLIBC 2.36-9+deb12u7) 2.36"),
+            Some(Libc::Glibc(2, 36))
+        );
+        assert_eq!(Libc::parse("ldd (GNU libc) 2.39"), Some(Libc::Glibc(2, 39)));
+        assert_eq!(Libc::parse("musl libc (x86_64)"), Some(Libc::Musl));
+        assert_eq!(Libc::parse("sh: ldd: not found"), None);
+        assert_eq!(Libc::parse(""), None);
+    }
+
+    #[test]
+    fn test_libc_runs() {
+        let built = Libc::Glibc(2, 36);
+        assert!(Libc::Glibc(2, 36).runs(&built));
+        assert!(Libc::Glibc(2, 39).runs(&built));
+        assert!(Libc::Glibc(3, 0).runs(&built));
+        assert!(!Libc::Glibc(2, 31).runs(&built));
+        assert!(!Libc::Musl.runs(&built));
+        assert!(Libc::Musl.runs(&Libc::Musl));
+        assert!(!Libc::Glibc(2, 39).runs(&Libc::Musl));
+    }
+
+    #[cfg(all(target_os = "linux", target_env = "gnu", not(target_feature = "crt-static")))]
+    #[test]
+    fn test_libc_matches_local() {
+        let probe = |libc: &str| Probe::parse(&format!("Linux\nx86_64\nlibc: {libc}\n")).unwrap();
+        assert!(matches!(Libc::local(), Some(Libc::Glibc(2, _))));
+        assert!(probe("ldd (GNU libc) 99.0").libc_matches_local());
+        assert!(!probe("ldd (GNU libc) 2.1").libc_matches_local());
+        assert!(!probe("musl libc (x86_64)").libc_matches_local());
+        assert!(!probe("sh: ldd: not found").libc_matches_local());
+    }
+}
@@ -1,8 +1,29 @@
-use color_eyre::eyre::Result;
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::fs;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::sync::Mutex;
+use std::thread;
+use std::{fmt::Write as _, io};
+
+use color_eyre::eyre::{Context, Result};
 use rust_i18n::t;
+use tracing::{debug, warn};
 
+use crate::breaking_changes::topgrade_data_dir;
+use crate::config::Remote;
+use crate::error::TopgradeError;
+use crate::executor::ExecutorChild;
+use crate::report::{EmbeddedReport, REMOTE_REPORT_ENV, RunReport};
+use crate::runner::{Runner, StepReport, StepResult};
+use crate::step::Step;
+use crate::steps::remote::push::{self, Pushed};
+use crate::terminal::{format_duration, print_result};
 use crate::{
-    command::CommandExt, error::SkipStep, execution_context::ExecutionContext, terminal::print_separator, utils,
+    command::CommandExt, ctrlc, error::SkipStep, execution_context::ExecutionContext, parallel, step_log,
+    terminal::print_separator, utils,
 };
 
 fn prepare_async_ssh_command(args: &mut Vec<&str>) {
@@ -10,18 +31,82 @@ fn prepare_async_ssh_command(args: &mut Vec<&str>) {
     args.push("--keep");
 }
 
-pub fn ssh_step(ctx: &ExecutionContext, hostname: &str) -> Result<()> {
-    let ssh = utils::require("ssh")?;
+/// The arguments to ssh that connect to `remote`, after the ones about the terminal.
+fn connection_args(ctx: &ExecutionContext, remote: &Remote) -> Vec<String> {
+    let mut args = vec![remote.host.clone()];
+
+    for ssh_arguments in ctx.config().ssh_arguments().into_iter().chain(&remote.ssh_arguments) {
+        args.extend(ssh_arguments.split_whitespace().map(String::from));
+    }
+
+    args
+}
 
-    let topgrade = ctx.config().remote_topgrade_path();
-    let mut args = vec!["-t", hostname];
+/// The arguments to ssh that run Topgrade on `remote`, or the copy of it in `pushed`.
+///
+/// With `report`, the remote Topgrade prints its report at the end, for `EmbeddedReport`
+/// to take out of its output.
+fn remote_args(ctx: &ExecutionContext, remote: &Remote, pushed: Option<&Pushed>, report: bool) -> Vec<String> {
+    let mut args = connection_args(ctx, remote);
 
-    if let Some(ssh_arguments) = ctx.config().ssh_arguments() {
-        args.extend(ssh_arguments.split_whitespace());
+    // The remote shell parses the command again, so anything from the config is quoted.
+    args.extend([String::from("env"), format!("TOPGRADE_PREFIX={}", remote.host)]);
+    if report {
+        args.push(format!("{REMOTE_REPORT_ENV}=1"));
     }
+    args.extend(
+        remote
+            .env
+            .iter()
+            .map(|(key, value)| shell_words::quote(&format!("{key}={value}")).into_owned()),
+    );
 
-    let env = format!("TOPGRADE_PREFIX={hostname}");
-    args.extend(["env", &env, "$SHELL", "-lc", topgrade]);
+    let mut topgrade = match pushed {
+        Some(pushed) => pushed.topgrade_path(),
+        None => remote
+            .topgrade_path
+            .clone()
+            .unwrap_or_else(|| ctx.config().remote_topgrade_path().to_string()),
+    };
+    if let Some(config) = pushed.and_then(Pushed::config_path) {
+        topgrade = format!("{topgrade} --config {config}");
+    }
+    if let Some(arguments) = &remote.topgrade_arguments {
+        topgrade = format!("{topgrade} {arguments}");
+    }
+    if topgrade.contains(' ') {
+        topgrade = shell_words::quote(&topgrade).into_owned();
+    }
+    args.extend([String::from("$SHELL"), String::from("-lc"), topgrade]);
+
+    args
+}
+
+/// Run Topgrade on `remote`, adding the steps it ran to the report of `runner`.
+pub fn remote_step(runner: &mut Runner, ctx: &ExecutionContext, remote: &Remote) -> Result<()> {
+    let hostname = &remote.host;
+    let remote_report = RefCell::new(None);
+    let result = runner.execute(Step::Remotes, format!("Remote ({hostname})"), || {
+        ssh_step(ctx, remote, &remote_report)
+    });
+    if let Some(report) = remote_report.into_inner() {
+        runner.extend_report(remote_steps(hostname, report));
+    }
+    result
+}
+
+fn ssh_step(
+    ctx: &ExecutionContext,
+    remote: &Remote,
+    remote_report: &RefCell<Option<RunReport<'static>>>,
+) -> Result<()> {
+    let ssh = utils::require("ssh")?;
+    let hostname = &remote.host;
+
+    // Nothing takes the report out of the output of Topgrades launched elsewhere.
+    let launch_args = remote_args(ctx, remote, None, false);
+    let mut args = vec!["-t"];
+    args.extend(launch_args.iter().map(String::as_str));
 
     #[cfg(unix)]
     if ctx.config().run_in_tmux() && !ctx.run_type().dry() {
@@ -35,18 +120,379 @@ pub fn ssh_step(ctx: &ExecutionContext, hostname: &str) -> Result<()> {
         ctx.execute("wt").args(&args).spawn()?;
         Err(SkipStep(String::from(t!("Remote Topgrade launched in an external terminal"))).into())
     } else {
-        let mut args = vec!["-t", hostname];
+        print_separator(format!("Remote ({hostname})"));
+        println!("{}", t!("Connecting to {hostname}...", hostname = hostname));
 
-        if let Some(ssh_arguments) = ctx.config().ssh_arguments() {
-            args.extend(ssh_arguments.split_whitespace());
+        let launch_args = remote_args(ctx, remote, None, true);
+        let mut args = vec!["-t"];
+        args.extend(launch_args.iter().map(String::as_str));
+        if ctx.run_type().dry() {
+            return ctx.execute(ssh).args(&args).status_checked();
         }
 
-        let env = format!("TOPGRADE_PREFIX={hostname}");
-        args.extend(["env", &env, "$SHELL", "-lc", topgrade]);
+        let connection = connection_args(ctx, remote);
+        let Some(pushed) = push::prepare(ctx, &ssh, &connection, remote)? else {
+            return status_with_report(ctx, &ssh, &args, remote_report);
+        };
+        let pushed_args = remote_args(ctx, remote, Some(&pushed), true);
+        let mut args = vec!["-t"];
+        args.extend(pushed_args.iter().map(String::as_str));
+        let result = status_with_report(ctx, &ssh, &args, remote_report);
+        push::cleanup(ctx, &ssh, &connection, &pushed);
+        result
+    }
+}
 
-        print_separator(format!("Remote ({hostname})"));
-        println!("{}", t!("Connecting to {hostname}...", hostname = hostname));
+/// Run `ssh` with its output shown as usual, except for the report of the remote
+/// Topgrade, which is taken out of it and saved to `remote_report`.
+fn status_with_report(
+    ctx: &ExecutionContext,
+    ssh: &Path,
+    args: &[&str],
+    remote_report: &RefCell<Option<RunReport<'static>>>,
+) -> Result<()> {
+    let mut command = ctx.execute(ssh);
+    command.args(args).stdout(Stdio::piped());
+    // The output is written to the step log below, without the report.
+    let ExecutorChild::Wet(mut child) = command.spawn()? else {
+        return Ok(());
+    };
+
+    let mut filter = EmbeddedReport::default();
+    if let Some(mut output) = child.stdout.take() {
+        let mut buffer = [0; 8192];
+        while let Ok(read) = output.read(&mut buffer) {
+            if read == 0 {
+                break;
+            }
+            show(&filter.feed(&buffer[..read]));
+        }
+    }
+    let (rest, report) = filter.finish();
+    show(&rest);
+    *remote_report.borrow_mut() = report;
+
+    let status = child.wait().context("Failed to wait for ssh")?;
+    if status.success() {
+        Ok(())
+    } else {
+        let program = ssh.display().to_string();
+        let command = format!("{program} {}", shell_words::join(args));
+        Err(TopgradeError::ProcessFailed(program, status)).with_context(|| format!("Command failed: `{command}`"))
+    }
+}
+
+fn show(output: &[u8]) {
+    if output.is_empty() {
+        return;
+    }
+    let mut stdout = io::stdout().lock();
+    stdout.write_all(output).ok();
+    stdout.flush().ok();
+    step_log::write(format_args!("{}", String::from_utf8_lossy(output)));
+}
+
+/// The steps of a remote run, with their keys prefixed by the host they ran on.
+fn remote_steps(hostname: &str, report: RunReport<'static>) -> Vec<StepReport<'static>> {
+    report
+        .steps
+        .into_owned()
+        .into_iter()
+        .map(|step| StepReport {
+            key: format!("{hostname}: {}", step.key).into(),
+            // The log is on the remote host.
+            log: None,
+            ..step
+        })
+        .collect()
+}
+
+/// Run Topgrade on `remote` without a terminal, so nothing can prompt.
+fn ssh_batch(ctx: &ExecutionContext, remote: &Remote) -> Result<()> {
+    let ssh = utils::require("ssh")?;
+
+    let mut connection = vec![String::from("-o"), String::from("BatchMode=yes")];
+    connection.extend(connection_args(ctx, remote));
+    let pushed = push::prepare(ctx, &ssh, &connection, remote)?;
+
+    let mut args = vec![String::from("-T"), String::from("-o"), String::from("BatchMode=yes")];
+    args.extend(remote_args(ctx, remote, pushed.as_ref(), true));
+    let result = ctx.execute(&ssh).args(&args).stdin(Stdio::null()).status_checked();
+
+    if let Some(pushed) = pushed {
+        push::cleanup(ctx, &ssh, &connection, &pushed);
+    }
+    result
+}
+
+/// The directory the output of remote Topgrade runs is saved to.
+fn remote_log_dir() -> PathBuf {
+    topgrade_data_dir().join("remotes")
+}
+
+/// The result of running Topgrade on one host.
+struct HostRun<'a> {
+    hostname: &'a str,
+    report: Vec<StepReport<'static>>,
+    log: Option<PathBuf>,
+}
+
+/// Run Topgrade on `remotes` concurrently, on up to `jobs` hosts at a time.
+///
+/// The output of every host is saved to its own log file instead of being
+/// shown, and a table of the results is printed once all of them finished.
+pub fn ssh_fanout(runner: &mut Runner, ctx: &ExecutionContext, remotes: &[Remote], jobs: usize) -> Result<()> {
+    print_separator(t!("Remotes"));
+    let workers = jobs.max(1).min(remotes.len());
+    debug!("Running Topgrade on {} hosts, {workers} at a time", remotes.len());
+
+    let log_dir = remote_log_dir();
+    if let Err(e) = fs::create_dir_all(&log_dir) {
+        warn!("Failed to create {}: {e}", log_dir.display());
+    }
+
+    let runs = run_hosts(remotes, workers, |remote| {
+        let run = run_host(ctx, remote, &log_dir);
+        for report in &run.report {
+            print_result(report);
+        }
+        run
+    });
+
+    print_separator(t!("Remotes summary"));
+    print!("{}", summary_table(&runs));
+
+    for run in runs {
+        runner.extend_report(run.report);
+    }
+
+    if ctrlc::interrupted() {
+        ctrlc::unset_interrupted();
+        return Err(io::Error::from(io::ErrorKind::Interrupted)).context("Interrupted while running remote Topgrades");
+    }
+
+    Ok(())
+}
+
+/// Run `run` for every remote on `workers` threads, and return the results in the order of `remotes`.
+fn run_hosts<'a, R: Send>(remotes: &'a [Remote], workers: usize, run: impl Fn(&'a Remote) -> R + Sync) -> Vec<R> {
+    let queue = Mutex::new(remotes.iter().enumerate().collect::<VecDeque<_>>());
+    let runs = Mutex::new(Vec::new());
+    thread::scope(|scope| {
+        for _ in 0..workers {
+            scope.spawn(|| {
+                loop {
+                    // Popping in the loop condition would hold the lock while the host runs.
+                    let next = queue.lock().unwrap().pop_front();
+                    let Some((index, remote)) = next else { break };
+                    if ctrlc::interrupted() {
+                        return;
+                    }
+                    let run = run(remote);
+                    runs.lock().unwrap().push((index, run));
+                }
+            });
+        }
+    });
+
+    let mut runs = runs.into_inner().unwrap();
+    runs.sort_by_key(|(index, _)| *index);
+    runs.into_iter().map(|(_, run)| run).collect()
+}
+
+fn run_host<'a>(ctx: &ExecutionContext, remote: &'a Remote, log_dir: &Path) -> HostRun<'a> {
+    let hostname = remote.host.as_str();
+    let mut runner = Runner::unattended(ctx);
+    let captured =
+        parallel::capture(|| runner.execute(Step::Remotes, format!("Remote ({hostname})"), || ssh_batch(ctx, remote)));
+
+    let mut remote_report = None;
+    let log = match captured {
+        Ok((_, output)) => {
+            let mut filter = EmbeddedReport::default();
+            let mut output = filter.feed(&output);
+            let (rest, report) = filter.finish();
+            output.extend(rest);
+            remote_report = report;
+
+            let path = log_dir.join(format!("{}.log", hostname.replace(['/', '\\'], "_")));
+            match fs::write(&path, output) {
+                Ok(()) => Some(path),
+                Err(e) => {
+                    warn!("Failed to save the output of {hostname} to {}: {e}", path.display());
+                    None
+                }
+            }
+        }
+        Err(e) => {
+            warn!("Failed to capture the output of {hostname}: {e:?}");
+            None
+        }
+    };
+
+    let mut report: Vec<StepReport<'static>> = runner.into_report().into_iter().map(StepReport::into_owned).collect();
+    if let Some(remote_report) = remote_report {
+        report.extend(remote_steps(hostname, remote_report));
+    }
+    HostRun { hostname, report, log }
+}
+
+fn summary_table(runs: &[HostRun]) -> String {
+    let rows: Vec<[String; 4]> = runs
+        .iter()
+        .map(|run| {
+            let (result, duration) = match run.report.first() {
+                Some(report) => (result_label(&report.result), format_duration(report.duration)),
+                None => (t!("Not run").to_string(), String::from("-")),
+            };
+            let log = run
+                .log
+                .as_ref()
+                .map_or_else(|| String::from("-"), |log| log.display().to_string());
+            [run.hostname.to_string(), result, duration, log]
+        })
+        .collect();
+
+    let header = [t!("Host"), t!("Result"), t!("Duration"), t!("Log")].map(|title| title.to_string());
+    let widths: Vec<usize> = (0..4)
+        .map(|column| {
+            rows.iter()
+                .chain([&header])
+                .map(|row| row[column].chars().count())
+                .max()
+                .unwrap_or(0)
+        })
+        .collect();
+
+    let mut table = String::new();
+    for row in [&header].into_iter().chain(&rows) {
+        writeln!(
+            table,
+            "{:<w0$}  {:<w1$}  {:>w2$}  {}",
+            row[0],
+            row[1],
+            row[2],
+            row[3],
+            w0 = widths[0],
+            w1 = widths[1],
+            w2 = widths[2]
+        )
+        .unwrap();
+    }
+    table
+}
+
+fn result_label(result: &StepResult) -> String {
+    match result {
+        StepResult::Success => t!("OK"),
+        StepResult::Failure => t!("FAILED"),
+        StepResult::TimedOut => t!("TIMED OUT"),
+        StepResult::Ignored => t!("IGNORED"),
+        StepResult::SkippedMissingSudo | StepResult::Skipped(_) => t!("SKIPPED"),
+    }
+    .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    fn run<'a>(hostname: &'a str, result: Option<StepResult>, log: Option<&str>) -> HostRun<'a> {
+        let report = result
+            .into_iter()
+            .map(|result| {
+                StepReport::for_test(
+                    Step::Remotes,
+                    format!("Remote ({hostname})"),
+                    result,
+                    Duration::from_secs(75),
+                )
+            })
+            .collect();
+        HostRun {
+            hostname,
+            report,
+            log: log.map(PathBuf::from),
+        }
+    }
+
+    #[test]
+    fn test_summary_table() {
+        let table = summary_table(&[
+            run("pi", Some(StepResult::Success), Some("/logs/pi.log")),
+            run("toothless", Some(StepResult::Failure), Some("/logs/toothless.log")),
+            run("parnas", None, None),
+        ]);
+        assert_eq!(
+            table.lines().collect::<Vec<_>>(),
+            [
+                "Host       Result   Duration  Log",
+                "pi         OK         1m 15s  /logs/pi.log",
+                "toothless  FAILED     1m 15s  /logs/toothless.log",
+                "parnas     Not run         -  -",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_only_filtered_runs_print_their_report() {
+        use crate::config::test::config_from_toml;
+        use crate::execution_context::RunType;
+
+        let config = config_from_toml("");
+        #[cfg(target_os = "linux")]
+        let distribution = Err(color_eyre::eyre::eyre!("Not detected in tests"));
+        let ctx = ExecutionContext::new(
+            RunType::Wet,
+            None,
+            &config,
+            #[cfg(target_os = "linux")]
+            &distribution,
+        );
+        let remote = Remote {
+            host: String::from("pi"),
+            ..Remote::default()
+        };
+        let report_env = format!("{REMOTE_REPORT_ENV}=1");
+
+        assert!(remote_args(&ctx, &remote, None, true).contains(&report_env));
+        assert!(!remote_args(&ctx, &remote, None, false).contains(&report_env));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_hosts_run_concurrently() {
+        use std::os::unix::fs::PermissionsExt;
+        use std::process::Command;
+
+        // An ssh that only succeeds once the other host connected too.
+        let dir = tempfile::tempdir().unwrap();
+        let ssh = dir.path().join("ssh");
+        fs::write(
+            &ssh,
+            format!(
+                "#!/bin/sh\ncd {}\ntouch \"$1.connected\"\nfor _ in $(seq 50); do\n  \
+                 [ \"$(ls *.connected | wc -l)\" -ge 2 ] && exit 0\n  sleep 0.1\ndone\nexit 1\n",
+                dir.path().display()
+            ),
+        )
+        .unwrap();
+        fs::set_permissions(&ssh, fs::Permissions::from_mode(0o755)).unwrap();
 
-        ctx.execute(ssh).args(&args).status_checked()
+        let remotes: Vec<Remote> = ["pi", "toothless"]
+            .into_iter()
+            .map(|host| Remote {
+                host: String::from(host),
+                ..Remote::default()
+            })
+            .collect();
+        let runs = run_hosts(&remotes, 2, |remote| {
+            #[expect(clippy::disallowed_methods)]
+            let status = Command::new(&ssh).arg(&remote.host).status().unwrap();
+            (remote.host.as_str(), status.success())
+        });
+        assert_eq!(runs, [("pi", true), ("toothless", true)]);
     }
 }
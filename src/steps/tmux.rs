@@ -37,7 +37,7 @@ pub fn run_tpm(ctx: &ExecutionContext) -> Result<()> {
 
     print_separator("tmux plugins");
 
-    ctx.execute(tpm).arg("all").status_checked()
+    ctx.execute(tpm).arg("all").extra_args().status_checked()
 }
 
 pub fn run_tpack(ctx: &ExecutionContext) -> Result<()> {
@@ -45,5 +45,5 @@ pub fn run_tpack(ctx: &ExecutionContext) -> Result<()> {
 
     print_separator("tpack");
 
-    ctx.execute(tpack).args(["update", "all"]).status_checked()
+    ctx.execute(tpack).args(["update", "all"]).extra_args().status_checked()
 }
@@ -67,3 +67,29 @@ pub fn run_toolbx(ctx: &ExecutionContext) -> Result<()> {
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::test::config_from_toml;
+
+    #[test]
+    fn test_list_toolboxes() {
+        let config = config_from_toml("");
+        let ctx = ExecutionContext::replaying(
+            &config,
+            r#"
+[[commands]]
+command = "toolbox list --containers"
+stdout = """
+CONTAINER ID  CONTAINER NAME     CREATED      STATUS   IMAGE NAME
+8a6e8f1b9a2c  fedora-toolbox-39  2 weeks ago  running  registry.fedoraproject.org/fedora-toolbox:39
+3f2e1d0c9b8a  dev                3 days ago   exited   quay.io/toolbx/arch-toolbox:latest
+"""
+"#,
+        );
+
+        let toolboxes = list_toolboxes(&ctx, Path::new("/usr/bin/toolbox")).unwrap();
+        assert_eq!(toolboxes, ["fedora-toolbox-39", "dev"]);
+    }
+}
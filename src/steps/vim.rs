@@ -158,5 +158,5 @@ pub fn run_voom(ctx: &ExecutionContext) -> Result<()> {
 
     print_separator("voom");
 
-    ctx.execute(voom).arg("update").status_checked()
+    ctx.execute(voom).arg("update").extra_args().status_checked()
 }
@@ -91,7 +91,7 @@ pub fn run_antibody(ctx: &ExecutionContext) -> Result<()> {
 
     print_separator("antibody");
 
-    ctx.execute(antibody).arg("update").status_checked()
+    ctx.execute(antibody).arg("update").extra_args().status_checked()
 }
 
 pub fn run_antigen(ctx: &ExecutionContext) -> Result<()> {
@@ -11,7 +11,7 @@ use color_eyre::eyre::Result;
 use color_eyre::eyre::eyre;
 use itertools::Itertools;
 use rust_i18n::t;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use strum::Display;
 use thiserror::Error;
 #[cfg(windows)]
@@ -23,6 +23,7 @@ use crate::command::CommandExt;
 use crate::error::UnsupportedSudo;
 use crate::execution_context::{ExecutionContext, RunType};
 use crate::executor::Executor;
+use crate::step_config;
 use crate::terminal::print_separator;
 use crate::utils::which;
 
@@ -413,7 +414,7 @@ impl Sudo {
             // no sudo effectively preserves these by default
 
             // run command directly
-            return Ok(ctx.execute(command));
+            return Ok(ctx.execute(command).elevated_with(self.kind));
         }
 
         // self.path is only None for null sudo, which we've handled above
@@ -447,12 +448,24 @@ impl Sudo {
         }
 
         let mut preserve_env = opts.preserve_env;
-        // The `--env` arguments are set globally in `main.rs`, but sudo by default
-        // does not pass these environment variables through unless explicitly told to.
-        // So we add them here to the preserve_env list.
+        // The `--env` arguments are set globally in `main.rs`, and the `env` of the
+        // step is set on the sudo command by `ExecutionContext::execute`, but sudo by
+        // default does not pass these environment variables through unless explicitly
+        // told to. So we add them here to the preserve_env list.
         let cfg_env_vars = ctx.config().env_variables();
-        if !cfg_env_vars.is_empty() {
-            let cfg_env_var_keys = cfg_env_vars.iter().map(|(key, _value)| key.as_str());
+        let step_env_vars = step_config::current_step()
+            .map(|step| {
+                ctx.config()
+                    .step_env(step)
+                    .map(|(key, _value)| key.as_str())
+                    .collect::<Vec<_>>()
+            })
+            .unwrap_or_default();
+        if !cfg_env_vars.is_empty() || !step_env_vars.is_empty() {
+            let cfg_env_var_keys = cfg_env_vars
+                .iter()
+                .map(|(key, _value)| key.as_str())
+                .chain(step_env_vars);
             // merge the user-specified env vars with the opts.preserve_env
             match preserve_env {
                 SudoPreserveEnv::All => {}
@@ -545,7 +558,7 @@ impl Sudo {
 
         cmd.arg(command);
 
-        Ok(cmd)
+        Ok(cmd.elevated_with(self.kind))
     }
 }
 
@@ -553,7 +566,7 @@ impl Sudo {
 // We always define both though, so that we don't have to put
 // #[cfg(...)] everywhere.
 
-#[derive(Clone, Copy, Debug, Display, Deserialize)]
+#[derive(Clone, Copy, Debug, Display, Deserialize, Serialize)]
 #[serde(rename_all = "lowercase")]
 #[strum(serialize_all = "lowercase")]
 pub enum SudoKind {
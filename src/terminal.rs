@@ -1,5 +1,6 @@
 use std::cmp::{max, min};
 use std::env;
+use std::fmt;
 use std::io::{self, Write};
 use std::process::Command;
 use std::sync::{LazyLock, Mutex};
@@ -11,6 +12,7 @@ use color_eyre::eyre::Context;
 use console::{Term, measure_text_width, style};
 use crossterm::event::{DisableBracketedPaste, EnableBracketedPaste, Event, KeyCode, KeyEventKind, read};
 use crossterm::terminal::{disable_raw_mode, enable_raw_mode};
+use itertools::Itertools;
 use notify_rust::{Notification, Timeout};
 use rust_i18n::t;
 use tracing::{debug, error};
@@ -18,7 +20,9 @@ use tracing::{debug, error};
 use which_crate::which;
 
 use crate::command::CommandExt;
-use crate::runner::StepResult;
+use crate::parallel;
+use crate::runner::{StepReport, StepResult};
+use crate::step_log;
 
 static TERMINAL: LazyLock<Mutex<Terminal>> = LazyLock::new(|| Mutex::new(Terminal::new()));
 
@@ -103,8 +107,20 @@ impl Terminal {
         notification.show().ok();
     }
 
+    /// Write step output, to the capture buffer of this thread if there is one.
+    /// It is also added to the log of the running step.
+    fn write_output(&mut self, args: fmt::Arguments) -> io::Result<()> {
+        step_log::write(args);
+        if parallel::write_captured(args) {
+            Ok(())
+        } else {
+            self.term.write_fmt(args)
+        }
+    }
+
     fn print_separator<P: AsRef<str>>(&mut self, message: P) {
-        if self.set_title {
+        // Steps running in parallel would fight over the title.
+        if self.set_title && !parallel::capturing() {
             self.term
                 .set_title(format!("{}Topgrade - {}", self.prefix, message.as_ref()));
         }
@@ -129,27 +145,26 @@ impl Terminal {
 
         match self.width {
             Some(width) => {
-                self.term
-                    .write_fmt(format_args!(
-                        "{}\n",
-                        style(format_args!(
-                            "\n── {} {:─^border$}",
-                            message,
-                            "",
-                            border = max(
-                                2,
-                                min(80, width as usize)
-                                    .checked_sub(4)
-                                    .and_then(|e| e.checked_sub(measure_text_width(&message)))
-                                    .unwrap_or(0)
-                            )
-                        ))
-                        .bold()
+                self.write_output(format_args!(
+                    "{}\n",
+                    style(format_args!(
+                        "\n── {} {:─^border$}",
+                        message,
+                        "",
+                        border = max(
+                            2,
+                            min(80, width as usize)
+                                .checked_sub(4)
+                                .and_then(|e| e.checked_sub(measure_text_width(&message)))
+                                .unwrap_or(0)
+                        )
                     ))
-                    .ok();
+                    .bold()
+                ))
+                .ok();
             }
             None => {
-                self.term.write_fmt(format_args!("―― {message} ――\n")).ok();
+                self.write_output(format_args!("―― {message} ――\n")).ok();
             }
         }
     }
@@ -158,51 +173,91 @@ impl Terminal {
     fn print_error<P: AsRef<str>, Q: AsRef<str>>(&mut self, key: Q, message: P) {
         let key = key.as_ref();
         let message = message.as_ref();
-        self.term
-            .write_fmt(format_args!(
-                "{} {}",
-                style(format!("{}", t!("{key} failed:", key = key))).red().bold(),
-                message
+        self.write_output(format_args!(
+            "{} {}",
+            style(format!("{}", t!("{key} failed:", key = key))).red().bold(),
+            message
+        ))
+        .ok();
+        if let Some(log) = step_log::path(key) {
+            self.write_output(format_args!(
+                "\n\n{}",
+                style(t!("Full output: {path}", path = log.display())).dim()
             ))
             .ok();
+        }
     }
 
     #[allow(dead_code)]
     fn print_warning<P: AsRef<str>>(&mut self, message: P) {
         let message = message.as_ref();
-        self.term
-            .write_fmt(format_args!("{}\n", style(message).yellow().bold()))
+        self.write_output(format_args!("{}\n", style(message).yellow().bold()))
             .ok();
     }
 
     #[allow(dead_code)]
     fn print_info<P: AsRef<str>>(&mut self, message: P) {
         let message = message.as_ref();
-        self.term
-            .write_fmt(format_args!("{}\n", style(message).blue().bold()))
+        self.write_output(format_args!("{}\n", style(message).blue().bold()))
             .ok();
     }
 
-    fn print_result<P: AsRef<str>>(&mut self, key: P, result: &StepResult) {
-        let key = key.as_ref();
-
-        self.term
-            .write_fmt(format_args!(
-                "{}: {}\n",
-                key,
-                match result {
-                    StepResult::Success => format!("{}", style(t!("OK")).bold().green()),
-                    StepResult::Failure => format!("{}", style(t!("FAILED")).bold().red()),
-                    StepResult::Ignored => format!("{}", style(t!("IGNORED")).bold().yellow()),
-                    StepResult::SkippedMissingSudo => format!(
-                        "{}: {}",
-                        style(t!("SKIPPED")).bold().yellow(),
-                        t!("Could not find sudo")
-                    ),
-                    StepResult::Skipped(reason) => format!("{}: {}", style(t!("SKIPPED")).bold().blue(), reason),
+    fn print_result(&mut self, report: &StepReport) {
+        let timing = match report.result {
+            StepResult::Success | StepResult::Failure | StepResult::TimedOut | StepResult::Ignored => {
+                let mut timing = format!(" ({}", format_duration(report.duration));
+                if report.attempt_durations.len() > 1 {
+                    timing.push_str(&format!(
+                        "; {}",
+                        t!(
+                            "attempts: {durations}",
+                            durations = report.attempt_durations.iter().map(|d| format_duration(*d)).join(", ")
+                        )
+                    ));
                 }
+                timing.push(')');
+                timing
+            }
+            StepResult::SkippedMissingSudo | StepResult::Skipped(_) => String::new(),
+        };
+
+        self.write_output(format_args!(
+            "{}: {}{}\n",
+            report.key,
+            match &report.result {
+                StepResult::Success if !report.updates.is_empty() => format!(
+                    "{}",
+                    style(t!("{count} updates available", count = report.updates.len()))
+                        .bold()
+                        .yellow()
+                ),
+                StepResult::Success => format!("{}", style(t!("OK")).bold().green()),
+                StepResult::Failure => format!("{}", style(t!("FAILED")).bold().red()),
+                StepResult::TimedOut => format!("{}", style(t!("TIMED OUT")).bold().red()),
+                StepResult::Ignored => format!("{}", style(t!("IGNORED")).bold().yellow()),
+                StepResult::SkippedMissingSudo => format!(
+                    "{}: {}",
+                    style(t!("SKIPPED")).bold().yellow(),
+                    t!("Could not find sudo")
+                ),
+                StepResult::Skipped(reason) => format!("{}: {}", style(t!("SKIPPED")).bold().blue(), reason),
+            },
+            style(timing).dim()
+        ))
+        .ok();
+        for update in &report.updates {
+            self.write_output(format_args!("    {update}\n")).ok();
+        }
+        if let Some(note) = &report.note {
+            self.write_output(format_args!("    {note}\n")).ok();
+        }
+        if let Some(log) = report.log.as_ref().filter(|_| report.result.failed()) {
+            self.write_output(format_args!(
+                "    {}\n",
+                style(t!("Full output: {path}", path = log.display())).dim()
             ))
             .ok();
+        }
     }
 
     #[allow(dead_code)]
@@ -325,8 +380,45 @@ pub fn print_info<P: AsRef<str>>(message: P) {
     TERMINAL.lock().unwrap().print_info(message);
 }
 
-pub fn print_result<P: AsRef<str>>(key: P, result: &StepResult) {
-    TERMINAL.lock().unwrap().print_result(key, result);
+pub fn print_result(report: &StepReport) {
+    TERMINAL.lock().unwrap().print_result(report);
+}
+
+/// Print a line of step output. Unlike `println!`, this is captured when steps run in parallel.
+pub fn print_line<P: AsRef<str>>(message: P) {
+    TERMINAL
+        .lock()
+        .unwrap()
+        .write_output(format_args!("{}\n", message.as_ref()))
+        .ok();
+}
+
+/// Print the output of a command that was run with its output captured, like `print_line`.
+pub fn print_output(stdout: &[u8], stderr: &[u8]) {
+    let mut terminal = TERMINAL.lock().unwrap();
+    terminal
+        .write_output(format_args!("{}", String::from_utf8_lossy(stdout)))
+        .ok();
+    if parallel::capturing() {
+        terminal
+            .write_output(format_args!("{}", String::from_utf8_lossy(stderr)))
+            .ok();
+    } else {
+        step_log::write(format_args!("{}", String::from_utf8_lossy(stderr)));
+        io::stderr().write_all(stderr).ok();
+    }
+}
+
+/// Format a duration for humans, e.g. `4.2s`, `3m 07s` or `1h 02m`.
+pub fn format_duration(duration: Duration) -> String {
+    let secs = duration.as_secs();
+    if secs < 60 {
+        format!("{:.1}s", duration.as_secs_f64())
+    } else if secs < 60 * 60 {
+        format!("{}m {:02}s", secs / 60, secs % 60)
+    } else {
+        format!("{}h {:02}m", secs / 3600, (secs % 3600) / 60)
+    }
 }
 
 /// Tells whether the terminal is dumb.
@@ -361,3 +453,15 @@ pub fn notify_desktop<P: AsRef<str>>(message: P, timeout: Option<Duration>) {
 pub fn display_time(display_time: bool) {
     TERMINAL.lock().unwrap().display_time(display_time);
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_format_duration() {
+        assert_eq!(format_duration(Duration::from_millis(4200)), "4.2s");
+        assert_eq!(format_duration(Duration::from_secs(187)), "3m 07s");
+        assert_eq!(format_duration(Duration::from_secs(3720 + 59)), "1h 02m");
+    }
+}
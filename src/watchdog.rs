@@ -0,0 +1,324 @@
+//! Killing steps that run for longer than `misc.step_timeout`.
+//!
+//! Commands that are started while a step is watched register their process
+//! here as a `WatchedChild`, see `command.rs`. When the step runs out of time, the watchdog kills
+//! those processes and everything they started, which makes the step fail.
+//! This includes commands whose output is captured, which are spawned and
+//! waited for rather than run with `Command::output`.
+
+use std::cell::RefCell;
+use std::io;
+use std::ops::{Deref, DerefMut};
+use std::process::{Child, ExitStatus, Output};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+#[cfg(unix)]
+use std::time::Instant;
+
+use tracing::debug;
+#[cfg(unix)]
+use tracing::warn;
+
+/// How long processes get to exit after being asked to, before they are killed.
+#[cfg(unix)]
+const KILL_GRACE_PERIOD: Duration = Duration::from_secs(5);
+
+#[derive(Default)]
+struct Watch {
+    children: Mutex<Vec<u32>>,
+    timed_out: AtomicBool,
+}
+
+thread_local! {
+    /// The watch of the step running on this thread, if it has a timeout.
+    static CURRENT: RefCell<Option<Arc<Watch>>> = const { RefCell::new(None) };
+}
+
+/// Run `f`, killing the processes it starts once `timeout` has passed.
+///
+/// Returns the result of `f` and whether it ran out of time.
+pub fn run<T>(timeout: Option<Duration>, f: impl FnOnce() -> T) -> (T, bool) {
+    let Some(timeout) = timeout else {
+        return (f(), false);
+    };
+
+    let watch = Arc::new(Watch::default());
+    let previous = CURRENT.replace(Some(Arc::clone(&watch)));
+    let (done, wait) = mpsc::channel::<()>();
+    let watchdog = {
+        let watch = Arc::clone(&watch);
+        thread::spawn(move || {
+            if wait.recv_timeout(timeout) == Err(RecvTimeoutError::Timeout) {
+                debug!("Step timed out after {timeout:?}, killing its processes");
+                watch.timed_out.store(true, Ordering::SeqCst);
+                // Processes registered from now on are killed by `register`.
+                let children = watch.children.lock().unwrap().clone();
+                for pid in children {
+                    kill_tree(pid);
+                }
+            }
+        })
+    };
+
+    let value = f();
+    drop(done);
+    watchdog.join().ok();
+    CURRENT.set(previous);
+
+    (value, watch.timed_out.load(Ordering::SeqCst))
+}
+
+/// A child process registered with the watchdog of the step that started it.
+///
+/// The watchdog forgets the process once it has been waited for or dropped, so
+/// that a timeout never kills another process that got the same PID.
+pub struct WatchedChild {
+    child: Child,
+    registration: Registration,
+}
+
+impl WatchedChild {
+    /// Like [`Child::wait`], forgetting the process once it has exited.
+    pub fn wait(&mut self) -> io::Result<ExitStatus> {
+        let status = self.child.wait();
+        self.registration.release();
+        status
+    }
+
+    /// Like [`Child::wait_with_output`], forgetting the process once it has exited.
+    pub fn wait_with_output(self) -> io::Result<Output> {
+        let WatchedChild {
+            child,
+            mut registration,
+        } = self;
+        let output = child.wait_with_output();
+        registration.release();
+        output
+    }
+}
+
+impl Deref for WatchedChild {
+    type Target = Child;
+
+    fn deref(&self) -> &Child {
+        &self.child
+    }
+}
+
+impl DerefMut for WatchedChild {
+    fn deref_mut(&mut self) -> &mut Child {
+        &mut self.child
+    }
+}
+
+/// Register `child`, started by the step running on this thread.
+///
+/// A process started after the step ran out of time is killed right away.
+pub fn watch(child: Child) -> WatchedChild {
+    let pid = child.id();
+    let watch = CURRENT.with_borrow(|watch| {
+        let watch = watch.as_ref()?;
+        let mut children = watch.children.lock().unwrap();
+        if watch.timed_out.load(Ordering::SeqCst) {
+            drop(children);
+            kill_tree(pid);
+            None
+        } else {
+            children.push(pid);
+            Some(Arc::clone(watch))
+        }
+    });
+    WatchedChild {
+        child,
+        registration: Registration { pid, watch },
+    }
+}
+
+/// The entry of a process in the watch of a step, removed when dropped.
+struct Registration {
+    pid: u32,
+    watch: Option<Arc<Watch>>,
+}
+
+impl Registration {
+    fn release(&mut self) {
+        if let Some(watch) = self.watch.take() {
+            watch.children.lock().unwrap().retain(|&child| child != self.pid);
+        }
+    }
+}
+
+impl Drop for Registration {
+    fn drop(&mut self) {
+        self.release();
+    }
+}
+
+/// Kill `pid` and all of its descendants.
+///
+/// They are asked to terminate first, so that e.g. `sudo` can pass the signal
+/// on to the command it runs as root, which we are not allowed to signal.
+#[cfg(unix)]
+fn kill_tree(pid: u32) {
+    use nix::sys::signal::{Signal, kill};
+    use nix::unistd::Pid;
+
+    let signal = |pids: &[u32], signal: Signal| {
+        for &pid in pids {
+            kill(Pid::from_raw(pid as i32), signal).ok();
+        }
+    };
+
+    let tree = match processes() {
+        Ok(processes) => descendants(pid, &processes),
+        Err(e) => {
+            warn!("Failed to list processes, only killing {pid}: {e}");
+            vec![pid]
+        }
+    };
+    signal(&tree, Signal::SIGTERM);
+
+    // The tree falls apart as its processes exit, so look for survivors among the
+    // processes that were found before.
+    let survivors = || -> Vec<u32> {
+        processes()
+            .unwrap_or_default()
+            .into_iter()
+            .map(|(pid, _)| pid)
+            .filter(|pid| tree.contains(pid))
+            .collect()
+    };
+    let deadline = Instant::now() + KILL_GRACE_PERIOD;
+    let mut alive = survivors();
+    while !alive.is_empty() && Instant::now() < deadline {
+        thread::sleep(Duration::from_millis(100));
+        alive = survivors();
+    }
+    signal(&alive, Signal::SIGKILL);
+}
+
+#[cfg(windows)]
+fn kill_tree(pid: u32) {
+    use crate::command::CommandExt;
+
+    #[expect(clippy::disallowed_methods)]
+    std::process::Command::new("taskkill")
+        .args(["/PID", &pid.to_string(), "/T", "/F"])
+        .output_checked()
+        .ok();
+}
+
+/// List all processes as `(pid, parent pid)` pairs.
+#[cfg(unix)]
+fn processes() -> color_eyre::Result<Vec<(u32, u32)>> {
+    use crate::command::CommandExt;
+
+    #[expect(clippy::disallowed_methods)]
+    let output = std::process::Command::new("ps")
+        .args(["-A", "-o", "pid=", "-o", "ppid="])
+        .output_checked_utf8()?;
+
+    Ok(output
+        .stdout
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.split_whitespace().map(str::parse);
+            Some((fields.next()?.ok()?, fields.next()?.ok()?))
+        })
+        .collect())
+}
+
+/// `root` and the processes descending from it.
+#[cfg(unix)]
+fn descendants(root: u32, processes: &[(u32, u32)]) -> Vec<u32> {
+    let mut tree = vec![root];
+    let mut index = 0;
+    while let Some(&parent) = tree.get(index) {
+        tree.extend(
+            processes
+                .iter()
+                .filter(|&&(pid, ppid)| ppid == parent && !tree.contains(&pid))
+                .map(|&(pid, _)| pid)
+                .collect::<Vec<_>>(),
+        );
+        index += 1;
+    }
+    tree
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[cfg(unix)]
+    #[test]
+    fn test_descendants() {
+        let processes = [(1, 0), (10, 1), (11, 10), (12, 10), (13, 12), (20, 1), (21, 20)];
+        assert_eq!(descendants(10, &processes), [10, 11, 12, 13]);
+        assert_eq!(descendants(21, &processes), [21]);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_run_kills_processes_on_timeout() {
+        use crate::command::CommandExt;
+        use std::time::Instant;
+
+        let started = Instant::now();
+        let (result, timed_out) = run(Some(Duration::from_millis(200)), || {
+            #[expect(clippy::disallowed_methods)]
+            std::process::Command::new("sleep").arg("30").status_checked()
+        });
+
+        assert!(timed_out);
+        assert!(result.is_err());
+        assert!(started.elapsed() < Duration::from_secs(10));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_run_kills_captured_processes_on_timeout() {
+        use crate::command::CommandExt;
+        use std::time::Instant;
+
+        let started = Instant::now();
+        let (result, timed_out) = run(Some(Duration::from_millis(200)), || {
+            #[expect(clippy::disallowed_methods)]
+            std::process::Command::new("sleep").arg("30").output_checked()
+        });
+
+        assert!(timed_out);
+        assert!(result.is_err());
+        assert!(started.elapsed() < Duration::from_secs(10));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_waited_children_are_forgotten() {
+        use crate::command::CommandExt;
+
+        let ((), timed_out) = run(Some(Duration::from_secs(60)), || {
+            #[expect(clippy::disallowed_methods)]
+            let mut child = std::process::Command::new("true").spawn_checked().unwrap();
+            let watch = CURRENT.with_borrow(|watch| Arc::clone(watch.as_ref().unwrap()));
+            assert_eq!(*watch.children.lock().unwrap(), [child.id()]);
+            child.wait().unwrap();
+            assert!(watch.children.lock().unwrap().is_empty());
+
+            #[expect(clippy::disallowed_methods)]
+            let child = std::process::Command::new("true").spawn_checked().unwrap();
+            drop(child);
+            assert!(watch.children.lock().unwrap().is_empty());
+        });
+        assert!(!timed_out);
+    }
+
+    #[test]
+    fn test_run_without_timeout() {
+        assert_eq!(run(None, || 42), (42, false));
+        assert_eq!(run(Some(Duration::from_secs(60)), || 42), (42, false));
+    }
+}